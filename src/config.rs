@@ -0,0 +1,181 @@
+//! PayU VAS products (DSTV, GOtv, Showmax, ...) we sell, one per service and
+//! country.
+//!
+//! Products are read from `VAS_PRODUCTS`, a JSON array of [`VasProduct`],
+//! parsed again only when the variable changes. Every product must carry
+//! its own `username` and `password`. The Nigerian DSTV product (`dstv-ng`)
+//! is the exception: it is always available, built from the `DSTV_*`
+//! variables unless `VAS_PRODUCTS` overrides it, and authenticates with
+//! `DSTV_AUTH` when it has no credentials.
+
+use crate::models::money::{Currency, Money};
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+pub const DEFAULT_VAS_PRODUCT: &str = "dstv-ng";
+
+const DEFAULT_BASE_URL: &str = "https://mcapi-demo.herokuapp.com";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unknown VAS product {0}")]
+    UnknownProduct(String),
+
+    #[error("Invalid VAS_PRODUCTS: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VasProduct {
+    /// Identifier clients send to pick the product, e.g. `gotv-ng`
+    pub id: String,
+    pub name: String,
+    pub vas_id: String,
+    pub country_code: String,
+    pub currency: Currency,
+    pub merchant_id: String,
+    #[serde(default, skip_serializing)]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+    /// Defaults to `DSTV_BASE_URL`
+    #[serde(default, skip_serializing)]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub lookup_url: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub payment_url: Option<String>,
+}
+
+impl VasProduct {
+    /// Nigerian DSTV, configured through the `DSTV_*` variables.
+    fn dstv_ng() -> Self {
+        VasProduct {
+            id: DEFAULT_VAS_PRODUCT.to_string(),
+            name: "DSTV Nigeria".to_string(),
+            vas_id: env::var("DSTV_VAS_ID").unwrap_or_else(|_| "MCA_ACCOUNT_SQ_NG".to_string()),
+            country_code: "NG".to_string(),
+            currency: Currency::Ngn,
+            merchant_id: env::var("DSTV_MERCHANT_ID").unwrap_or_else(|_| "test".to_string()),
+            username: None,
+            password: None,
+            base_url: None,
+            lookup_url: env::var("DSTV_LOOKUP_URL").ok(),
+            payment_url: env::var("DSTV_PAYMENT_URL").ok(),
+        }
+    }
+
+    /// `minor` units of the product's currency.
+    pub fn money(&self, minor: i64) -> Money {
+        Money::from_minor(minor, self.currency)
+    }
+
+    pub fn base_url(&self) -> String {
+        self.base_url.clone().unwrap_or_else(|| {
+            env::var("DSTV_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string())
+        })
+    }
+
+    pub fn lookup_url(&self) -> String {
+        self.lookup_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/vendor/lookup", self.base_url()))
+    }
+
+    pub fn payment_url(&self) -> String {
+        self.payment_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/vendor/singlepayment", self.base_url()))
+    }
+
+    /// `{base_url}/transactions/single/{reference}`, with the reference
+    /// percent-encoded as a single path segment.
+    pub fn requery_url(&self, merchant_reference: &str) -> Result<String, ConfigError> {
+        let base_url = self.base_url();
+        let mut url = Url::parse(&base_url)
+            .map_err(|e| ConfigError::Invalid(format!("base URL {}: {}", base_url, e)))?;
+        url.path_segments_mut()
+            .map_err(|_| ConfigError::Invalid(format!("base URL {} cannot have a path", base_url)))?
+            .pop_if_empty()
+            .extend(["transactions", "single", merchant_reference]);
+        Ok(url.into())
+    }
+
+    /// Basic auth from the product's credentials. Only `dstv-ng` may have
+    /// none, and falls back to `DSTV_AUTH`.
+    pub fn auth_header(&self) -> String {
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            return format!(
+                "Basic {}",
+                general_purpose::STANDARD.encode(format!("{}:{}", username, password))
+            );
+        }
+
+        env::var("DSTV_AUTH").unwrap_or_else(|_| {
+            format!(
+                "Basic {}",
+                general_purpose::STANDARD.encode("test:NeRWNtWQMS")
+            )
+        })
+    }
+}
+
+/// `VAS_PRODUCTS` as last parsed, with the raw value it was parsed from
+static CONFIGURED: RwLock<Option<(String, Arc<Vec<VasProduct>>)>> = RwLock::new(None);
+
+/// Every configured product, `dstv-ng` first.
+pub fn vas_products() -> Result<Vec<VasProduct>, ConfigError> {
+    let configured = configured_products()?;
+
+    let mut products = Vec::with_capacity(configured.len() + 1);
+    if !configured.iter().any(|p| p.id == DEFAULT_VAS_PRODUCT) {
+        products.push(VasProduct::dstv_ng());
+    }
+    products.extend(configured.iter().cloned());
+
+    Ok(products)
+}
+
+fn configured_products() -> Result<Arc<Vec<VasProduct>>, ConfigError> {
+    let json = env::var("VAS_PRODUCTS").unwrap_or_default();
+
+    if let Some((raw, products)) = CONFIGURED.read().unwrap().as_ref() {
+        if *raw == json {
+            return Ok(products.clone());
+        }
+    }
+
+    let products: Vec<VasProduct> = if json.trim().is_empty() {
+        Vec::new()
+    } else {
+        serde_json::from_str(&json).map_err(|e| ConfigError::Invalid(e.to_string()))?
+    };
+    if let Some(product) = products.iter().find(|p| {
+        p.id != DEFAULT_VAS_PRODUCT
+            && (p.username.as_deref().unwrap_or("").is_empty()
+                || p.password.as_deref().unwrap_or("").is_empty())
+    }) {
+        return Err(ConfigError::Invalid(format!(
+            "product {} has no username or password",
+            product.id
+        )));
+    }
+
+    let products = Arc::new(products);
+    *CONFIGURED.write().unwrap() = Some((json, products.clone()));
+    Ok(products)
+}
+
+/// Looks up a product by id; `None` selects `dstv-ng`.
+pub fn vas_product(id: Option<&str>) -> Result<VasProduct, ConfigError> {
+    let id = id.unwrap_or(DEFAULT_VAS_PRODUCT);
+
+    vas_products()?
+        .into_iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| ConfigError::UnknownProduct(id.to_string()))
+}
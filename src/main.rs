@@ -1,16 +1,26 @@
-use axum::http::{header, Method};
-use axum::Router;
+use axum::{Extension, Router};
 
+use bills_backend::routes::airtime::airtime_routes;
+use bills_backend::routes::billers::biller_routes;
 use bills_backend::routes::bluecode::bluecode_routes;
 use bills_backend::routes::dstv::dstv_routes;
 use bills_backend::routes::transactions::transaction_routes;
+use bills_backend::routes::vendor_exchanges::vendor_exchange_routes;
+use bills_backend::services::bluecode_poller::spawn_bluecode_poller;
+use bills_backend::services::bulk_renewals::resume_bulk_renewals;
+use bills_backend::services::catalog::spawn_catalog_refresher;
+use bills_backend::services::checkout::spawn_checkout_worker;
+use bills_backend::services::customer_validation::spawn_validation_pruner;
+use bills_backend::services::quickteller_auth::TokenManager;
+use bills_backend::services::quickteller_requery::spawn_quickteller_requery;
+use bills_backend::services::subscriptions::spawn_subscription_scheduler;
+use bills_backend::utils::cors::cors_layer;
+use bills_backend::utils::idempotency::spawn_idempotency_pruner;
 
 use dotenvy::dotenv;
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
-use tower_http::cors::{Any, CorsLayer};
-use tracing_subscriber;
 
 #[tokio::main]
 async fn main() {
@@ -24,23 +34,55 @@ async fn main() {
         .await
         .expect("Failed to connect to DB");
 
-    // ✅ Global CORS middleware
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers([header::CONTENT_TYPE]);
+    // ✅ Resume DSTV checkouts left unfinished by a previous run
+    spawn_checkout_worker(pool.clone());
+
+    // ✅ Finish approved DSTV bulk renewals interrupted by a restart
+    if let Err(err) = resume_bulk_renewals(&pool).await {
+        tracing::error!("❌ Failed to resume DSTV bulk renewals: {}", err);
+    }
+
+    // ✅ Requery Bluecode payments whose callback never arrived
+    spawn_bluecode_poller(pool.clone());
+
+    // ✅ One Quickteller access token, shared by handlers and workers
+    let quickteller_tokens = TokenManager::new();
+
+    // ✅ Resolve Quickteller transactions left pending by a timeout
+    spawn_quickteller_requery(pool.clone(), quickteller_tokens.clone());
+
+    // ✅ Forget Idempotency-Keys once they expire
+    spawn_idempotency_pruner(pool.clone());
+
+    // ✅ Forget Quickteller customer validations once they expire
+    spawn_validation_pruner(pool.clone());
+
+    // ✅ Keep the DSTV product catalog in sync with its source
+    spawn_catalog_refresher(pool.clone());
+
+    // ✅ Renew recurring DSTV subscriptions when they fall due
+    spawn_subscription_scheduler(pool.clone());
 
     // ✅ Top-level router WITH state: PgPool
     let app = Router::<PgPool>::new()
         .nest("/dstv", dstv_routes(pool.clone()))
         .nest("/bluecode", bluecode_routes(pool.clone()))
-        .nest("/transactions", transaction_routes())
-        .layer(cors)
+        .nest("/billers", biller_routes())
+        .nest("/airtime", airtime_routes(pool.clone()))
+        .nest("/transactions", transaction_routes(pool.clone()))
+        .nest("/admin/vendor-exchanges", vendor_exchange_routes())
+        .layer(Extension(quickteller_tokens))
+        .layer(cors_layer()) // ✅ Global CORS middleware
         .with_state(pool); // 👈 attaches the PgPool to all routes
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
     tracing::info!("🚀 Server running at http://{}", addr);
 
     let listener = TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
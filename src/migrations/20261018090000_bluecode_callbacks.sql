@@ -0,0 +1,7 @@
+-- Keep the last Bluecode callback received for each transaction
+ALTER TABLE transactions
+ADD COLUMN IF NOT EXISTS bluecode_callback_payload JSONB NULL,
+ADD COLUMN IF NOT EXISTS bluecode_callback_at TIMESTAMPTZ NULL;
+
+CREATE INDEX IF NOT EXISTS transactions_merchant_reference_idx
+    ON transactions (merchant_reference);
@@ -0,0 +1,5 @@
+-- Deliveries already accepted from Bluecode, used to reject replays
+CREATE TABLE IF NOT EXISTS bluecode_webhook_deliveries (
+    delivery_id TEXT PRIMARY KEY,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
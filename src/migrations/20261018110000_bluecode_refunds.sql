@@ -0,0 +1,14 @@
+-- Refunds issued through Bluecode, linked to the original transaction
+CREATE TABLE IF NOT EXISTS bluecode_refunds (
+    id SERIAL PRIMARY KEY,
+    transaction_id INTEGER NOT NULL REFERENCES transactions (id),
+    merchant_tx_id TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    state TEXT NOT NULL,
+    refund_tx_id TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS bluecode_refunds_transaction_id_idx
+    ON bluecode_refunds (transaction_id);
@@ -0,0 +1,19 @@
+-- Server-side DSTV checkouts: Bluecode QR payment followed by MultiChoice confirmation
+CREATE TABLE IF NOT EXISTS dstv_checkouts (
+    id UUID PRIMARY KEY,
+    merchant_tx_id TEXT NOT NULL UNIQUE,
+    transaction_id INTEGER NOT NULL REFERENCES transactions (id),
+    customer_id TEXT NOT NULL,
+    basket_id TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    account_name TEXT NULL,
+    checkin_code TEXT NULL,
+    step TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT NULL,
+    next_attempt_at TIMESTAMPTZ NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS dstv_checkouts_step_idx ON dstv_checkouts (step);
@@ -0,0 +1,7 @@
+-- Track transaction age and the last Bluecode status poll
+ALTER TABLE transactions
+ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+ADD COLUMN IF NOT EXISTS last_polled_at TIMESTAMPTZ NULL;
+
+CREATE INDEX IF NOT EXISTS transactions_qr_status_created_at_idx
+    ON transactions (qr_status, created_at);
@@ -0,0 +1,8 @@
+-- Checkin codes issued by Bluecode, so QR images can be rendered later
+CREATE TABLE IF NOT EXISTS bluecode_registrations (
+    merchant_tx_id TEXT PRIMARY KEY,
+    checkin_code TEXT NOT NULL,
+    requested_amount BIGINT NOT NULL,
+    currency TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
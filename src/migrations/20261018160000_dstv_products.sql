@@ -0,0 +1,10 @@
+-- DSTV bouquets and add-ons that can be paid for, with prices in kobo
+CREATE TABLE IF NOT EXISTS dstv_products (
+    basket_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    price BIGINT NOT NULL CHECK (price > 0),
+    validity_days INTEGER NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
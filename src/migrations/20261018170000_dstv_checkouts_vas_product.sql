@@ -0,0 +1,2 @@
+-- PayU VAS product (dstv-ng, gotv-ng, ...) a checkout pays for
+ALTER TABLE dstv_checkouts ADD COLUMN IF NOT EXISTS vas_product TEXT NOT NULL DEFAULT 'dstv-ng';
@@ -0,0 +1,18 @@
+-- Every reference we send upstream, so provider support can trace our calls
+CREATE TABLE IF NOT EXISTS merchant_references (
+    reference TEXT PRIMARY KEY,
+    provider TEXT NOT NULL,
+    purpose TEXT NOT NULL,
+    -- Reference of the payment this call was made for, e.g. the Bluecode
+    -- merchant_tx_id a MultiChoice confirmation belongs to
+    parent_reference TEXT NULL,
+    transaction_id INTEGER NULL REFERENCES transactions(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS merchant_references_transaction_id_idx
+    ON merchant_references (transaction_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS merchant_references_parent_purpose_idx
+    ON merchant_references (parent_reference, provider, purpose)
+    WHERE parent_reference IS NOT NULL;
@@ -0,0 +1,27 @@
+-- Every call made to Bluecode, PayU VAS and Quickteller, for dispute handling
+CREATE TABLE IF NOT EXISTS vendor_exchanges (
+    id BIGSERIAL PRIMARY KEY,
+    provider TEXT NOT NULL,
+    operation TEXT NOT NULL,
+    method TEXT NOT NULL,
+    url TEXT NOT NULL,
+    -- Bodies are redacted before they are stored
+    request_body TEXT NULL,
+    response_body TEXT NULL,
+    status_code INTEGER NULL,
+    error TEXT NULL,
+    latency_ms BIGINT NOT NULL,
+    -- Merchant reference sent with the call
+    correlation_id TEXT NULL,
+    transaction_id INTEGER NULL REFERENCES transactions(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS vendor_exchanges_provider_created_at_idx
+    ON vendor_exchanges (provider, created_at);
+
+CREATE INDEX IF NOT EXISTS vendor_exchanges_correlation_id_idx
+    ON vendor_exchanges (correlation_id);
+
+CREATE INDEX IF NOT EXISTS vendor_exchanges_transaction_id_idx
+    ON vendor_exchanges (transaction_id);
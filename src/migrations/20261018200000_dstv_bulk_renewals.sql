@@ -0,0 +1,34 @@
+-- Bulk DSTV renewals uploaded as CSV: validated into a preview, then
+-- confirmed row by row once approved
+CREATE TABLE IF NOT EXISTS dstv_bulk_batches (
+    id UUID PRIMARY KEY,
+    name TEXT NULL,
+    vas_product TEXT NOT NULL,
+    status TEXT NOT NULL,
+    total_rows INTEGER NOT NULL,
+    valid_rows INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    approved_at TIMESTAMPTZ NULL,
+    completed_at TIMESTAMPTZ NULL
+);
+
+CREATE TABLE IF NOT EXISTS dstv_bulk_rows (
+    batch_id UUID NOT NULL REFERENCES dstv_bulk_batches(id) ON DELETE CASCADE,
+    line_number INTEGER NOT NULL,
+    customer_id TEXT NOT NULL,
+    basket_id TEXT NOT NULL,
+    -- Minor units of the batch product's currency; NULL when unparseable
+    amount BIGINT NULL,
+    -- Parent reference the MultiChoice confirmation is issued under
+    reference TEXT NULL,
+    account_name TEXT NULL,
+    status TEXT NOT NULL,
+    error_code TEXT NULL,
+    error TEXT NULL,
+    receipt_number TEXT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (batch_id, line_number)
+);
+
+CREATE INDEX IF NOT EXISTS dstv_bulk_rows_batch_status_idx
+    ON dstv_bulk_rows (batch_id, status);
@@ -0,0 +1,40 @@
+-- Recurring DSTV renewals and the outcome of every attempt to renew them
+CREATE TABLE IF NOT EXISTS dstv_subscriptions (
+    id UUID PRIMARY KEY,
+    customer_id TEXT NOT NULL,
+    basket_id TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    vas_product TEXT NOT NULL,
+    account_name TEXT NULL,
+    schedule JSONB NOT NULL,
+    funding_source TEXT NOT NULL,
+    status TEXT NOT NULL,
+    -- When the renewal currently being attempted was due
+    cycle_scheduled_for TIMESTAMPTZ NOT NULL,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cancelled_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX IF NOT EXISTS dstv_subscriptions_due_idx
+    ON dstv_subscriptions (status, next_run_at);
+
+CREATE TABLE IF NOT EXISTS dstv_subscription_runs (
+    id BIGSERIAL PRIMARY KEY,
+    subscription_id UUID NOT NULL REFERENCES dstv_subscriptions (id) ON DELETE CASCADE,
+    scheduled_for TIMESTAMPTZ NOT NULL,
+    status TEXT NOT NULL,
+    transaction_id INTEGER NULL REFERENCES transactions (id),
+    checkout_id UUID NULL REFERENCES dstv_checkouts (id),
+    error_code TEXT NULL,
+    error TEXT NULL,
+    receipt_number TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS dstv_subscription_runs_subscription_idx
+    ON dstv_subscription_runs (subscription_id, scheduled_for);
+CREATE INDEX IF NOT EXISTS dstv_subscription_runs_checkout_idx
+    ON dstv_subscription_runs (checkout_id) WHERE checkout_id IS NOT NULL;
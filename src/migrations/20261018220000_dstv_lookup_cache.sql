@@ -0,0 +1,11 @@
+-- Smartcard lookups shared between instances when DSTV_LOOKUP_CACHE_STORE=postgres
+CREATE TABLE IF NOT EXISTS dstv_lookup_cache (
+    vas_product TEXT NOT NULL,
+    customer_id TEXT NOT NULL,
+    outcome JSONB NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (vas_product, customer_id)
+);
+
+CREATE INDEX IF NOT EXISTS dstv_lookup_cache_expires_at_idx ON dstv_lookup_cache (expires_at);
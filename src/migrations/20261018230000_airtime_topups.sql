@@ -0,0 +1,22 @@
+-- Airtime top-ups sent to Quickteller, with the reference customers quote in disputes
+CREATE TABLE IF NOT EXISTS airtime_topups (
+    id BIGSERIAL PRIMARY KEY,
+    transaction_id INTEGER NOT NULL REFERENCES transactions(id),
+    -- Our requestReference, also the transaction's merchant_reference
+    request_reference TEXT NOT NULL UNIQUE,
+    phone_number TEXT NOT NULL,
+    network TEXT NOT NULL,
+    service_id INTEGER NOT NULL,
+    payment_code TEXT NOT NULL,
+    amount BIGINT NOT NULL,
+    status TEXT NOT NULL,
+    -- Quickteller's TransactionRef
+    transaction_ref TEXT NULL,
+    response_code TEXT NULL,
+    message TEXT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS airtime_topups_phone_number_idx
+    ON airtime_topups (phone_number, created_at);
@@ -0,0 +1,19 @@
+-- Customers Quickteller validated (meter, smartcard, betting account, ...),
+-- checked again before the customer is charged
+CREATE TABLE IF NOT EXISTS quickteller_customer_validations (
+    id UUID PRIMARY KEY,
+    service_id INTEGER NOT NULL,
+    payment_code TEXT NOT NULL,
+    customer_id TEXT NOT NULL,
+    customer_name TEXT NULL,
+    -- Kobo owed or due, as Quickteller reported it
+    balance BIGINT NULL,
+    amount_type TEXT NULL,
+    required_fields TEXT[] NOT NULL DEFAULT '{}',
+    response_code TEXT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS quickteller_customer_validations_customer_idx
+    ON quickteller_customer_validations (payment_code, customer_id, created_at);
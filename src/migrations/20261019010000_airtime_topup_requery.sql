@@ -0,0 +1,6 @@
+-- When the background requery last asked Quickteller about a pending top-up
+ALTER TABLE airtime_topups ADD COLUMN IF NOT EXISTS last_requeried_at TIMESTAMPTZ NULL;
+
+CREATE INDEX IF NOT EXISTS airtime_topups_pending_idx
+    ON airtime_topups (last_requeried_at NULLS FIRST, created_at)
+    WHERE status = 'PENDING';
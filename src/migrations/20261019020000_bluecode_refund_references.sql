@@ -0,0 +1,8 @@
+-- Our reference for each refund attempt, under which an unanswered refund is
+-- requeried, and when its state last changed
+ALTER TABLE bluecode_refunds ADD COLUMN IF NOT EXISTS refund_reference TEXT NULL UNIQUE;
+ALTER TABLE bluecode_refunds ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX IF NOT EXISTS bluecode_refunds_unresolved_idx
+    ON bluecode_refunds (updated_at)
+    WHERE state IN ('PENDING', 'UNKNOWN');
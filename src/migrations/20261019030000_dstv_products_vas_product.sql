@@ -0,0 +1,4 @@
+-- Baskets are priced per PayU VAS product (dstv-ng, gotv-ng, ...)
+ALTER TABLE dstv_products ADD COLUMN IF NOT EXISTS vas_product TEXT NOT NULL DEFAULT 'dstv-ng';
+ALTER TABLE dstv_products DROP CONSTRAINT IF EXISTS dstv_products_pkey;
+ALTER TABLE dstv_products ADD PRIMARY KEY (vas_product, basket_id);
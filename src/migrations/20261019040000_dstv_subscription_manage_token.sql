@@ -0,0 +1,3 @@
+-- Customers manage a subscription with a token returned once at creation;
+-- only its hash is kept. Older subscriptions are managed with the admin key.
+ALTER TABLE dstv_subscriptions ADD COLUMN IF NOT EXISTS manage_token_hash TEXT;
@@ -0,0 +1,6 @@
+-- Validations are looked up per biller and pruned once expired
+DROP INDEX IF EXISTS quickteller_customer_validations_customer_idx;
+CREATE INDEX IF NOT EXISTS quickteller_customer_validations_customer_idx
+    ON quickteller_customer_validations (service_id, payment_code, customer_id, created_at);
+CREATE INDEX IF NOT EXISTS quickteller_customer_validations_expiry_idx
+    ON quickteller_customer_validations (expires_at);
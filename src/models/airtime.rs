@@ -1,49 +1,191 @@
-use serde::Deserialize;
+use crate::models::billers::{PENDING_RESPONSE_CODES, SUCCESS_RESPONSE_CODE};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize)]
-pub struct GetBillersByCategoryResponse {
-    #[serde(rename = "BillerList")]
-    pub biller_list: BillerList,
-    #[serde(rename = "ResponseCode")]
-    pub response_code: String,
-    #[serde(rename = "ResponseCodeGrouping")]
-    pub response_code_grouping: String,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AirtimeNetwork {
+    Mtn,
+    Airtel,
+    Glo,
+    #[serde(rename = "9mobile")]
+    NineMobile,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct BillerList {
-    #[serde(rename = "Count")]
-    pub count: u32,
-    #[serde(rename = "Category")]
-    pub category: Vec<BillerCategory>,
+impl AirtimeNetwork {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AirtimeNetwork::Mtn => "mtn",
+            AirtimeNetwork::Airtel => "airtel",
+            AirtimeNetwork::Glo => "glo",
+            AirtimeNetwork::NineMobile => "9mobile",
+        }
+    }
+
+    /// Quickteller biller selling the network's top-ups, overridable with
+    /// `QUICKTELLER_{MTN,AIRTEL,GLO,9MOBILE}_SERVICE_ID`.
+    pub fn service_id(&self) -> u32 {
+        let (var, default) = match self {
+            AirtimeNetwork::Mtn => ("QUICKTELLER_MTN_SERVICE_ID", 109),
+            AirtimeNetwork::Airtel => ("QUICKTELLER_AIRTEL_SERVICE_ID", 17305),
+            AirtimeNetwork::Glo => ("QUICKTELLER_GLO_SERVICE_ID", 402),
+            AirtimeNetwork::NineMobile => ("QUICKTELLER_9MOBILE_SERVICE_ID", 120),
+        };
+        env::var(var)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    }
+}
+
+impl FromStr for AirtimeNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mtn" => Ok(AirtimeNetwork::Mtn),
+            "airtel" => Ok(AirtimeNetwork::Airtel),
+            "glo" => Ok(AirtimeNetwork::Glo),
+            "9mobile" => Ok(AirtimeNetwork::NineMobile),
+            other => Err(format!("Unknown airtime network: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for AirtimeNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, Deserialize)]
-pub struct BillerCategory {
-    #[serde(rename = "Id")]
-    pub id: u32,
-    #[serde(rename = "Name")]
-    pub name: String,
-    #[serde(rename = "Description")]
-    pub description: String,
-    #[serde(rename = "Billers")]
-    pub billers: Vec<Biller>,
+pub struct AirtimeTopupRequest {
+    pub phone_number: String,
+    pub network: AirtimeNetwork,
+    pub amount: i64, // in kobo
+}
+
+/// Bill payment advice sent to Quickteller.
+#[derive(Debug, Serialize)]
+pub struct PaymentAdvice {
+    #[serde(rename = "PaymentCode")]
+    pub payment_code: String,
+    #[serde(rename = "CustomerId")]
+    pub customer_id: String,
+    #[serde(rename = "CustomerMobile")]
+    pub customer_mobile: String,
+    /// Kobo
+    #[serde(rename = "Amount")]
+    pub amount: String,
+    #[serde(rename = "RequestReference")]
+    pub request_reference: String,
 }
 
+/// Quickteller's answer to a payment advice or a transaction query.
 #[derive(Debug, Deserialize)]
-pub struct Biller {
-    #[serde(rename = "Id")]
-    pub id: u32,
-    #[serde(rename = "Name")]
-    pub name: String,
-    #[serde(rename = "ShortName")]
-    pub short_name: Option<String>,
-    #[serde(rename = "CustomerField1")]
-    pub customer_field1: Option<String>,
-    #[serde(rename = "LogoUrl")]
-    pub logo_url: Option<String>,
-    #[serde(rename = "NetworkId")]
-    pub network_id: Option<String>,
-    #[serde(rename = "ProductCode")]
-    pub product_code: Option<String>,
+pub struct PaymentAdviceResponse {
+    #[serde(default, rename = "TransactionRef")]
+    pub transaction_ref: Option<String>,
+    #[serde(default, rename = "ResponseCode")]
+    pub response_code: Option<String>,
+    #[serde(default, rename = "ResponseCodeGrouping")]
+    pub response_code_grouping: Option<String>,
+    #[serde(default, rename = "ResponseDescription")]
+    pub response_description: Option<String>,
+}
+
+impl PaymentAdviceResponse {
+    pub fn status(&self) -> TopupStatus {
+        TopupStatus::from_response(
+            self.response_code.as_deref(),
+            self.response_code_grouping.as_deref(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TopupStatus {
+    Successful,
+    /// Quickteller has not settled the top-up yet; query it again later
+    Pending,
+    Failed,
+}
+
+impl TopupStatus {
+    /// Maps a Quickteller response: `90000` is success, the pending family
+    /// and a `PENDING` grouping are pending, and every other code is a
+    /// failure. The grouping decides only when no code was sent; a reply with
+    /// neither says nothing yet and is pending.
+    pub fn from_response(code: Option<&str>, grouping: Option<&str>) -> Self {
+        match (code, grouping) {
+            (Some(SUCCESS_RESPONSE_CODE), _) | (None, Some("SUCCESSFUL")) => {
+                TopupStatus::Successful
+            }
+            (Some(code), _) if PENDING_RESPONSE_CODES.contains(&code) => TopupStatus::Pending,
+            (_, Some("PENDING")) | (None, None) => TopupStatus::Pending,
+            _ => TopupStatus::Failed,
+        }
+    }
+
+    /// `confirm_status` of the transaction behind the top-up.
+    pub fn confirm_status(&self) -> &'static str {
+        match self {
+            TopupStatus::Successful => "CONFIRMED",
+            TopupStatus::Pending => "PENDING",
+            TopupStatus::Failed => "FAILED",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TopupStatus::Successful => "SUCCESSFUL",
+            TopupStatus::Pending => "PENDING",
+            TopupStatus::Failed => "FAILED",
+        }
+    }
+}
+
+impl FromStr for TopupStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SUCCESSFUL" => Ok(TopupStatus::Successful),
+            "PENDING" => Ok(TopupStatus::Pending),
+            "FAILED" => Ok(TopupStatus::Failed),
+            other => Err(format!("Unknown top-up status: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for TopupStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AirtimeTopup {
+    pub id: i64,
+    pub transaction_id: i32,
+    /// Our reference for the top-up
+    pub request_reference: String,
+    pub phone_number: String,
+    pub network: String,
+    pub service_id: i32,
+    pub payment_code: String,
+    pub amount: i64,
+    pub status: String,
+    /// Quickteller's reference, quoted when disputing a top-up
+    pub transaction_ref: Option<String>,
+    pub response_code: Option<String>,
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Last time the background requery asked Quickteller about it
+    pub last_requeried_at: Option<DateTime<Utc>>,
 }
@@ -1,25 +1,272 @@
-use serde::Deserialize;
+//! The Quickteller v5 services API: biller categories, billers and the
+//! payment items they sell.
+//!
+//! Quickteller's PascalCase fields are read as they come and served to our
+//! clients in snake_case. Amounts are kobo, whether Quickteller sends them as
+//! numbers or strings.
 
-#[derive(Debug, Deserialize)]
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+
+/// `ResponseCode` of a successful request
+pub const SUCCESS_RESPONSE_CODE: &str = "90000";
+
+/// `ResponseCode`s of payments Quickteller has not settled yet: `900` plus
+/// the ISO 8583 codes for in progress (09), late response (68), issuer or
+/// switch inoperative (91) and system malfunction (96). Requerying them
+/// later gives the final outcome.
+pub const PENDING_RESPONSE_CODES: &[&str] = &["90009", "90068", "90091", "90096"];
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct GetBillerCategoriesResponse {
-    #[serde(rename = "BillerCategories")]
+    #[serde(rename(deserialize = "BillerCategories"))]
     pub biller_categories: Vec<BillerCategory>,
 
-    #[serde(rename = "ResponseCode")]
+    #[serde(default, rename(deserialize = "ResponseCode"))]
     pub response_code: Option<String>,
 
-    #[serde(rename = "ResponseCodeGrouping")]
+    #[serde(default, rename(deserialize = "ResponseCodeGrouping"))]
     pub response_code_grouping: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetBillersByCategoryResponse {
+    #[serde(rename(deserialize = "BillerList"))]
+    pub biller_list: BillerList,
+
+    #[serde(default, rename(deserialize = "ResponseCode"))]
+    pub response_code: Option<String>,
+
+    #[serde(default, rename(deserialize = "ResponseCodeGrouping"))]
+    pub response_code_grouping: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BillerList {
+    #[serde(default, rename(deserialize = "Count"))]
+    pub count: u32,
+
+    #[serde(default, rename(deserialize = "Category"))]
+    pub category: Vec<BillerCategory>,
+}
+
+/// A biller category. Its billers are only listed when the category is
+/// fetched on its own.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct BillerCategory {
-    #[serde(rename = "Id")]
+    #[serde(rename(deserialize = "Id"))]
     pub id: u32,
 
-    #[serde(rename = "Name")]
+    #[serde(rename(deserialize = "Name"))]
     pub name: String,
 
-    #[serde(rename = "Description")]
-    pub description: String,
+    #[serde(default, rename(deserialize = "Description"))]
+    pub description: Option<String>,
+
+    #[serde(
+        default,
+        rename(deserialize = "Billers"),
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub billers: Vec<Biller>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Biller {
+    #[serde(rename(deserialize = "Id"))]
+    pub id: u32,
+
+    #[serde(rename(deserialize = "Name"))]
+    pub name: String,
+
+    #[serde(default, rename(deserialize = "ShortName"))]
+    pub short_name: Option<String>,
+
+    #[serde(default, rename(deserialize = "CategoryId"))]
+    pub category_id: Option<u32>,
+
+    /// Label of the customer id the biller expects, e.g. "Mobile Number"
+    #[serde(default, rename(deserialize = "CustomerField1"))]
+    pub customer_field1: Option<String>,
+
+    #[serde(default, rename(deserialize = "CustomerField2"))]
+    pub customer_field2: Option<String>,
+
+    #[serde(default, rename(deserialize = "LogoUrl"))]
+    pub logo_url: Option<String>,
+
+    #[serde(default, rename(deserialize = "NetworkId"))]
+    pub network_id: Option<String>,
+
+    #[serde(default, rename(deserialize = "ProductCode"))]
+    pub product_code: Option<String>,
+
+    #[serde(default, rename(deserialize = "CurrencyCode"))]
+    pub currency_code: Option<String>,
+
+    #[serde(default, rename(deserialize = "CurrencySymbol"))]
+    pub currency_symbol: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetBillerPaymentItemsResponse {
+    #[serde(default, rename(deserialize = "PaymentItems"))]
+    pub payment_items: Vec<PaymentItem>,
+
+    #[serde(default, rename(deserialize = "ResponseCode"))]
+    pub response_code: Option<String>,
+
+    #[serde(default, rename(deserialize = "ResponseCodeGrouping"))]
+    pub response_code_grouping: Option<String>,
+}
+
+/// Something a biller sells, e.g. a top-up or a bundle.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentItem {
+    #[serde(rename(deserialize = "Id"))]
+    pub id: String,
+
+    #[serde(rename(deserialize = "Name"))]
+    pub name: String,
+
+    #[serde(default, rename(deserialize = "BillerName"))]
+    pub biller_name: Option<String>,
+
+    #[serde(default, rename(deserialize = "BillerId"))]
+    pub biller_id: Option<String>,
+
+    #[serde(default, rename(deserialize = "ConsumerIdField"))]
+    pub consumer_id_field: Option<String>,
+
+    /// Item code within the biller
+    #[serde(default, rename(deserialize = "Code"))]
+    pub code: Option<String>,
+
+    /// Sent as `PaymentCode` in a payment advice
+    #[serde(rename(deserialize = "PaymentCode"))]
+    pub payment_code: String,
+
+    /// Price in kobo when `is_amount_fixed`
+    #[serde(default, rename(deserialize = "Amount"), deserialize_with = "kobo")]
+    pub amount: Option<i64>,
+
+    #[serde(default, rename(deserialize = "IsAmountFixed"))]
+    pub is_amount_fixed: bool,
+
+    #[serde(
+        default,
+        rename(deserialize = "MinimumAmount"),
+        deserialize_with = "kobo"
+    )]
+    pub minimum_amount: Option<i64>,
+
+    #[serde(
+        default,
+        rename(deserialize = "MaximumAmount"),
+        deserialize_with = "kobo"
+    )]
+    pub maximum_amount: Option<i64>,
+
+    #[serde(default, rename(deserialize = "ItemFee"), deserialize_with = "kobo")]
+    pub item_fee: Option<i64>,
+
+    /// ISO 4217 numeric code, `566` for naira
+    #[serde(default, rename(deserialize = "CurrencyCode"))]
+    pub currency_code: Option<String>,
+
+    #[serde(default, rename(deserialize = "CurrencySymbol"))]
+    pub currency_symbol: Option<String>,
+}
+
+/// Customer validation sent to Quickteller.
+#[derive(Debug, Serialize)]
+pub struct ValidateCustomersRequest {
+    pub customers: Vec<CustomerToValidate>,
+    #[serde(rename = "TerminalId")]
+    pub terminal_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CustomerToValidate {
+    #[serde(rename = "PaymentCode")]
+    pub payment_code: String,
+    #[serde(rename = "CustomerId")]
+    pub customer_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateCustomersResponse {
+    #[serde(default, rename = "Customers")]
+    pub customers: Vec<ValidatedCustomer>,
+    #[serde(default, rename = "ResponseCode")]
+    pub response_code: Option<String>,
+    #[serde(default, rename = "ResponseCodeGrouping")]
+    pub response_code_grouping: Option<String>,
+}
+
+/// What Quickteller knows about one customer of a biller.
+#[derive(Debug, Deserialize)]
+pub struct ValidatedCustomer {
+    #[serde(default, rename = "PaymentCode")]
+    pub payment_code: Option<String>,
+    #[serde(default, rename = "CustomerId")]
+    pub customer_id: Option<String>,
+    #[serde(default, rename = "ResponseCode")]
+    pub response_code: Option<String>,
+    #[serde(default, rename = "ResponseDescription")]
+    pub response_description: Option<String>,
+    #[serde(default, rename = "FullName")]
+    pub full_name: Option<String>,
+    /// Kobo owed or due
+    #[serde(default, rename = "Amount", deserialize_with = "kobo")]
+    pub amount: Option<i64>,
+    #[serde(default, rename = "AmountTypeDescription")]
+    pub amount_type_description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CustomerValidationRequest {
+    pub payment_code: String,
+    pub customer_id: String,
+}
+
+/// A customer Quickteller validated, remembered until `expires_at` so the
+/// payment can be checked against it.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct CustomerValidation {
+    pub id: Uuid,
+    pub service_id: i32,
+    pub payment_code: String,
+    pub customer_id: String,
+    pub customer_name: Option<String>,
+    /// Kobo owed or due, when the biller reports one
+    pub balance: Option<i64>,
+    pub amount_type: Option<String>,
+    /// Customer details the biller asks for, e.g. "Meter Number"
+    pub required_fields: Vec<String>,
+    pub response_code: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A kobo amount sent as a number, a numeric string, an empty string or null.
+fn kobo<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Number(i64),
+        Text(String),
+    }
+
+    match Option::<Amount>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Amount::Number(kobo)) => Ok(Some(kobo)),
+        Some(Amount::Text(text)) if text.trim().is_empty() => Ok(None),
+        Some(Amount::Text(text)) => text
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid amount {:?}", text))),
+    }
 }
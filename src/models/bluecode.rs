@@ -1,4 +1,92 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Payment state as reported by Bluecode. States this backend does not know
+/// about are kept verbatim in `Unknown` so they can be logged; they are never
+/// stored, so write [`BluecodePaymentState::known`] instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum BluecodePaymentState {
+    Registered,
+    Pending,
+    Approved,
+    Declined,
+    Failure,
+    Cancelled,
+    Unknown(String),
+}
+
+impl BluecodePaymentState {
+    pub fn as_str(&self) -> &str {
+        match self {
+            BluecodePaymentState::Registered => "REGISTERED",
+            BluecodePaymentState::Pending => "PENDING",
+            BluecodePaymentState::Approved => "APPROVED",
+            BluecodePaymentState::Declined => "DECLINED",
+            BluecodePaymentState::Failure => "FAILURE",
+            BluecodePaymentState::Cancelled => "CANCELLED",
+            BluecodePaymentState::Unknown(raw) => raw,
+        }
+    }
+
+    /// The state, unless it is one this backend does not know.
+    pub fn known(&self) -> Option<&Self> {
+        match self {
+            BluecodePaymentState::Unknown(_) => None,
+            state => Some(state),
+        }
+    }
+
+    /// Whether Bluecode will not move the payment to another state anymore.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            BluecodePaymentState::Approved
+                | BluecodePaymentState::Declined
+                | BluecodePaymentState::Failure
+                | BluecodePaymentState::Cancelled
+        )
+    }
+}
+
+impl From<String> for BluecodePaymentState {
+    fn from(raw: String) -> Self {
+        match raw.to_ascii_uppercase().as_str() {
+            "REGISTERED" => BluecodePaymentState::Registered,
+            "PENDING" => BluecodePaymentState::Pending,
+            "APPROVED" => BluecodePaymentState::Approved,
+            "DECLINED" => BluecodePaymentState::Declined,
+            "FAILURE" => BluecodePaymentState::Failure,
+            "CANCELLED" => BluecodePaymentState::Cancelled,
+            _ => BluecodePaymentState::Unknown(raw),
+        }
+    }
+}
+
+impl From<&str> for BluecodePaymentState {
+    fn from(raw: &str) -> Self {
+        BluecodePaymentState::from(raw.to_string())
+    }
+}
+
+impl From<BluecodePaymentState> for String {
+    fn from(state: BluecodePaymentState) -> Self {
+        state.as_str().to_string()
+    }
+}
+
+impl fmt::Display for BluecodePaymentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Default for BluecodePaymentState {
+    fn default() -> Self {
+        BluecodePaymentState::Unknown("UNKNOWN".to_string())
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BluecodeRegisterResponseWrapper {
@@ -10,18 +98,18 @@ pub struct BluecodeRegisterResponseWrapper {
 pub struct BluecodeRegisterResponse {
     pub merchant_tx_id: String,
     pub checkin_code: String,
-    pub state: String,
+    pub state: BluecodePaymentState,
 }
 #[derive(Debug, Deserialize)]
 pub struct PaymentInitRequest {
-    pub amount: i64, // in kobo
+    pub amount: i64, // in minor units of BLUECODE_CURRENCY
 }
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct BluecodeRegisterRequest {
     pub merchant_tx_id: String,
     pub branch_ext_id: String,
     pub scheme: String,
-    pub requested_amount: i64,
+    pub requested_amount: i64, // in minor units of `currency`
     pub currency: String,
     pub terminal: String,
     pub source: String,
@@ -42,9 +130,86 @@ pub struct BluecodeStatusResponseWrapper {
     pub payment: BluecodeStatusResponse,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct BluecodeStatusResponse {
+    pub state: BluecodePaymentState,
+    pub merchant_tx_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_amount: Option<i64>, // in kobo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_amount: Option<i64>, // in kobo, including tip
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acquirer_tx_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consumer_tip_amount: Option<i64>, // in kobo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BluecodeCancelRequest {
+    pub merchant_tx_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BluecodeCancelResponse {
+    pub result: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BluecodeRefundRequest {
+    pub merchant_tx_id: String,
+    /// Our reference for this refund, under which it can be requeried
+    pub merchant_refund_id: String,
+    pub amount: i64, // in kobo
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BluecodeRefundStatusRequest {
+    pub merchant_tx_id: String,
+    pub merchant_refund_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BluecodeRefundResponseWrapper {
+    pub result: String,
+    pub refund: BluecodeRefundResponse,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BluecodeRefundResponse {
+    pub refund_tx_id: Option<String>,
+    pub merchant_tx_id: String,
+    pub amount: i64,
     pub state: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefundInitRequest {
+    /// Amount to refund in kobo; the whole remaining amount when omitted
+    pub amount: Option<i64>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct BluecodeRefund {
+    pub id: i32,
+    pub transaction_id: i32,
     pub merchant_tx_id: String,
-    // Add other fields as needed
+    pub amount: i64,
+    pub reason: String,
+    pub state: String,
+    pub refund_tx_id: Option<String>,
+    pub refund_reference: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
 }
@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// `Previewed` → `Running` → `Completed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BulkBatchStatus {
+    /// Rows validated, waiting for approval
+    Previewed,
+    /// Approved, valid rows being confirmed with MultiChoice
+    Running,
+    /// Every valid row has a final result
+    Completed,
+}
+
+impl BulkBatchStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkBatchStatus::Previewed => "PREVIEWED",
+            BulkBatchStatus::Running => "RUNNING",
+            BulkBatchStatus::Completed => "COMPLETED",
+        }
+    }
+}
+
+impl FromStr for BulkBatchStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PREVIEWED" => Ok(BulkBatchStatus::Previewed),
+            "RUNNING" => Ok(BulkBatchStatus::Running),
+            "COMPLETED" => Ok(BulkBatchStatus::Completed),
+            other => Err(format!("Unknown bulk batch status: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for BulkBatchStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `Valid` → `Processing` → `Confirmed`, `Pending` or `Failed`; rows that
+/// fail validation stay `Invalid` and are never sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BulkRowStatus {
+    Invalid,
+    Valid,
+    Processing,
+    Confirmed,
+    /// MultiChoice has not settled the renewal yet
+    Pending,
+    Failed,
+}
+
+impl BulkRowStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkRowStatus::Invalid => "INVALID",
+            BulkRowStatus::Valid => "VALID",
+            BulkRowStatus::Processing => "PROCESSING",
+            BulkRowStatus::Confirmed => "CONFIRMED",
+            BulkRowStatus::Pending => "PENDING",
+            BulkRowStatus::Failed => "FAILED",
+        }
+    }
+}
+
+impl fmt::Display for BulkRowStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRenewalQuery {
+    /// VAS product id, `dstv-ng` when omitted
+    pub product: Option<String>,
+    /// Label for the batch, e.g. the customer's name
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkRenewalBatch {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub vas_product: String,
+    pub status: String,
+    pub total_rows: i32,
+    pub valid_rows: i32,
+    pub created_at: DateTime<Utc>,
+    pub approved_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkRenewalRow {
+    /// Line in the uploaded file, the header being line 1
+    pub line_number: i32,
+    pub customer_id: String,
+    pub basket_id: String,
+    /// Minor units of the product's currency
+    pub amount: Option<i64>,
+    pub account_name: Option<String>,
+    pub status: String,
+    pub error_code: Option<String>,
+    pub error: Option<String>,
+    pub receipt_number: Option<String>,
+}
+
+/// A batch with every row, returned as the preview and as progress.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkRenewalReport {
+    pub batch: BulkRenewalBatch,
+    pub rows: Vec<BulkRenewalRow>,
+}
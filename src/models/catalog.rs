@@ -0,0 +1,58 @@
+use crate::config::DEFAULT_VAS_PRODUCT;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ProductKind {
+    /// Main subscription (Padi, Yanga, Compact, Premium, ...)
+    Bouquet,
+    /// Extra on top of a bouquet (BoxOffice, extra view, French, ...)
+    Addon,
+}
+
+impl ProductKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProductKind::Bouquet => "BOUQUET",
+            ProductKind::Addon => "ADDON",
+        }
+    }
+}
+
+impl FromStr for ProductKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "BOUQUET" => Ok(ProductKind::Bouquet),
+            "ADDON" => Ok(ProductKind::Addon),
+            other => Err(format!("Unknown product kind: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DstvProduct {
+    /// VAS product the basket is sold under, `dstv-ng` when omitted
+    #[serde(default = "default_vas_product")]
+    pub vas_product: String,
+    /// Sent to MultiChoice as the `BasketId` custom field
+    pub basket_id: String,
+    pub name: String,
+    pub kind: ProductKind,
+    /// Price in kobo
+    pub price: i64,
+    pub validity_days: i32,
+}
+
+fn default_vas_product() -> String {
+    DEFAULT_VAS_PRODUCT.to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProductQuery {
+    pub kind: Option<ProductKind>,
+    /// VAS product id, `dstv-ng` when omitted
+    pub product: Option<String>,
+}
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Where a server-side DSTV checkout currently stands.
+///
+/// `Created` → `PaymentRegistered` → `PaymentApproved` → `Confirmed`, with a
+/// detour through `ConfirmationPending` while MultiChoice is still processing,
+/// or one of the terminal failure steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CheckoutStep {
+    /// Checkout stored, Bluecode payment not registered yet
+    Created,
+    /// QR code issued, waiting for the customer to pay
+    PaymentRegistered,
+    /// Customer paid, MultiChoice confirmation pending or being retried
+    PaymentApproved,
+    /// MultiChoice accepted the confirmation but has not settled it; requeried
+    /// until it does
+    ConfirmationPending,
+    /// MultiChoice confirmed the subscription
+    Confirmed,
+    /// Bluecode payment was declined, cancelled or failed
+    PaymentFailed,
+    /// Customer paid but MultiChoice never confirmed; the payment must be refunded
+    RefundRequired,
+}
+
+impl CheckoutStep {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckoutStep::Created => "CREATED",
+            CheckoutStep::PaymentRegistered => "PAYMENT_REGISTERED",
+            CheckoutStep::PaymentApproved => "PAYMENT_APPROVED",
+            CheckoutStep::ConfirmationPending => "CONFIRMATION_PENDING",
+            CheckoutStep::Confirmed => "CONFIRMED",
+            CheckoutStep::PaymentFailed => "PAYMENT_FAILED",
+            CheckoutStep::RefundRequired => "REFUND_REQUIRED",
+        }
+    }
+
+    /// Steps the checkout worker still has to run
+    pub const UNFINISHED: [CheckoutStep; 4] = [
+        CheckoutStep::Created,
+        CheckoutStep::PaymentRegistered,
+        CheckoutStep::PaymentApproved,
+        CheckoutStep::ConfirmationPending,
+    ];
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            CheckoutStep::Confirmed | CheckoutStep::PaymentFailed | CheckoutStep::RefundRequired
+        )
+    }
+}
+
+impl FromStr for CheckoutStep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CREATED" => Ok(CheckoutStep::Created),
+            "PAYMENT_REGISTERED" => Ok(CheckoutStep::PaymentRegistered),
+            "PAYMENT_APPROVED" => Ok(CheckoutStep::PaymentApproved),
+            "CONFIRMATION_PENDING" => Ok(CheckoutStep::ConfirmationPending),
+            "CONFIRMED" => Ok(CheckoutStep::Confirmed),
+            "PAYMENT_FAILED" => Ok(CheckoutStep::PaymentFailed),
+            "REFUND_REQUIRED" => Ok(CheckoutStep::RefundRequired),
+            other => Err(format!("Unknown checkout step: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for CheckoutStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartCheckoutRequest {
+    pub customer_id: String,
+    pub basket_id: String,
+    pub amount: i64, // in kobo
+    /// VAS product id, `dstv-ng` when omitted
+    #[serde(default)]
+    pub product: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DstvCheckout {
+    pub id: Uuid,
+    pub merchant_tx_id: String,
+    pub transaction_id: i32,
+    pub customer_id: String,
+    pub basket_id: String,
+    /// Catalog price of the basket when the checkout started; MultiChoice is
+    /// confirmed for this amount even if the catalog changes meanwhile
+    pub amount: i64,
+    pub vas_product: String,
+    pub account_name: Option<String>,
+    pub checkin_code: Option<String>,
+    pub step: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
@@ -1,3 +1,4 @@
+use crate::models::payu_vas::{PayUVasResponse, VasOutcome, VasRequeryItem};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -5,20 +6,214 @@ use std::collections::HashMap;
 pub struct DstvConfirmPaymentRequest {
     pub customer_id: String,
     pub basket_id: String,
-    pub amount: u32,
+    /// Minor units of the product's currency (kobo for `dstv-ng`)
+    pub amount: i64,
     pub merchant_reference: String,
+    /// VAS product id, `dstv-ng` when omitted
+    #[serde(default)]
+    pub product: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DstvLookupRequest {
     pub customer_id: String,
+    /// VAS product id, `dstv-ng` when omitted
+    #[serde(default)]
+    pub product: Option<String>,
+    /// Skip the lookup cache and ask MultiChoice again
+    #[serde(default)]
+    pub refresh: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Result codes seen from MultiChoice through PayU. PayU publishes no code
+/// table for MultiChoice, so these only choose the customer message; other
+/// codes are classified by their `ResultMessage`. A duplicate is settled by
+/// requerying the reference, never by the code alone.
+const INVALID_SMARTCARD_CODES: &[&str] = &["12", "14", "56"];
+const ACCOUNT_SUSPENDED_CODES: &[&str] = &["62"];
+const AMOUNT_MISMATCH_CODES: &[&str] = &["13", "64"];
+const DUPLICATE_REFERENCE_CODES: &[&str] = &["26", "94"];
+const UPSTREAM_UNAVAILABLE_CODES: &[&str] = &["91", "96", "99"];
+
+/// Why MultiChoice did not complete a request. Serialized as a stable code
+/// the frontend can switch on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DstvErrorCode {
+    InvalidSmartcard,
+    AccountSuspended,
+    AmountMismatch,
+    DuplicateReference,
+    UpstreamUnavailable,
+    Pending,
+    Unknown,
+}
+
+impl DstvErrorCode {
+    /// Classifies a PayU `ResultCode`/`ResultMessage`; `None` on success.
+    pub fn from_result(code: &str, message: &str) -> Option<Self> {
+        let code = code.trim();
+        match VasOutcome::from_result_code(code) {
+            VasOutcome::Success => return None,
+            VasOutcome::Pending => return Some(DstvErrorCode::Pending),
+            VasOutcome::Failure => {}
+        }
+
+        let by_code = [
+            (INVALID_SMARTCARD_CODES, DstvErrorCode::InvalidSmartcard),
+            (ACCOUNT_SUSPENDED_CODES, DstvErrorCode::AccountSuspended),
+            (AMOUNT_MISMATCH_CODES, DstvErrorCode::AmountMismatch),
+            (DUPLICATE_REFERENCE_CODES, DstvErrorCode::DuplicateReference),
+            (
+                UPSTREAM_UNAVAILABLE_CODES,
+                DstvErrorCode::UpstreamUnavailable,
+            ),
+        ];
+        if let Some((_, kind)) = by_code.iter().find(|(codes, _)| codes.contains(&code)) {
+            return Some(*kind);
+        }
+
+        let message = message.to_ascii_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|w| message.contains(w));
+        Some(
+            if mentions(&["duplicate", "already processed", "already exists"]) {
+                DstvErrorCode::DuplicateReference
+            } else if mentions(&["suspend", "disconnect", "inactive", "blocked", "dormant"]) {
+                DstvErrorCode::AccountSuspended
+            } else if mentions(&[
+                "smartcard",
+                "smart card",
+                "customer number",
+                "invalid account",
+            ]) {
+                DstvErrorCode::InvalidSmartcard
+            } else if mentions(&["amount", "price"]) {
+                DstvErrorCode::AmountMismatch
+            } else if mentions(&[
+                "timeout",
+                "timed out",
+                "unavailable",
+                "system error",
+                "try again",
+            ]) {
+                DstvErrorCode::UpstreamUnavailable
+            } else {
+                DstvErrorCode::Unknown
+            },
+        )
+    }
+
+    /// Classifies a requery `status`; `None` when the payment went through.
+    pub fn from_requery_status(status: i32) -> Option<Self> {
+        match VasOutcome::from_requery_status(status) {
+            VasOutcome::Success => None,
+            VasOutcome::Pending => Some(DstvErrorCode::Pending),
+            VasOutcome::Failure => Some(DstvErrorCode::Unknown),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DstvErrorCode::InvalidSmartcard => "INVALID_SMARTCARD",
+            DstvErrorCode::AccountSuspended => "ACCOUNT_SUSPENDED",
+            DstvErrorCode::AmountMismatch => "AMOUNT_MISMATCH",
+            DstvErrorCode::DuplicateReference => "DUPLICATE_REFERENCE",
+            DstvErrorCode::UpstreamUnavailable => "UPSTREAM_UNAVAILABLE",
+            DstvErrorCode::Pending => "PENDING",
+            DstvErrorCode::Unknown => "UNKNOWN",
+        }
+    }
+
+    /// Message the frontend can show as is.
+    pub fn customer_message(&self) -> &'static str {
+        match self {
+            DstvErrorCode::InvalidSmartcard => {
+                "We couldn't find that smartcard number. Please check it and try again."
+            }
+            DstvErrorCode::AccountSuspended => {
+                "This DSTV account is suspended. Please contact MultiChoice to reactivate it."
+            }
+            DstvErrorCode::AmountMismatch => {
+                "The amount doesn't match the selected package. Please refresh and try again."
+            }
+            DstvErrorCode::DuplicateReference => "This payment has already been submitted.",
+            DstvErrorCode::UpstreamUnavailable => {
+                "DSTV is not responding right now. Please try again in a few minutes."
+            }
+            DstvErrorCode::Pending => "Your payment is being processed. We'll confirm it shortly.",
+            DstvErrorCode::Unknown => {
+                "We couldn't complete your DSTV request. Please try again or contact support."
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DstvLookupResponse {
     pub account_name: Option<String>,
     pub customer_id: Option<String>,
     pub message: String,
     pub success: bool,
     pub custom_fields: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<DstvErrorCode>,
+}
+
+/// MultiChoice's answer to a subscription payment, from the payment call or,
+/// when that failed, from a requery.
+#[derive(Debug, Clone, Serialize)]
+pub struct DstvConfirmation {
+    pub outcome: VasOutcome,
+    pub result_code: String,
+    pub message: String,
+    pub payu_reference: Option<String>,
+    pub receipt_number: Option<String>,
+    pub custom_fields: HashMap<String, String>,
+    pub requeried: bool,
+    /// Set unless the outcome is a success
+    pub error_code: Option<DstvErrorCode>,
+}
+
+impl From<PayUVasResponse> for DstvConfirmation {
+    fn from(response: PayUVasResponse) -> Self {
+        DstvConfirmation {
+            outcome: response.outcome(),
+            error_code: DstvErrorCode::from_result(&response.result_code, &response.result_message),
+            custom_fields: response.fields(),
+            result_code: response.result_code,
+            message: response.result_message,
+            payu_reference: response.payu_reference,
+            receipt_number: response.receipt_number,
+            requeried: false,
+        }
+    }
+}
+
+impl From<VasRequeryItem> for DstvConfirmation {
+    fn from(item: VasRequeryItem) -> Self {
+        let outcome = VasOutcome::from_requery_status(item.status);
+        DstvConfirmation {
+            outcome,
+            result_code: item.status.to_string(),
+            message: format!("Requery reported {:?}", outcome),
+            payu_reference: None,
+            receipt_number: None,
+            custom_fields: HashMap::from([
+                ("smartcard".to_string(), item.smartcard),
+                ("basketid".to_string(), item.basketid),
+            ]),
+            requeried: true,
+            error_code: DstvErrorCode::from_requery_status(item.status),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DstvConfirmPaymentResponse {
+    pub success: bool,
+    pub outcome: Option<VasOutcome>,
+    pub confirmation: Option<DstvConfirmation>,
+    /// Customer-friendly when `error_code` is set
+    pub message: String,
+    pub error_code: Option<DstvErrorCode>,
 }
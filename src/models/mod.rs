@@ -1,6 +1,13 @@
 pub mod airtime;
 pub mod billers;
 pub mod bluecode;
+pub mod bulk_renewals;
+pub mod catalog;
+pub mod checkout;
 pub mod dstv;
+pub mod money;
 pub mod payments;
+pub mod payu_vas;
+pub mod subscriptions;
 pub mod transactions;
+pub mod vendor_exchanges;
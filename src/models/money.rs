@@ -0,0 +1,220 @@
+//! Amounts in integer minor units (kobo, pesewas, cents, ...) tagged with
+//! their currency.
+//!
+//! Amounts become a [`Money`] where they reach a provider: VAS payments,
+//! Bluecode registrations, Quickteller payments and airtime top-ups, as well
+//! as bulk renewal rows. Conversions to the unit a provider expects happen
+//! in that provider's adapter and nowhere else.
+//!
+//! Request bodies (`PaymentInitRequest`, `StartCheckoutRequest`, ...),
+//! stored rows (`Transaction.amount`, refunds, checkouts) and Bluecode status
+//! replies still carry raw minor units as `i64`, in the currency of the VAS
+//! product or of Bluecode they belong to.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MoneyError {
+    #[error("Cannot combine {0} with {1}")]
+    CurrencyMismatch(Currency, Currency),
+
+    #[error("Amount overflow")]
+    Overflow,
+
+    #[error("Amount {0} is out of range")]
+    OutOfRange(String),
+
+    #[error("Invalid amount: {0}")]
+    Invalid(String),
+
+    #[error("Unknown currency {0}")]
+    UnknownCurrency(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Ngn,
+    Ghs,
+    Kes,
+    Zar,
+    Usd,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Ngn => "NGN",
+            Currency::Ghs => "GHS",
+            Currency::Kes => "KES",
+            Currency::Zar => "ZAR",
+            Currency::Usd => "USD",
+        }
+    }
+
+    /// Digits after the decimal point; 2 for every currency we sell in.
+    pub fn minor_digits(&self) -> u32 {
+        2
+    }
+
+    /// Minor units in one major unit, e.g. 100 kobo per naira.
+    pub fn minor_per_major(&self) -> i64 {
+        10_i64.pow(self.minor_digits())
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "NGN" => Ok(Currency::Ngn),
+            "GHS" => Ok(Currency::Ghs),
+            "KES" => Ok(Currency::Kes),
+            "ZAR" => Ok(Currency::Zar),
+            "USD" => Ok(Currency::Usd),
+            other => Err(MoneyError::UnknownCurrency(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Money {
+    /// Minor units, e.g. kobo for NGN
+    amount: i64,
+    currency: Currency,
+}
+
+impl Money {
+    pub fn from_minor(amount: i64, currency: Currency) -> Self {
+        Money { amount, currency }
+    }
+
+    pub fn ngn(kobo: i64) -> Self {
+        Money::from_minor(kobo, Currency::Ngn)
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Money::from_minor(0, currency)
+    }
+
+    /// Whole major units, e.g. naira.
+    pub fn from_major(amount: i64, currency: Currency) -> Result<Self, MoneyError> {
+        amount
+            .checked_mul(currency.minor_per_major())
+            .map(|minor| Money::from_minor(minor, currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Parses a decimal in major units such as `"1500"` or `"1500.50"`.
+    /// Fractions finer than the currency's minor unit are rejected rather
+    /// than rounded.
+    pub fn parse_major(value: &str, currency: Currency) -> Result<Self, MoneyError> {
+        let invalid = || MoneyError::Invalid(value.to_string());
+        let value = value.trim();
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+        let places = currency.minor_digits() as usize;
+        if whole.is_empty()
+            || fraction.len() > places
+            || !whole
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let whole: i64 = whole.parse().map_err(|_| MoneyError::Overflow)?;
+        let fraction: i64 = format!("{:0<places$}", fraction).parse().unwrap_or(0);
+        let minor = whole
+            .checked_mul(currency.minor_per_major())
+            .and_then(|m| m.checked_add(fraction))
+            .ok_or(MoneyError::Overflow)?;
+
+        Ok(Money::from_minor(
+            if negative { -minor } else { minor },
+            currency,
+        ))
+    }
+
+    pub fn minor(&self) -> i64 {
+        self.amount
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.amount > 0
+    }
+
+    /// Major units as a decimal string with every minor digit, e.g.
+    /// `"1500.50"`.
+    pub fn to_major_string(&self) -> String {
+        let per_major = self.currency.minor_per_major().unsigned_abs();
+        let abs = self.amount.unsigned_abs();
+        format!(
+            "{}{}.{:0width$}",
+            if self.amount < 0 { "-" } else { "" },
+            abs / per_major,
+            abs % per_major,
+            width = self.currency.minor_digits() as usize
+        )
+    }
+
+    /// Minor units for providers that take a `u32`, such as PayU VAS.
+    pub fn minor_u32(&self) -> Result<u32, MoneyError> {
+        u32::try_from(self.amount).map_err(|_| MoneyError::OutOfRange(self.to_string()))
+    }
+
+    pub fn expect_currency(&self, currency: Currency) -> Result<(), MoneyError> {
+        if self.currency != currency {
+            return Err(MoneyError::CurrencyMismatch(self.currency, currency));
+        }
+        Ok(())
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        other.expect_currency(self.currency)?;
+        self.amount
+            .checked_add(other.amount)
+            .map(|amount| Money::from_minor(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        other.expect_currency(self.currency)?;
+        self.amount
+            .checked_sub(other.amount)
+            .map(|amount| Money::from_minor(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_mul(self, factor: i64) -> Result<Money, MoneyError> {
+        self.amount
+            .checked_mul(factor)
+            .map(|amount| Money::from_minor(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.currency, self.to_major_string())
+    }
+}
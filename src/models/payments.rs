@@ -1,7 +1,15 @@
+use crate::models::money::Money;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PaymentRequest {
     pub biller_id: String,
-    pub amount: f64,
+    pub amount: Money,
+}
+
+/// Body sent to Quickteller, which takes amounts in kobo.
+#[derive(Debug, Serialize)]
+pub struct QuicktellerPaymentRequest {
+    pub biller_id: String,
+    pub amount: i64,
 }
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Result code PayU returns for a successful VAS request
+pub const VAS_SUCCESS_CODE: &str = "00";
+
+/// Result codes PayU returns while MultiChoice is still processing a request
+pub const VAS_PENDING_CODES: &[&str] = &["01", "09"];
+
+/// Requery statuses reported by `/transactions/single/{reference}`
+pub const REQUERY_SUCCESS: i32 = 1;
+pub const REQUERY_PENDING: i32 = -1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum VasOutcome {
+    Success,
+    Pending,
+    Failure,
+}
+
+impl VasOutcome {
+    pub fn from_result_code(code: &str) -> Self {
+        match code.trim() {
+            VAS_SUCCESS_CODE => VasOutcome::Success,
+            code if VAS_PENDING_CODES.contains(&code) => VasOutcome::Pending,
+            _ => VasOutcome::Failure,
+        }
+    }
+
+    pub fn from_requery_status(status: i32) -> Self {
+        match status {
+            REQUERY_SUCCESS => VasOutcome::Success,
+            REQUERY_PENDING => VasOutcome::Pending,
+            _ => VasOutcome::Failure,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum VasTransactionType {
+    AccountLookup,
+    Single,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename = "PayUVasRequest")]
+pub struct PayUVasRequest {
+    #[serde(rename = "@Ver")]
+    pub version: String,
+    #[serde(rename = "MerchantId")]
+    pub merchant_id: String,
+    #[serde(rename = "MerchantReference")]
+    pub merchant_reference: String,
+    #[serde(rename = "TransactionType")]
+    pub transaction_type: VasTransactionType,
+    #[serde(rename = "VasId")]
+    pub vas_id: String,
+    #[serde(rename = "CountryCode")]
+    pub country_code: String,
+    #[serde(rename = "AmountInCents", skip_serializing_if = "Option::is_none")]
+    pub amount_in_cents: Option<u32>,
+    #[serde(rename = "CustomerId")]
+    pub customer_id: String,
+    #[serde(rename = "CustomFields", skip_serializing_if = "Option::is_none")]
+    pub custom_fields: Option<VasCustomFields>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VasCustomFields {
+    #[serde(rename = "Customfield", default)]
+    pub fields: Vec<VasCustomField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VasCustomField {
+    #[serde(rename = "@Key")]
+    pub key: String,
+    #[serde(rename = "@Value")]
+    pub value: String,
+}
+
+impl VasCustomFields {
+    pub fn single(key: &str, value: &str) -> Self {
+        VasCustomFields {
+            fields: vec![VasCustomField {
+                key: key.to_string(),
+                value: value.to_string(),
+            }],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayUVasResponse {
+    #[serde(rename = "ResultCode")]
+    pub result_code: String,
+    #[serde(rename = "ResultMessage", default)]
+    pub result_message: String,
+    #[serde(rename = "MerchantReference", default)]
+    pub merchant_reference: Option<String>,
+    #[serde(rename = "PayUVasReference", alias = "PayUReference", default)]
+    pub payu_reference: Option<String>,
+    #[serde(rename = "ReceiptNumber", default)]
+    pub receipt_number: Option<String>,
+    #[serde(rename = "CustomFields", default)]
+    pub custom_fields: Option<VasCustomFields>,
+}
+
+impl PayUVasResponse {
+    pub fn outcome(&self) -> VasOutcome {
+        VasOutcome::from_result_code(&self.result_code)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.outcome() == VasOutcome::Success
+    }
+
+    pub fn field(&self, key: &str) -> Option<&str> {
+        self.custom_fields
+            .as_ref()?
+            .fields
+            .iter()
+            .find(|f| f.key == key)
+            .map(|f| f.value.as_str())
+    }
+
+    pub fn fields(&self) -> HashMap<String, String> {
+        self.custom_fields
+            .iter()
+            .flat_map(|c| &c.fields)
+            .map(|f| (f.key.clone(), f.value.clone()))
+            .collect()
+    }
+}
+
+/// Entry of `GET /transactions/single/{reference}`, which is JSON unlike the
+/// rest of the VAS API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VasRequeryItem {
+    pub merchantreference: String,
+    pub smartcard: String,
+    pub status: i32,
+    pub basketid: String,
+}
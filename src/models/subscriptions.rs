@@ -0,0 +1,225 @@
+use chrono::{DateTime, Datelike, Months, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// When a subscription is renewed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Schedule {
+    /// Every month on `day` (1-31), or on the last day of shorter months
+    Monthly { day: u32 },
+    /// `days_before` the due date MultiChoice returns on lookup
+    BeforeDueDate { days_before: u32 },
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Schedule::Monthly { day } if !(1..=31).contains(&day) => {
+                Err(format!("day must be between 1 and 31, got {}", day))
+            }
+            Schedule::BeforeDueDate { days_before } if days_before > 28 => Err(format!(
+                "days_before must be at most 28, got {}",
+                days_before
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// First midnight (UTC) on day `day` of a month that is strictly after
+/// `after`. Months without that day use their last day.
+pub fn next_monthly_run(day: u32, after: DateTime<Utc>) -> DateTime<Utc> {
+    let first_of_month = NaiveDate::from_ymd_opt(after.year(), after.month(), 1).unwrap();
+    (0..=2)
+        .filter_map(|offset| first_of_month.checked_add_months(Months::new(offset)))
+        .map(|month| {
+            let last_day = month
+                .checked_add_months(Months::new(1))
+                .and_then(|next| next.pred_opt())
+                .map_or(28, |d| d.day());
+            month.with_day(day.min(last_day)).unwrap()
+        })
+        .map(|date| Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))
+        .find(|run| *run > after)
+        .unwrap()
+}
+
+/// Parses a due date as MultiChoice formats it in lookup custom fields.
+pub fn parse_due_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    let date = value.split(['T', ' ']).next().unwrap_or(value);
+    ["%Y-%m-%d", "%d/%m/%Y", "%d-%m-%Y", "%Y/%m/%d"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+}
+
+/// How a renewal is paid for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FundingSource {
+    /// Billed to the merchant's prefunded PayU account; confirmed right away
+    Prepaid,
+    /// A Bluecode payment is requested through a DSTV checkout and the
+    /// renewal is confirmed once the customer approves it
+    Bluecode,
+}
+
+impl FundingSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FundingSource::Prepaid => "PREPAID",
+            FundingSource::Bluecode => "BLUECODE",
+        }
+    }
+}
+
+impl FromStr for FundingSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PREPAID" => Ok(FundingSource::Prepaid),
+            "BLUECODE" => Ok(FundingSource::Bluecode),
+            other => Err(format!("Unknown funding source: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for FundingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `Active` ⇄ `Paused`, either of them → `Cancelled`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SubscriptionStatus {
+    Active,
+    Paused,
+    Cancelled,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::Active => "ACTIVE",
+            SubscriptionStatus::Paused => "PAUSED",
+            SubscriptionStatus::Cancelled => "CANCELLED",
+        }
+    }
+}
+
+impl FromStr for SubscriptionStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ACTIVE" => Ok(SubscriptionStatus::Active),
+            "PAUSED" => Ok(SubscriptionStatus::Paused),
+            "CANCELLED" => Ok(SubscriptionStatus::Cancelled),
+            other => Err(format!("Unknown subscription status: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Outcome of one attempt to renew a subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RunStatus {
+    /// MultiChoice confirmed the renewal
+    Confirmed,
+    /// Bluecode payment requested, waiting for the customer and the checkout
+    PaymentRequested,
+    /// MultiChoice has not settled the renewal yet
+    Pending,
+    /// Failed, another attempt is scheduled within the retry window
+    Retrying,
+    /// Failed for good; the next renewal is scheduled
+    Failed,
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Confirmed => "CONFIRMED",
+            RunStatus::PaymentRequested => "PAYMENT_REQUESTED",
+            RunStatus::Pending => "PENDING",
+            RunStatus::Retrying => "RETRYING",
+            RunStatus::Failed => "FAILED",
+        }
+    }
+}
+
+impl fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSubscriptionRequest {
+    pub customer_id: String,
+    pub basket_id: String,
+    pub amount: i64, // in kobo
+    /// VAS product id, `dstv-ng` when omitted
+    #[serde(default)]
+    pub product: Option<String>,
+    pub schedule: Schedule,
+    pub funding_source: FundingSource,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DstvSubscription {
+    pub id: Uuid,
+    pub customer_id: String,
+    pub basket_id: String,
+    pub amount: i64,
+    pub vas_product: String,
+    pub account_name: Option<String>,
+    pub schedule: Json<Schedule>,
+    pub funding_source: String,
+    pub status: String,
+    pub cycle_scheduled_for: DateTime<Utc>,
+    pub next_run_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscriptionRun {
+    pub id: i64,
+    /// When the renewal this attempt belongs to was due
+    pub scheduled_for: DateTime<Utc>,
+    pub status: String,
+    pub transaction_id: Option<i32>,
+    /// Checkout holding the Bluecode payment, for `BLUECODE` subscriptions
+    pub checkout_id: Option<Uuid>,
+    pub error_code: Option<String>,
+    pub error: Option<String>,
+    pub receipt_number: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A subscription with its latest runs, newest first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscriptionDetails {
+    pub subscription: DstvSubscription,
+    pub runs: Vec<SubscriptionRun>,
+    /// Sent as `X-Subscription-Token` to manage the subscription. Only
+    /// returned when it is created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manage_token: Option<String>,
+}
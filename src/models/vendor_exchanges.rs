@@ -0,0 +1,35 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+pub struct VendorExchange {
+    pub id: i64,
+    pub provider: String,
+    pub operation: String,
+    pub method: String,
+    pub url: String,
+    pub request_body: Option<String>,
+    pub response_body: Option<String>,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub latency_ms: i64,
+    pub correlation_id: Option<String>,
+    pub transaction_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Filters for `GET /admin/vendor-exchanges`; all optional.
+#[derive(Debug, Default, Deserialize)]
+pub struct VendorExchangeQuery {
+    pub provider: Option<String>,
+    pub operation: Option<String>,
+    pub correlation_id: Option<String>,
+    pub transaction_id: Option<i32>,
+    pub status_code: Option<i32>,
+    /// Only exchanges that failed (no response, or a non-2xx status)
+    #[serde(default)]
+    pub failed: bool,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
@@ -1 +1,84 @@
+use crate::models::airtime::{AirtimeTopup, AirtimeTopupRequest, TopupStatus};
+use crate::routes::billers::{quickteller_message, quickteller_status};
+use crate::services::airtime_topup::{load_topup, topup_airtime, TopupError};
+use crate::services::quickteller_auth::TokenManager;
+use crate::utils::admin::require_admin_key;
+use crate::utils::idempotency::idempotency;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::Extension;
+use axum::{Json, Router};
+use sqlx::PgPool;
 
+/// Airtime top-ups, mounted at `/airtime`. Airtime is bought from our
+/// Quickteller float before anyone has paid for it, so every route needs the
+/// admin key.
+pub fn airtime_routes(pool: PgPool) -> Router<PgPool> {
+    Router::new()
+        .route("/topup", post(topup_handler))
+        .route_layer(middleware::from_fn_with_state(pool.clone(), idempotency))
+        .route("/topup/{reference}", get(get_topup_handler))
+        // Outermost, so refused requests never reach the idempotency store
+        .route_layer(middleware::from_fn(require_admin_key))
+        .with_state(pool)
+}
+
+// POST /airtime/topup
+async fn topup_handler(
+    State(pool): State<PgPool>,
+    Extension(tokens): Extension<TokenManager>,
+    Json(body): Json<AirtimeTopupRequest>,
+) -> Result<(StatusCode, Json<AirtimeTopup>), (StatusCode, String)> {
+    tracing::info!(?body, "📥 Received airtime top-up request");
+
+    topup_airtime(&pool, &tokens, body)
+        .await
+        .map(topup_reply)
+        .map_err(topup_error)
+}
+
+// GET /airtime/topup/{reference}
+async fn get_topup_handler(
+    State(pool): State<PgPool>,
+    Extension(tokens): Extension<TokenManager>,
+    Path(reference): Path<String>,
+) -> Result<(StatusCode, Json<AirtimeTopup>), (StatusCode, String)> {
+    load_topup(&pool, &tokens, &reference)
+        .await
+        .map(topup_reply)
+        .map_err(topup_error)
+}
+
+/// 200 once delivered, 202 while pending and 422 when Quickteller declined
+/// the top-up. Every reply carries the references needed to dispute it.
+fn topup_reply(topup: AirtimeTopup) -> (StatusCode, Json<AirtimeTopup>) {
+    let status = match topup.status.parse() {
+        Ok(TopupStatus::Successful) => StatusCode::OK,
+        Ok(TopupStatus::Pending) => StatusCode::ACCEPTED,
+        Ok(TopupStatus::Failed) | Err(_) => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    (status, Json(topup))
+}
+
+pub(crate) fn topup_error(err: TopupError) -> (StatusCode, String) {
+    let status = match &err {
+        TopupError::InvalidPhoneNumber(_) | TopupError::InvalidAmount(_) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        TopupError::NotFound(_) => StatusCode::NOT_FOUND,
+        // A missing biller is our misconfiguration, not the client's
+        TopupError::Quickteller(api) => match quickteller_status(api) {
+            StatusCode::NOT_FOUND => StatusCode::BAD_GATEWAY,
+            status => status,
+        },
+        TopupError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    tracing::error!("❌ Airtime top-up failed: {}", err);
+    let message = match &err {
+        TopupError::Quickteller(api) => quickteller_message(api).to_string(),
+        _ => err.to_string(),
+    };
+    (status, message)
+}
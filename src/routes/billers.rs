@@ -1 +1,163 @@
+use crate::models::airtime::AirtimeTopup;
+use crate::models::billers::{
+    CustomerValidation, CustomerValidationRequest, GetBillerCategoriesResponse,
+    GetBillerPaymentItemsResponse, GetBillersByCategoryResponse,
+};
+use crate::routes::airtime::topup_error;
+use crate::services::airtime::{get_biller_payment_items, get_billers_by_category};
+use crate::services::billers::get_biller_categories;
+use crate::services::customer_validation::{validate_customer, ValidationError};
+use crate::services::quickteller_auth::TokenManager;
+use crate::services::quickteller_requery::requery_transaction;
+use crate::utils::admin::require_admin_key;
+use crate::utils::error::ApiError;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::Extension;
+use axum::{Json, Router};
+use sqlx::PgPool;
 
+/// The Quickteller biller catalog, customer validation and transaction
+/// requery, mounted at `/billers`. Requerying calls Quickteller and reveals
+/// top-ups by reference, so it needs the admin key.
+pub fn biller_routes() -> Router<PgPool> {
+    Router::new()
+        .route(
+            "/transactions/{reference}",
+            get(requery_transaction_handler),
+        )
+        .route_layer(middleware::from_fn(require_admin_key))
+        .route("/categories", get(list_categories_handler))
+        .route("/categories/{id}", get(list_billers_handler))
+        .route("/{service_id}/items", get(list_payment_items_handler))
+        .route("/{service_id}/validate", post(validate_customer_handler))
+}
+
+// GET /billers/categories
+async fn list_categories_handler(
+    State(pool): State<PgPool>,
+    Extension(tokens): Extension<TokenManager>,
+) -> Result<Json<GetBillerCategoriesResponse>, (StatusCode, String)> {
+    tokens
+        .with_token(&pool, |token| {
+            let pool = &pool;
+            async move { get_biller_categories(pool, &token).await }
+        })
+        .await
+        .map(Json)
+        .map_err(quickteller_error)
+}
+
+// GET /billers/categories/{id}
+async fn list_billers_handler(
+    State(pool): State<PgPool>,
+    Extension(tokens): Extension<TokenManager>,
+    Path(category_id): Path<u32>,
+) -> Result<Json<GetBillersByCategoryResponse>, (StatusCode, String)> {
+    tokens
+        .with_token(&pool, |token| {
+            let pool = &pool;
+            async move { get_billers_by_category(pool, category_id, &token).await }
+        })
+        .await
+        .map(Json)
+        .map_err(quickteller_error)
+}
+
+// GET /billers/{service_id}/items
+async fn list_payment_items_handler(
+    State(pool): State<PgPool>,
+    Extension(tokens): Extension<TokenManager>,
+    Path(service_id): Path<u32>,
+) -> Result<Json<GetBillerPaymentItemsResponse>, (StatusCode, String)> {
+    tokens
+        .with_token(&pool, |token| {
+            let pool = &pool;
+            async move { get_biller_payment_items(pool, &token, service_id).await }
+        })
+        .await
+        .map(Json)
+        .map_err(quickteller_error)
+}
+
+// POST /billers/{service_id}/validate
+async fn validate_customer_handler(
+    State(pool): State<PgPool>,
+    Extension(tokens): Extension<TokenManager>,
+    Path(service_id): Path<u32>,
+    Json(body): Json<CustomerValidationRequest>,
+) -> Result<Json<CustomerValidation>, (StatusCode, String)> {
+    validate_customer(&pool, &tokens, service_id, body)
+        .await
+        .map(Json)
+        .map_err(validation_error)
+}
+
+// GET /billers/transactions/{reference}
+async fn requery_transaction_handler(
+    State(pool): State<PgPool>,
+    Extension(tokens): Extension<TokenManager>,
+    Path(reference): Path<String>,
+) -> Result<Json<AirtimeTopup>, (StatusCode, String)> {
+    requery_transaction(&pool, &tokens, &reference)
+        .await
+        .map(Json)
+        .map_err(topup_error)
+}
+
+fn validation_error(err: ValidationError) -> (StatusCode, String) {
+    let status = match &err {
+        ValidationError::MissingCustomerId
+        | ValidationError::UnknownPaymentItem { .. }
+        | ValidationError::Rejected { .. }
+        | ValidationError::NotValidated { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        ValidationError::Quickteller(api) => quickteller_status(api),
+        ValidationError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    tracing::error!("❌ Customer validation failed: {}", err);
+    let message = match &err {
+        ValidationError::Quickteller(api) => quickteller_message(api).to_string(),
+        _ => err.to_string(),
+    };
+    (status, message)
+}
+
+fn quickteller_error(err: ApiError) -> (StatusCode, String) {
+    tracing::error!("❌ Quickteller catalog request failed: {}", err);
+    (
+        quickteller_status(&err),
+        quickteller_message(&err).to_string(),
+    )
+}
+
+/// What the client is told when a request failed talking to Quickteller.
+/// Upstream bodies are only logged, since they can quote our request or
+/// credentials back.
+pub(crate) fn quickteller_message(err: &ApiError) -> &'static str {
+    match quickteller_status(err) {
+        StatusCode::NOT_FOUND => "Not found at Quickteller",
+        StatusCode::GATEWAY_TIMEOUT => "Quickteller did not respond in time",
+        StatusCode::BAD_GATEWAY => "Quickteller request failed",
+        _ => "Internal server error",
+    }
+}
+
+/// HTTP status for a request that failed talking to Quickteller.
+pub(crate) fn quickteller_status(err: &ApiError) -> StatusCode {
+    match err {
+        ApiError::UpstreamStatus { status, .. } if *status == StatusCode::NOT_FOUND => {
+            StatusCode::NOT_FOUND
+        }
+        ApiError::RequestError(e) if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+        ApiError::RequestError(_)
+        | ApiError::UpstreamStatus { .. }
+        | ApiError::Unauthorized(_)
+        | ApiError::HttpError(_)
+        | ApiError::ParseError(_) => StatusCode::BAD_GATEWAY,
+        ApiError::EnvVarMissing(_) | ApiError::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
@@ -1,9 +1,30 @@
-use crate::models::bluecode::BluecodeStatusResponseWrapper;
-use axum::response::IntoResponse;
-use axum::{routing::post, Json, Router};
+use crate::models::bluecode::{
+    BluecodePaymentState, BluecodeRefund, BluecodeStatusResponseWrapper, RefundInitRequest,
+};
+use crate::services::bluecode_qr::{
+    deep_link, find_checkin_code, render_png, render_svg, QrError, QrFormat, QrOptions,
+};
+use crate::services::bluecode_webhook::{
+    check_source, claim_delivery, release_delivery, source_address, verify, WebhookConfig,
+    WebhookError, DELIVERY_HEADER,
+};
+use crate::services::checkout::advance_by_merchant_tx_id;
+use crate::services::refunds::{cancel_transaction, list_refunds, refund_transaction, RefundError};
+use crate::services::transactions::record_bluecode_callback;
+use crate::utils::admin::require_admin_key;
+use axum::body::{to_bytes, Body, Bytes};
+use axum::extract::{ConnectInfo, Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
 use serde::Serialize;
 use sqlx::PgPool;
-use tracing::info;
+use std::net::SocketAddr;
+use tracing::{error, info, warn};
+
+const MAX_CALLBACK_BYTES: usize = 64 * 1024;
 
 #[derive(Serialize)]
 struct AckResponse {
@@ -12,15 +33,293 @@ struct AckResponse {
 
 pub fn bluecode_routes(pool: PgPool) -> Router<PgPool> {
     Router::new()
-        .route("/callback", post(callback_handler))
+        .route(
+            "/callback",
+            post(callback_handler).route_layer(middleware::from_fn_with_state(
+                pool.clone(),
+                verify_bluecode_webhook,
+            )),
+        )
+        .route("/qr/{merchant_tx_id}", get(qr_image_handler))
+        .route("/qr/{merchant_tx_id}/link", get(qr_link_handler))
+        .nest("/admin", admin_routes())
         .with_state(pool)
 }
 
-pub async fn callback_handler(
-    Json(payload): Json<BluecodeStatusResponseWrapper>,
-) -> impl IntoResponse {
+#[derive(Serialize)]
+struct QrLinkResponse {
+    merchant_tx_id: String,
+    checkin_code: String,
+    deep_link: String,
+}
+
+// GET /bluecode/qr/{merchant_tx_id}?format=png|svg&size=320&ecc=M&logo=false
+async fn qr_image_handler(
+    State(pool): State<PgPool>,
+    Path(merchant_tx_id): Path<String>,
+    Query(options): Query<QrOptions>,
+) -> Result<Response, (StatusCode, String)> {
+    let checkin_code = checkin_code_or_404(&pool, &merchant_tx_id).await?;
+    let link = deep_link(&checkin_code);
+
+    let response = match options.format {
+        QrFormat::Png => (
+            [(header::CONTENT_TYPE, "image/png")],
+            render_png(&link, &options).map_err(qr_error)?,
+        )
+            .into_response(),
+        QrFormat::Svg => (
+            [(header::CONTENT_TYPE, "image/svg+xml")],
+            render_svg(&link, &options).map_err(qr_error)?,
+        )
+            .into_response(),
+    };
+
+    Ok(response)
+}
+
+// GET /bluecode/qr/{merchant_tx_id}/link
+async fn qr_link_handler(
+    State(pool): State<PgPool>,
+    Path(merchant_tx_id): Path<String>,
+) -> Result<Json<QrLinkResponse>, (StatusCode, String)> {
+    let checkin_code = checkin_code_or_404(&pool, &merchant_tx_id).await?;
+
+    Ok(Json(QrLinkResponse {
+        deep_link: deep_link(&checkin_code),
+        merchant_tx_id,
+        checkin_code,
+    }))
+}
+
+async fn checkin_code_or_404(
+    pool: &PgPool,
+    merchant_tx_id: &str,
+) -> Result<String, (StatusCode, String)> {
+    match find_checkin_code(pool, merchant_tx_id).await {
+        Ok(Some(code)) => Ok(code),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("No registered payment {}", merchant_tx_id),
+        )),
+        Err(err) => {
+            error!(
+                "❌ Failed to load checkin code for {}: {}",
+                merchant_tx_id, err
+            );
+            Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+        }
+    }
+}
+
+fn qr_error(err: QrError) -> (StatusCode, String) {
+    let status = match &err {
+        QrError::InvalidSize | QrError::InvalidEcLevel(_) => StatusCode::BAD_REQUEST,
+        QrError::Logo(_) | QrError::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error!("❌ QR rendering failed: {}", err);
+    (status, err.to_string())
+}
+
+fn admin_routes() -> Router<PgPool> {
+    Router::new()
+        .route(
+            "/payments/{merchant_tx_id}/cancel",
+            post(cancel_payment_handler),
+        )
+        .route(
+            "/payments/{merchant_tx_id}/refunds",
+            post(refund_payment_handler).get(list_refunds_handler),
+        )
+        .route_layer(middleware::from_fn(require_admin_key))
+}
+
+/// Rejects callbacks that do not come from Bluecode: disallowed source,
+/// bad signature, stale timestamp or an already-processed delivery.
+pub async fn verify_bluecode_webhook(
+    State(pool): State<PgPool>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = WebhookConfig::from_env();
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip());
+    let (parts, body) = request.into_parts();
+
+    let source = source_address(&config, &parts.headers, peer);
+    if let Err(err) = check_source(&config, source) {
+        return reject(err);
+    }
+
+    let bytes = match to_bytes(body, MAX_CALLBACK_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            warn!("⚠️ Could not read Bluecode callback body: {}", err);
+            return ack(StatusCode::BAD_REQUEST, "invalid_payload");
+        }
+    };
+
+    let replay_key = match verify(
+        &config,
+        &parts.headers,
+        &bytes,
+        chrono::Utc::now().timestamp(),
+    ) {
+        Ok(key) => key,
+        Err(err) => return reject(err),
+    };
+    let delivery_id = parts
+        .headers
+        .get(DELIVERY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or(&replay_key)
+        .to_string();
+
+    match claim_delivery(&pool, &replay_key, config.tolerance_secs).await {
+        Ok(true) => {}
+        Ok(false) => return reject(WebhookError::Replayed(delivery_id)),
+        Err(err) => {
+            error!(
+                "❌ Failed to claim Bluecode delivery {}: {}",
+                delivery_id, err
+            );
+            return ack(StatusCode::INTERNAL_SERVER_ERROR, "error");
+        }
+    }
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+
+    if !response.status().is_success() {
+        if let Err(err) = release_delivery(&pool, &replay_key).await {
+            error!(
+                "❌ Failed to release Bluecode delivery {}: {}",
+                delivery_id, err
+            );
+        }
+    }
+
+    response
+}
+
+fn reject(err: WebhookError) -> Response {
+    warn!("🚫 Rejected Bluecode callback: {}", err);
+    ack(StatusCode::UNAUTHORIZED, "unauthorized")
+}
+
+/// Bluecode retries a callback until it receives a 2xx, so anything we could
+/// not store (unknown transaction, DB failure) is answered with an error status.
+pub async fn callback_handler(State(pool): State<PgPool>, body: Bytes) -> Response {
+    let raw: serde_json::Value = match serde_json::from_slice(&body) {
+        Ok(raw) => raw,
+        Err(err) => {
+            warn!("⚠️ Bluecode callback is not valid JSON: {}", err);
+            return ack(StatusCode::BAD_REQUEST, "invalid_payload");
+        }
+    };
+
+    let payload: BluecodeStatusResponseWrapper = match serde_json::from_value(raw.clone()) {
+        Ok(payload) => payload,
+        Err(err) => {
+            warn!("⚠️ Unexpected Bluecode callback shape: {}", err);
+            return ack(StatusCode::BAD_REQUEST, "invalid_payload");
+        }
+    };
+
     info!("📬 Received Bluecode callback: {:?}", payload);
 
-    // TODO: save status to DB, update transaction, etc.
-    Json(AckResponse { status: "received" })
+    let merchant_tx_id = &payload.payment.merchant_tx_id;
+    if let BluecodePaymentState::Unknown(raw) = &payload.payment.state {
+        warn!(
+            "❓ Bluecode callback for {} has unknown state {}, qr_status left unchanged",
+            merchant_tx_id, raw
+        );
+    }
+
+    match record_bluecode_callback(&pool, merchant_tx_id, &payload.payment.state, &raw).await {
+        Ok(Some(id)) => {
+            info!(
+                "✅ Transaction {} ({}) moved to qr_status {}",
+                id, merchant_tx_id, payload.payment.state
+            );
+            tokio::spawn(advance_by_merchant_tx_id(pool, merchant_tx_id.clone()));
+            ack(StatusCode::OK, "received")
+        }
+        Ok(None) => {
+            warn!(
+                "❓ Bluecode callback for unknown transaction {}",
+                merchant_tx_id
+            );
+            ack(StatusCode::NOT_FOUND, "unknown_transaction")
+        }
+        Err(err) => {
+            error!(
+                "❌ Failed to store Bluecode callback for {}: {}",
+                merchant_tx_id, err
+            );
+            ack(StatusCode::INTERNAL_SERVER_ERROR, "error")
+        }
+    }
+}
+
+fn ack(status: StatusCode, message: &'static str) -> Response {
+    (status, Json(AckResponse { status: message })).into_response()
+}
+
+// POST /bluecode/admin/payments/{merchant_tx_id}/cancel
+async fn cancel_payment_handler(
+    State(pool): State<PgPool>,
+    Path(merchant_tx_id): Path<String>,
+) -> Response {
+    info!("🛑 Cancelling Bluecode payment {}", merchant_tx_id);
+
+    match cancel_transaction(&pool, &merchant_tx_id).await {
+        Ok(response) => Json(response).into_response(),
+        Err(err) => refund_error(err).into_response(),
+    }
+}
+
+// POST /bluecode/admin/payments/{merchant_tx_id}/refunds
+async fn refund_payment_handler(
+    State(pool): State<PgPool>,
+    Path(merchant_tx_id): Path<String>,
+    Json(body): Json<RefundInitRequest>,
+) -> Result<Json<BluecodeRefund>, (StatusCode, String)> {
+    info!(
+        "💸 Refunding Bluecode payment {}: {:?}",
+        merchant_tx_id, body
+    );
+
+    refund_transaction(&pool, &merchant_tx_id, body.amount, body.reason)
+        .await
+        .map(Json)
+        .map_err(refund_error)
+}
+
+// GET /bluecode/admin/payments/{merchant_tx_id}/refunds
+async fn list_refunds_handler(
+    State(pool): State<PgPool>,
+    Path(merchant_tx_id): Path<String>,
+) -> Result<Json<Vec<BluecodeRefund>>, (StatusCode, String)> {
+    list_refunds(&pool, &merchant_tx_id)
+        .await
+        .map(Json)
+        .map_err(|err| refund_error(RefundError::Database(err)))
+}
+
+fn refund_error(err: RefundError) -> (StatusCode, String) {
+    let status = match &err {
+        RefundError::NotFound(_) => StatusCode::NOT_FOUND,
+        RefundError::InvalidState { .. } => StatusCode::CONFLICT,
+        RefundError::InvalidAmount | RefundError::ExceedsRefundable { .. } => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        RefundError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        RefundError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error!("❌ Bluecode admin operation failed: {}", err);
+    (status, err.to_string())
 }
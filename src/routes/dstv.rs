@@ -1,134 +1,363 @@
 // src/routes/dstv.rs
-use crate::models::bluecode::{
-    BluecodeRegisterRequest, BluecodeRegisterResponse, PaymentInitRequest,
-};
+use crate::config::{vas_products, ConfigError, VasProduct, DEFAULT_VAS_PRODUCT};
+use crate::models::bluecode::{BluecodePaymentState, PaymentInitRequest};
 use crate::models::bluecode::{BluecodeStatusResponse, BluecodeStatusResponseWrapper};
-use crate::models::dstv::{DstvConfirmPaymentRequest, DstvLookupRequest, DstvLookupResponse};
-use crate::services::bluecode::{initiate_qr_payment, requery_transaction};
-use crate::services::dstv::{confirm_dstv_payment, lookup_dstv_account, retry_dstv_confirmation};
-use axum::response::IntoResponse;
+use crate::models::bulk_renewals::{BulkRenewalQuery, BulkRenewalReport};
+use crate::models::catalog::{DstvProduct, ProductQuery};
+use crate::models::checkout::{DstvCheckout, StartCheckoutRequest};
+use crate::models::dstv::{DstvConfirmPaymentRequest, DstvLookupRequest};
+use crate::models::money::Money;
+use crate::routes::subscriptions::subscription_routes;
+use crate::services::bluecode::{
+    bluecode_currency, initiate_qr_payment, register_request, requery_transaction,
+};
+use crate::services::bluecode_qr::record_registration;
+use crate::services::bulk_renewals::{
+    approve_batch, create_batch, load_report, results_csv, BulkRenewalError,
+};
+use crate::services::catalog::{list_products, refresh_catalog, CatalogError};
+use crate::services::checkout::{load_checkout, start_checkout, CheckoutError};
+use crate::services::dstv::{
+    confirm_dstv_payment, confirmation_reply, lookup_dstv_account, lookup_reply,
+    retry_dstv_confirmation,
+};
+use crate::services::lookup_cache::{clear_lookup_cache, lookup_cache_stats, LookupCacheStats};
+use crate::services::references::{issue_reference, ReferenceProvider, ReferencePurpose};
+use crate::utils::admin::require_admin_key;
+use crate::utils::idempotency::idempotency;
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{Json, Router};
-use serde::Serialize;
 use sqlx::PgPool;
-use std::env;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize)]
-struct DstvConfirmPaymentResponse {
-    success: bool,
-    raw_xml: Option<String>,
-    message: Option<String>,
-}
-
 pub fn dstv_routes(pool: PgPool) -> Router<PgPool> {
     Router::new()
-        .route("/lookup", post(lookup_handler))
+        // Mutating routes honour the Idempotency-Key header
         .route("/initiate-payment", post(initiate_payment))
-        .route("/requery/{merchant_tx_id}", get(requery_handler))
         .route("/confirm-payment", post(confirm_payment_handler))
         .route("/retry-confirm", post(retry_dstv_confirmation))
+        .route("/checkout", post(start_checkout_handler))
+        .route_layer(middleware::from_fn_with_state(pool.clone(), idempotency))
+        .route("/lookup", post(lookup_handler))
+        .route("/requery/{merchant_tx_id}", get(requery_handler))
+        .route("/checkout/{id}", get(get_checkout_handler))
+        .route("/products", get(list_products_handler))
+        .route("/vas-products", get(list_vas_products_handler))
+        .nest("/subscriptions", subscription_routes(pool.clone()))
+        .nest("/admin", admin_routes())
         .with_state(pool)
 }
 
+fn admin_routes() -> Router<PgPool> {
+    Router::new()
+        .route("/products/refresh", post(refresh_products_handler))
+        .route("/lookup-cache", get(lookup_cache_stats_handler))
+        .route("/lookup-cache/clear", post(clear_lookup_cache_handler))
+        .route("/bulk-renewals", post(create_bulk_renewal_handler))
+        .route("/bulk-renewals/{id}", get(get_bulk_renewal_handler))
+        .route(
+            "/bulk-renewals/{id}/approve",
+            post(approve_bulk_renewal_handler),
+        )
+        .route(
+            "/bulk-renewals/{id}/results.csv",
+            get(bulk_renewal_results_handler),
+        )
+        .route_layer(middleware::from_fn(require_admin_key))
+}
+
+// GET /dstv/products?kind=BOUQUET|ADDON&product=gotv-ng
+async fn list_products_handler(
+    State(pool): State<PgPool>,
+    Query(query): Query<ProductQuery>,
+) -> Result<Json<Vec<DstvProduct>>, (StatusCode, String)> {
+    let products = list_products(&pool).await.map_err(|err| {
+        tracing::error!("❌ Failed to load DSTV catalog: {}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    })?;
+
+    let vas_product = query.product.as_deref().unwrap_or(DEFAULT_VAS_PRODUCT);
+    Ok(Json(
+        products
+            .iter()
+            .filter(|p| p.vas_product == vas_product)
+            .filter(|p| query.kind.is_none_or(|kind| p.kind == kind))
+            .cloned()
+            .collect(),
+    ))
+}
+
+// GET /dstv/vas-products
+async fn list_vas_products_handler() -> Result<Json<Vec<VasProduct>>, (StatusCode, String)> {
+    vas_products().map(Json).map_err(|err| {
+        tracing::error!("❌ Failed to load VAS products: {}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    })
+}
+
+// POST /dstv/admin/products/refresh
+async fn refresh_products_handler(
+    State(pool): State<PgPool>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    match refresh_catalog(&pool).await {
+        Ok(count) => Ok(Json(serde_json::json!({ "refreshed": count }))),
+        Err(err) => {
+            tracing::error!("❌ DSTV catalog refresh failed: {}", err);
+            let status = match err {
+                CatalogError::Source(_) => StatusCode::BAD_GATEWAY,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            Err((status, err.to_string()))
+        }
+    }
+}
+
+// GET /dstv/admin/lookup-cache
+async fn lookup_cache_stats_handler() -> Json<LookupCacheStats> {
+    Json(lookup_cache_stats())
+}
+
+// POST /dstv/admin/lookup-cache/clear
+async fn clear_lookup_cache_handler(
+    State(pool): State<PgPool>,
+) -> Result<Json<LookupCacheStats>, (StatusCode, String)> {
+    clear_lookup_cache(&pool).await.map_err(|err| {
+        tracing::error!("❌ Failed to clear DSTV lookup cache: {}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    })?;
+    Ok(Json(lookup_cache_stats()))
+}
+
+// POST /dstv/admin/bulk-renewals?product=dstv-ng&name=...
+// Body: CSV with customer_id, basket_id and amount (major units) columns
+async fn create_bulk_renewal_handler(
+    State(pool): State<PgPool>,
+    Query(query): Query<BulkRenewalQuery>,
+    body: Bytes,
+) -> Result<Json<BulkRenewalReport>, (StatusCode, String)> {
+    create_batch(&pool, query.product.as_deref(), query.name, &body)
+        .await
+        .map(Json)
+        .map_err(bulk_renewal_error)
+}
+
+// GET /dstv/admin/bulk-renewals/{id}
+async fn get_bulk_renewal_handler(
+    State(pool): State<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<BulkRenewalReport>, (StatusCode, String)> {
+    load_report(&pool, id)
+        .await
+        .map(Json)
+        .map_err(bulk_renewal_error)
+}
+
+// POST /dstv/admin/bulk-renewals/{id}/approve
+async fn approve_bulk_renewal_handler(
+    State(pool): State<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<BulkRenewalReport>), (StatusCode, String)> {
+    approve_batch(&pool, id)
+        .await
+        .map(|report| (StatusCode::ACCEPTED, Json(report)))
+        .map_err(bulk_renewal_error)
+}
+
+// GET /dstv/admin/bulk-renewals/{id}/results.csv
+async fn bulk_renewal_results_handler(
+    State(pool): State<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let csv = results_csv(&pool, id).await.map_err(bulk_renewal_error)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"bulk-renewal-{}.csv\"", id),
+            ),
+        ],
+        csv,
+    ))
+}
+
+fn bulk_renewal_error(err: BulkRenewalError) -> (StatusCode, String) {
+    let status = match &err {
+        BulkRenewalError::NotFound(_) => StatusCode::NOT_FOUND,
+        BulkRenewalError::InvalidState { .. } | BulkRenewalError::NothingToRenew(_) => {
+            StatusCode::CONFLICT
+        }
+        BulkRenewalError::Csv(_)
+        | BulkRenewalError::Empty
+        | BulkRenewalError::TooManyRows(..)
+        | BulkRenewalError::Config(ConfigError::UnknownProduct(_)) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        BulkRenewalError::Config(_) | BulkRenewalError::Database(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+    tracing::error!("❌ DSTV bulk renewal failed: {}", err);
+    (status, err.to_string())
+}
+
+// POST /dstv/checkout
+async fn start_checkout_handler(
+    State(pool): State<PgPool>,
+    Json(body): Json<StartCheckoutRequest>,
+) -> Result<Json<DstvCheckout>, (StatusCode, String)> {
+    tracing::info!(?body, "🛒 Received DSTV checkout request");
+
+    start_checkout(&pool, body)
+        .await
+        .map(Json)
+        .map_err(checkout_error)
+}
+
+// GET /dstv/checkout/{id}
+async fn get_checkout_handler(
+    State(pool): State<PgPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DstvCheckout>, (StatusCode, String)> {
+    load_checkout(&pool, id)
+        .await
+        .map(Json)
+        .map_err(checkout_error)
+}
+
+fn checkout_error(err: CheckoutError) -> (StatusCode, String) {
+    let status = match &err {
+        CheckoutError::NotFound(_) => StatusCode::NOT_FOUND,
+        CheckoutError::InvalidAmount(_) | CheckoutError::Lookup(_) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        CheckoutError::Catalog(CatalogError::UnknownBasket(_))
+        | CheckoutError::Catalog(CatalogError::PriceMismatch { .. }) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        CheckoutError::Config(ConfigError::UnknownProduct(_)) | CheckoutError::Money(_) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        CheckoutError::Catalog(_)
+        | CheckoutError::Config(_)
+        | CheckoutError::UnknownStep(_)
+        | CheckoutError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    tracing::error!("❌ DSTV checkout failed: {}", err);
+    (status, err.to_string())
+}
+
+/// Confirms a paid basket with MultiChoice. The status carries the outcome,
+/// as described on [`confirmation_reply`]: 200 confirmed, 202 pending, 422
+/// refused, 502 unreachable. This used to be 200 with `success: false` for
+/// every failure, so clients must not treat non-2xx as a transport error.
 pub async fn confirm_payment_handler(
+    State(pool): State<PgPool>,
     Json(body): Json<DstvConfirmPaymentRequest>,
 ) -> impl IntoResponse {
     tracing::info!(?body, "📥 Received DSTV confirm-payment request");
-    tracing::info!("🛰 Confirming with: {:?}", body);
 
-    match confirm_dstv_payment(
-        body.merchant_reference.clone(),
-        body.customer_id.clone(),
-        body.basket_id.clone(),
+    let result = confirm_dstv_payment(
+        &pool,
+        body.product.as_deref(),
+        body.merchant_reference,
+        body.customer_id,
+        body.basket_id,
         body.amount,
     )
-    .await
-    {
-        Ok(xml_response) => {
-            tracing::debug!("Raw XML body: {}", xml_response);
-
-            tracing::info!("✅ DSTV confirmation success");
-
-            tracing::info!("📄 Raw XML response:\n{}", xml_response);
-            tracing::info!(
-                "📄 Raw XML response ({} bytes):\n{}",
-                xml_response.len(),
-                xml_response
-            );
-
-            axum::Json(DstvConfirmPaymentResponse {
-                success: true,
-                raw_xml: Some(xml_response),
-                message: Some("Payment confirmed successfully".to_string()),
-            })
-            .into_response()
+    .await;
+
+    confirmation_reply(result)
+}
+
+async fn initiate_payment(
+    State(pool): State<PgPool>,
+    Json(payload): Json<PaymentInitRequest>,
+) -> impl IntoResponse {
+    let reference = match pool.acquire().await {
+        Ok(mut conn) => {
+            issue_reference(
+                &mut conn,
+                ReferenceProvider::Bluecode,
+                ReferencePurpose::Registration,
+                None,
+            )
+            .await
         }
+        Err(err) => Err(err),
+    };
+    let merchant_tx_id = match reference {
+        Ok(reference) => reference,
         Err(err) => {
-            tracing::error!(?err, "❌ Failed to confirm DSTV payment");
-            axum::Json(DstvConfirmPaymentResponse {
-                success: false,
-                raw_xml: None,
-                message: Some("DSTV payment confirmation failed".to_string()),
-            })
-            .into_response()
+            tracing::error!("❌ Failed to issue a Bluecode reference: {}", err);
+            return registration_failed(StatusCode::INTERNAL_SERVER_ERROR);
         }
-    }
-}
-
-async fn initiate_payment(Json(payload): Json<PaymentInitRequest>) -> impl IntoResponse {
-    let merchant_tx_id = format!("TXN-{}", Uuid::new_v4());
+    };
 
-    let req = BluecodeRegisterRequest {
+    let req = register_request(
         merchant_tx_id,
-        branch_ext_id: env::var("BLUECODE_BRANCH_EXT_ID").unwrap_or_default(),
-        scheme: env::var("BLUECODE_SCHEME").unwrap_or("blue_code".into()),
-        requested_amount: payload.amount,
-        currency: env::var("BLUECODE_CURRENCY").unwrap_or("NGN".into()),
-        terminal: env::var("BLUECODE_TERMINAL").unwrap_or("POS001".into()),
-        source: env::var("BLUECODE_SOURCE").unwrap_or("web".into()),
-        merchant_callback_url: env::var("BLUECODE_CALLBACK_URL").unwrap_or_default(),
-        return_url_failure: env::var("BLUECODE_REDIRECT_URL").unwrap_or_default(),
-        return_url_success: env::var("BLUECODE_SUCESS_URL").unwrap_or_default(),
-        return_url_cancel: env::var("BLUECODE_CANCEL_URL").unwrap_or_default(),
-    };
+        Money::from_minor(payload.amount, bluecode_currency()),
+    );
 
-    match initiate_qr_payment(req).await {
-        Ok(response) => Json(response).into_response(),
-        Err(_) => Json(BluecodeRegisterResponse {
-            merchant_tx_id: "".into(),
-            checkin_code: "".into(),
-            state: "FAILED".into(),
-        })
-        .into_response(),
+    match initiate_qr_payment(&pool, req.clone()).await {
+        Ok(response) => {
+            if let Err(err) = record_registration(&pool, &req, &response).await {
+                tracing::error!(
+                    "❌ Failed to store checkin code for {}: {}",
+                    response.merchant_tx_id,
+                    err
+                );
+            }
+            Json(response).into_response()
+        }
+        // Stored under the Idempotency-Key like any other reply; registering
+        // again takes a new key
+        Err(_) => registration_failed(StatusCode::BAD_GATEWAY),
     }
 }
 
-async fn lookup_handler(Json(payload): Json<DstvLookupRequest>) -> impl IntoResponse {
-    match lookup_dstv_account(payload).await {
-        Ok(data) => Json(data).into_response(),
-        Err(_) => Json(DstvLookupResponse {
-            account_name: None,
-            customer_id: None,
-            message: "Lookup failed".to_string(),
-            success: false,
-            custom_fields: None,
-        })
-        .into_response(),
-    }
+/// Answer when no payment could be registered. Its state has always been
+/// `"FAILED"`, our own marker rather than Bluecode's `FAILURE`, and clients
+/// rely on it.
+fn registration_failed(status: StatusCode) -> Response {
+    let body = serde_json::json!({
+        "merchant_tx_id": "",
+        "checkin_code": "",
+        "state": "FAILED",
+    });
+    (status, Json(body)).into_response()
+}
+
+async fn lookup_handler(
+    State(pool): State<PgPool>,
+    Json(payload): Json<DstvLookupRequest>,
+) -> impl IntoResponse {
+    lookup_reply(lookup_dstv_account(&pool, payload).await)
 }
 
 pub async fn requery_handler(
+    State(pool): State<PgPool>,
     axum::extract::Path(merchant_tx_id): axum::extract::Path<String>,
 ) -> impl IntoResponse {
-    match requery_transaction(merchant_tx_id).await {
-        Ok(response) => Json(response).into_response(),
+    match requery_transaction(&pool, merchant_tx_id).await {
+        Ok(response) => {
+            if let BluecodePaymentState::Unknown(raw) = &response.payment.state {
+                tracing::warn!(
+                    "❓ Bluecode reported unknown state {} for {}",
+                    raw,
+                    response.payment.merchant_tx_id
+                );
+            }
+            Json(response).into_response()
+        }
         Err(_) => Json(BluecodeStatusResponseWrapper {
             result: "ERROR".into(),
-            payment: BluecodeStatusResponse {
-                state: "UNKNOWN".into(),
-                merchant_tx_id: "".into(),
-            },
+            payment: BluecodeStatusResponse::default(),
         })
         .into_response(),
     }
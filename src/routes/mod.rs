@@ -3,4 +3,6 @@ pub mod billers;
 pub mod bluecode;
 pub mod dstv;
 pub mod payments;
+pub mod subscriptions;
 pub mod transactions;
+pub mod vendor_exchanges;
@@ -0,0 +1,149 @@
+use crate::config::ConfigError;
+use crate::models::subscriptions::{CreateSubscriptionRequest, FundingSource, SubscriptionDetails};
+use crate::services::catalog::CatalogError;
+use crate::services::subscriptions::{
+    authorize_subscription, cancel_subscription, create_subscription, load_subscription,
+    pause_subscription, resume_subscription, SubscriptionError,
+};
+use crate::utils::admin::has_admin_key;
+use crate::utils::idempotency::idempotency;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const SUBSCRIPTION_TOKEN_HEADER: &str = "x-subscription-token";
+
+/// Recurring DSTV renewals, mounted at `/dstv/subscriptions`. Anyone can
+/// subscribe with `BLUECODE`, which the customer approves every month;
+/// `PREPAID` renewals are paid from our float and need the admin key. A
+/// subscription is then managed with the token returned when it was created,
+/// or the admin key.
+pub fn subscription_routes(pool: PgPool) -> Router<PgPool> {
+    Router::new()
+        // Honours the Idempotency-Key header
+        .route("/", post(create_handler))
+        .route_layer(middleware::from_fn_with_state(pool, idempotency))
+        .route("/{id}", get(get_handler))
+        .route("/{id}/pause", post(pause_handler))
+        .route("/{id}/resume", post(resume_handler))
+        .route("/{id}/cancel", post(cancel_handler))
+}
+
+// POST /dstv/subscriptions
+async fn create_handler(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Json(body): Json<CreateSubscriptionRequest>,
+) -> Result<Json<SubscriptionDetails>, (StatusCode, String)> {
+    tracing::info!(?body, "📅 Received DSTV subscription request");
+
+    if body.funding_source == FundingSource::Prepaid && !has_admin_key(&headers) {
+        tracing::warn!("🚫 Rejected PREPAID subscription without the admin key");
+        return Err((
+            StatusCode::FORBIDDEN,
+            "PREPAID subscriptions need the admin key".to_string(),
+        ));
+    }
+
+    create_subscription(&pool, body)
+        .await
+        .map(Json)
+        .map_err(subscription_error)
+}
+
+// GET /dstv/subscriptions/{id}
+async fn get_handler(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SubscriptionDetails>, (StatusCode, String)> {
+    authorize(&pool, id, &headers).await?;
+    load_subscription(&pool, id)
+        .await
+        .map(Json)
+        .map_err(subscription_error)
+}
+
+// POST /dstv/subscriptions/{id}/pause
+async fn pause_handler(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SubscriptionDetails>, (StatusCode, String)> {
+    authorize(&pool, id, &headers).await?;
+    pause_subscription(&pool, id)
+        .await
+        .map(Json)
+        .map_err(subscription_error)
+}
+
+// POST /dstv/subscriptions/{id}/resume
+async fn resume_handler(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SubscriptionDetails>, (StatusCode, String)> {
+    authorize(&pool, id, &headers).await?;
+    resume_subscription(&pool, id)
+        .await
+        .map(Json)
+        .map_err(subscription_error)
+}
+
+// POST /dstv/subscriptions/{id}/cancel
+async fn cancel_handler(
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SubscriptionDetails>, (StatusCode, String)> {
+    authorize(&pool, id, &headers).await?;
+    cancel_subscription(&pool, id)
+        .await
+        .map(Json)
+        .map_err(subscription_error)
+}
+
+/// Admin callers manage any subscription; others need its token.
+async fn authorize(
+    pool: &PgPool,
+    id: Uuid,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, String)> {
+    if has_admin_key(headers) {
+        return Ok(());
+    }
+    let token = headers
+        .get(SUBSCRIPTION_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    authorize_subscription(pool, id, token)
+        .await
+        .map_err(subscription_error)
+}
+
+fn subscription_error(err: SubscriptionError) -> (StatusCode, String) {
+    let status = match &err {
+        SubscriptionError::NotFound(_) => StatusCode::NOT_FOUND,
+        SubscriptionError::Forbidden(_) => StatusCode::FORBIDDEN,
+        SubscriptionError::InvalidState { .. } => StatusCode::CONFLICT,
+        SubscriptionError::InvalidSchedule(_)
+        | SubscriptionError::InvalidAmount(_)
+        | SubscriptionError::Lookup(_)
+        | SubscriptionError::DueDateUnknown(_)
+        | SubscriptionError::Catalog(CatalogError::UnknownBasket(_))
+        | SubscriptionError::Catalog(CatalogError::PriceMismatch { .. })
+        | SubscriptionError::Config(ConfigError::UnknownProduct(_)) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        SubscriptionError::Catalog(_)
+        | SubscriptionError::Config(_)
+        | SubscriptionError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    tracing::error!("❌ DSTV subscription request failed: {}", err);
+    (status, err.to_string())
+}
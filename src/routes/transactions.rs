@@ -1,5 +1,6 @@
 use axum::{
     extract::State,
+    middleware,
     routing::{get, post},
     Json, Router,
 };
@@ -7,11 +8,14 @@ use sqlx::PgPool;
 use tracing::info;
 
 use crate::models::transactions::{NewTransaction, Transaction};
+use crate::utils::idempotency::idempotency;
 
-pub fn transaction_routes() -> Router<PgPool> {
+pub fn transaction_routes(pool: PgPool) -> Router<PgPool> {
     Router::new()
         .route("/", post(store_transaction))
+        .route_layer(middleware::from_fn_with_state(pool.clone(), idempotency))
         .route("/", get(get_transactions))
+        .with_state(pool)
 }
 
 // GET /transactions
@@ -0,0 +1,51 @@
+use crate::models::vendor_exchanges::{VendorExchange, VendorExchangeQuery};
+use crate::services::vendor_exchanges::{find_exchange, search_exchanges};
+use crate::utils::admin::require_admin_key;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::routing::get;
+use axum::{Json, Router};
+use sqlx::PgPool;
+
+/// Admin-only search over the vendor audit log, mounted at
+/// `/admin/vendor-exchanges`.
+pub fn vendor_exchange_routes() -> Router<PgPool> {
+    Router::new()
+        .route("/", get(search_handler))
+        .route("/{id}", get(get_handler))
+        .route_layer(middleware::from_fn(require_admin_key))
+}
+
+// GET /admin/vendor-exchanges?provider=&operation=&correlation_id=&transaction_id=&failed=
+async fn search_handler(
+    State(pool): State<PgPool>,
+    Query(query): Query<VendorExchangeQuery>,
+) -> Result<Json<Vec<VendorExchange>>, (StatusCode, String)> {
+    search_exchanges(&pool, &query)
+        .await
+        .map(Json)
+        .map_err(database_error)
+}
+
+// GET /admin/vendor-exchanges/{id}
+async fn get_handler(
+    State(pool): State<PgPool>,
+    Path(id): Path<i64>,
+) -> Result<Json<VendorExchange>, (StatusCode, String)> {
+    find_exchange(&pool, id)
+        .await
+        .map_err(database_error)?
+        .map(Json)
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Vendor exchange {} not found", id),
+            )
+        })
+}
+
+fn database_error(err: sqlx::Error) -> (StatusCode, String) {
+    tracing::error!("❌ Failed to search vendor exchanges: {}", err);
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
@@ -1,11 +1,16 @@
+use crate::models::airtime::{PaymentAdvice, PaymentAdviceResponse};
+use crate::models::billers::{GetBillerPaymentItemsResponse, GetBillersByCategoryResponse};
+use crate::services::vendor_exchanges::{Exchange, VendorProvider};
 use crate::utils::error::ApiError;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
+use sqlx::PgPool;
 use std::env;
 
 pub async fn get_biller_payment_items(
+    pool: &PgPool,
     access_token: &str,
     service_id: u32,
-) -> Result<serde_json::Value, ApiError> {
+) -> Result<GetBillerPaymentItemsResponse, ApiError> {
     let base_url = env::var("QUICKTELLER_BASE_URL")
         .map_err(|_| ApiError::EnvVarMissing("QUICKTELLER_BASE_URL".into()))?;
     let terminal_id = env::var("INTERSWITCH_TERMINAL_ID")
@@ -13,27 +18,35 @@ pub async fn get_biller_payment_items(
 
     let url = format!("{}/quicktellerservice/api/v5/services/options", base_url);
 
-    let client = Client::new();
-    let res = client
-        .get(&url)
-        .query(&[("serviceid", service_id.to_string())])
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("terminalId", terminal_id)
-        .header("Content-Type", "application/json")
-        .send()
+    let exchange = Exchange::start(
+        pool,
+        VendorProvider::Quickteller,
+        "payment_items",
+        "GET",
+        &format!("{}?serviceid={}", url, service_id),
+    );
+    let (status, body) = exchange
+        .send(
+            Client::new()
+                .get(&url)
+                .query(&[("serviceid", service_id.to_string())])
+                .header("Authorization", format!("Bearer {}", access_token))
+                .header("terminalId", terminal_id)
+                .header("Content-Type", "application/json"),
+        )
         .await
         .map_err(ApiError::RequestError)?;
 
-    let status = res.status();
-    let body = res.text().await.unwrap_or_default();
-
+    if status == StatusCode::UNAUTHORIZED {
+        return Err(ApiError::Unauthorized(body));
+    }
     if !status.is_success() {
         tracing::error!(
             "❌ Failed to fetch payment items. HTTP {}: {}",
             status,
             body
         );
-        return Err(ApiError::HttpError(format!("Status {}: {}", status, body)));
+        return Err(ApiError::UpstreamStatus { status, body });
     }
 
     tracing::info!("✅ Biller payment items retrieved successfully.");
@@ -44,9 +57,10 @@ pub async fn get_biller_payment_items(
 }
 
 pub async fn get_billers_by_category(
+    pool: &PgPool,
     category_id: u32,
     access_token: &str,
-) -> Result<serde_json::Value, ApiError> {
+) -> Result<GetBillersByCategoryResponse, ApiError> {
     let base_url = env::var("QUICKTELLER_BASE_URL")
         .map_err(|_| ApiError::EnvVarMissing("QUICKTELLER_BASE_URL".into()))?;
     let terminal_id = env::var("INTERSWITCH_TERMINAL_ID")
@@ -57,31 +71,141 @@ pub async fn get_billers_by_category(
         base_url, category_id
     );
 
-    let client = Client::new();
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("terminalId", terminal_id)
-        .header("Content-Type", "application/json")
-        .send()
+    let exchange = Exchange::start(pool, VendorProvider::Quickteller, "billers", "GET", &url);
+    let (status, body) = exchange
+        .send(
+            Client::new()
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", access_token))
+                .header("terminalId", terminal_id)
+                .header("Content-Type", "application/json"),
+        )
         .await
         .map_err(ApiError::RequestError)?;
 
-    let status = response.status();
-    let body = response.text().await.unwrap_or_default();
-
+    if status == StatusCode::UNAUTHORIZED {
+        return Err(ApiError::Unauthorized(body));
+    }
     if !status.is_success() {
         tracing::error!("❌ Biller fetch failed. HTTP {}: {}", status, body);
-        return Err(ApiError::HttpError(format!("Status {}: {}", status, body)));
+        return Err(ApiError::UpstreamStatus { status, body });
     }
 
-    tracing::info!(
-        "✅ Biller list retrieved for category {}: {}",
-        category_id,
-        body
-    );
+    tracing::info!("✅ Biller list retrieved for category {}", category_id);
     serde_json::from_str(&body).map_err(|e| {
         tracing::error!("❌ JSON parse error: {}", e);
         ApiError::ParseError(format!("Parse error: {}", e))
     })
 }
+
+/// Sends a bill payment advice, which makes Quickteller deliver the top-up.
+/// A refusal with a Quickteller response code comes back as `Ok`; check
+/// [`PaymentAdviceResponse::status`].
+pub async fn send_payment_advice(
+    pool: &PgPool,
+    access_token: &str,
+    advice: &PaymentAdvice,
+) -> Result<PaymentAdviceResponse, ApiError> {
+    let base_url = env::var("QUICKTELLER_BASE_URL")
+        .map_err(|_| ApiError::EnvVarMissing("QUICKTELLER_BASE_URL".into()))?;
+    let terminal_id = env::var("INTERSWITCH_TERMINAL_ID")
+        .map_err(|_| ApiError::EnvVarMissing("INTERSWITCH_TERMINAL_ID".into()))?;
+
+    let url = format!("{}/quicktellerservice/api/v5/Transactions", base_url);
+    let payload = serde_json::to_string(advice)
+        .map_err(|e| ApiError::ParseError(format!("Serialize error: {}", e)))?;
+
+    let exchange = Exchange::start(
+        pool,
+        VendorProvider::Quickteller,
+        "payment_advice",
+        "POST",
+        &url,
+    )
+    .correlation_id(&advice.request_reference)
+    .request_body(&payload);
+    let (status, body) = exchange
+        .send(
+            Client::new()
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", access_token))
+                .header("terminalId", terminal_id)
+                .header("Content-Type", "application/json")
+                .body(payload),
+        )
+        .await
+        .map_err(ApiError::RequestError)?;
+
+    advice_reply(status, body)
+}
+
+/// Current state of the top-up sent as `request_reference`.
+pub async fn query_transaction(
+    pool: &PgPool,
+    access_token: &str,
+    request_reference: &str,
+) -> Result<PaymentAdviceResponse, ApiError> {
+    let base_url = env::var("QUICKTELLER_BASE_URL")
+        .map_err(|_| ApiError::EnvVarMissing("QUICKTELLER_BASE_URL".into()))?;
+    let terminal_id = env::var("INTERSWITCH_TERMINAL_ID")
+        .map_err(|_| ApiError::EnvVarMissing("INTERSWITCH_TERMINAL_ID".into()))?;
+
+    let url = format!("{}/quicktellerservice/api/v5/Transactions", base_url);
+
+    let exchange = Exchange::start(
+        pool,
+        VendorProvider::Quickteller,
+        "transaction_query",
+        "GET",
+        &format!("{}?requestRef={}", url, request_reference),
+    )
+    .correlation_id(request_reference);
+    let (status, body) = exchange
+        .send(
+            Client::new()
+                .get(&url)
+                .query(&[("requestRef", request_reference)])
+                .header("Authorization", format!("Bearer {}", access_token))
+                .header("terminalId", terminal_id),
+        )
+        .await
+        .map_err(ApiError::RequestError)?;
+
+    // Quickteller never saw the reference
+    if status == StatusCode::NOT_FOUND {
+        return Err(ApiError::UpstreamStatus { status, body });
+    }
+    advice_reply(status, body)
+}
+
+fn advice_reply(status: StatusCode, body: String) -> Result<PaymentAdviceResponse, ApiError> {
+    if status == StatusCode::UNAUTHORIZED {
+        return Err(ApiError::Unauthorized(body));
+    }
+
+    let parsed = serde_json::from_str::<PaymentAdviceResponse>(&body);
+    match parsed {
+        Ok(reply) if status.is_success() => Ok(reply),
+        // Refusals come back as 4xx with a response code
+        Ok(reply) if status.is_client_error() && reply.response_code.is_some() => {
+            tracing::warn!(
+                "⚠️ Quickteller refused the payment advice. HTTP {}: {}",
+                status,
+                body
+            );
+            Ok(reply)
+        }
+        Err(e) if status.is_success() => {
+            tracing::error!("❌ JSON parse error: {}", e);
+            Err(ApiError::ParseError(format!("Parse error: {}", e)))
+        }
+        _ => {
+            tracing::error!(
+                "❌ Quickteller payment advice failed. HTTP {}: {}",
+                status,
+                body
+            );
+            Err(ApiError::UpstreamStatus { status, body })
+        }
+    }
+}
@@ -0,0 +1,459 @@
+//! Airtime top-ups through Quickteller.
+//!
+//! A top-up is checked against the limits of the network's payment item,
+//! recorded as a transaction and sent to Quickteller as a bill payment
+//! advice. When the advice fails in transit or comes back pending, the
+//! top-up is queried up to `AIRTIME_REQUERY_ATTEMPTS` times,
+//! `AIRTIME_REQUERY_DELAY_MS` apart. Top-ups still pending after that are
+//! queried again whenever they are fetched, and by the background job in
+//! [`crate::services::quickteller_requery`].
+
+use crate::models::airtime::{
+    AirtimeTopup, AirtimeTopupRequest, PaymentAdvice, PaymentAdviceResponse, TopupStatus,
+};
+use crate::models::billers::PaymentItem;
+use crate::models::money::Money;
+use crate::services::airtime::{get_biller_payment_items, query_transaction, send_payment_advice};
+use crate::services::quickteller_auth::TokenManager;
+use crate::services::references::{
+    issue_reference, link_transaction, ReferenceProvider, ReferencePurpose,
+};
+use crate::utils::error::ApiError;
+use chrono::{Duration as ChronoDuration, Utc};
+use reqwest::StatusCode;
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TopupError {
+    #[error("Invalid phone number: {0}")]
+    InvalidPhoneNumber(String),
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+
+    #[error("Airtime top-up {0} not found")]
+    NotFound(String),
+
+    #[error(transparent)]
+    Quickteller(#[from] ApiError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct TopupSettings {
+    pub requery_attempts: u32,
+    pub requery_delay_ms: u64,
+    /// Kobo, for payment items that do not state their own limits
+    pub min_amount: i64,
+    pub max_amount: i64,
+    /// How long a top-up whose payment advice got no answer may stay unknown
+    /// to Quickteller before it is taken as never made
+    pub lost_after_secs: i64,
+}
+
+impl TopupSettings {
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        TopupSettings {
+            requery_attempts: var("AIRTIME_REQUERY_ATTEMPTS", 3),
+            requery_delay_ms: var("AIRTIME_REQUERY_DELAY_MS", 2000),
+            min_amount: var("AIRTIME_MIN_AMOUNT", 5_000),
+            max_amount: var("AIRTIME_MAX_AMOUNT", 5_000_000),
+            lost_after_secs: var("AIRTIME_LOST_AFTER_SECS", 1800),
+        }
+    }
+}
+
+/// What Quickteller last said about a top-up.
+pub(crate) struct Outcome {
+    pub(crate) status: TopupStatus,
+    transaction_ref: Option<String>,
+    response_code: Option<String>,
+    pub(crate) message: String,
+}
+
+impl From<PaymentAdviceResponse> for Outcome {
+    fn from(reply: PaymentAdviceResponse) -> Self {
+        let status = reply.status();
+        let message = reply.response_description.unwrap_or_else(|| match status {
+            TopupStatus::Successful => "Airtime delivered".to_string(),
+            TopupStatus::Pending => "Airtime top-up is pending".to_string(),
+            TopupStatus::Failed => "Quickteller declined the airtime top-up".to_string(),
+        });
+        Outcome {
+            status,
+            transaction_ref: reply.transaction_ref,
+            response_code: reply.response_code,
+            message,
+        }
+    }
+}
+
+/// `phone_number` in the local `0XXXXXXXXXX` form Quickteller expects.
+/// `+234` and `234` prefixes are accepted.
+pub fn normalize_phone_number(phone_number: &str) -> Result<String, TopupError> {
+    let digits: String = phone_number
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '(' | ')'))
+        .collect();
+    let digits = digits.strip_prefix('+').unwrap_or(&digits);
+
+    let local = match digits.strip_prefix("234") {
+        Some(rest) if rest.len() == 10 => format!("0{}", rest),
+        _ => digits.to_string(),
+    };
+    if local.len() != 11 || !local.starts_with('0') || !local.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(TopupError::InvalidPhoneNumber(phone_number.to_string()));
+    }
+    Ok(local)
+}
+
+/// The payment item selling `amount` of airtime: a fixed-price item costing
+/// exactly `amount`, or an open one whose limits allow it.
+fn select_payment_item<'a>(
+    items: &'a [PaymentItem],
+    amount: i64,
+    settings: &TopupSettings,
+) -> Result<&'a PaymentItem, TopupError> {
+    let fixed = items
+        .iter()
+        .find(|item| item.is_amount_fixed && item.amount == Some(amount));
+    if let Some(item) = fixed {
+        return Ok(item);
+    }
+
+    let Some(open) = items.iter().find(|item| !item.is_amount_fixed) else {
+        return Err(TopupError::InvalidAmount(format!(
+            "no top-up costs {}",
+            Money::ngn(amount)
+        )));
+    };
+    let min = open.minimum_amount.unwrap_or(settings.min_amount);
+    let max = open.maximum_amount.unwrap_or(settings.max_amount);
+    if amount < min || amount > max {
+        return Err(TopupError::InvalidAmount(format!(
+            "{} is outside {} to {}",
+            Money::ngn(amount),
+            Money::ngn(min),
+            Money::ngn(max)
+        )));
+    }
+    Ok(open)
+}
+
+/// Buys airtime for `request.phone_number`. The returned top-up may still be
+/// pending; callers must check its status.
+pub async fn topup_airtime(
+    pool: &PgPool,
+    tokens: &TokenManager,
+    request: AirtimeTopupRequest,
+) -> Result<AirtimeTopup, TopupError> {
+    let settings = TopupSettings::from_env();
+    let phone_number = normalize_phone_number(&request.phone_number)?;
+    if request.amount <= 0 {
+        return Err(TopupError::InvalidAmount(format!(
+            "{} is not positive",
+            request.amount
+        )));
+    }
+
+    let service_id = request.network.service_id();
+    let items = tokens
+        .with_token(pool, |token| async move {
+            get_biller_payment_items(pool, &token, service_id).await
+        })
+        .await?;
+    let item = select_payment_item(&items.payment_items, request.amount, &settings)?;
+
+    let topup = create_topup(
+        pool,
+        &request,
+        &phone_number,
+        service_id,
+        &item.payment_code,
+    )
+    .await?;
+    tracing::info!(
+        "📱 Sending {} {} top-up for {} as {}",
+        Money::ngn(topup.amount),
+        request.network,
+        phone_number,
+        topup.request_reference
+    );
+
+    let advice = PaymentAdvice {
+        payment_code: topup.payment_code.clone(),
+        customer_id: phone_number.clone(),
+        customer_mobile: phone_number,
+        amount: topup.amount.to_string(),
+        request_reference: topup.request_reference.clone(),
+    };
+    let advice = &advice;
+    let mut outcome = match tokens
+        .with_token(pool, |token| async move {
+            send_payment_advice(pool, &token, advice).await
+        })
+        .await
+    {
+        Ok(reply) => Some(Outcome::from(reply)),
+        Err(err) => {
+            tracing::warn!("❌ Payment advice failed: {}", err);
+            tracing::warn!("🔁 Falling back to requery...");
+            None
+        }
+    };
+
+    for _ in 0..settings.requery_attempts {
+        if outcome
+            .as_ref()
+            .is_some_and(|o| o.status != TopupStatus::Pending)
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(settings.requery_delay_ms)).await;
+        // Too soon for an unknown reference to mean the advice was lost
+        if let Some(queried) = requery(pool, tokens, &topup.request_reference, false).await {
+            outcome = Some(queried);
+        }
+    }
+
+    let outcome = outcome.unwrap_or(Outcome {
+        status: TopupStatus::Pending,
+        transaction_ref: None,
+        response_code: None,
+        message: "Quickteller could not be reached; the top-up will be queried again".to_string(),
+    });
+    match outcome.status {
+        TopupStatus::Successful => {
+            tracing::info!("✅ Airtime top-up {} delivered", topup.request_reference)
+        }
+        TopupStatus::Pending => {
+            tracing::warn!("⏳ Airtime top-up {} is pending", topup.request_reference)
+        }
+        TopupStatus::Failed => tracing::error!(
+            "❌ Quickteller declined {} ({}): {}",
+            topup.request_reference,
+            outcome.response_code.as_deref().unwrap_or("-"),
+            outcome.message
+        ),
+    }
+
+    Ok(record_outcome(pool, &topup, outcome).await?)
+}
+
+/// Asks Quickteller about a top-up. Only when `lost` (see [`is_lost`]) does
+/// a reference Quickteller does not know mean the top-up was never made;
+/// otherwise the top-up stays pending.
+pub(crate) async fn query_outcome(
+    pool: &PgPool,
+    tokens: &TokenManager,
+    request_reference: &str,
+    lost: bool,
+) -> Result<Outcome, ApiError> {
+    let result = tokens
+        .with_token(pool, |token| async move {
+            query_transaction(pool, &token, request_reference).await
+        })
+        .await;
+
+    match result {
+        Ok(reply) => Ok(Outcome::from(reply)),
+        Err(ApiError::UpstreamStatus { status, .. }) if status == StatusCode::NOT_FOUND && lost => {
+            Ok(Outcome {
+                status: TopupStatus::Failed,
+                transaction_ref: None,
+                response_code: None,
+                message: "Quickteller never received the airtime top-up".to_string(),
+            })
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// [`query_outcome`], with failures logged and left for the next requery.
+pub(crate) async fn requery(
+    pool: &PgPool,
+    tokens: &TokenManager,
+    request_reference: &str,
+    lost: bool,
+) -> Option<Outcome> {
+    query_outcome(pool, tokens, request_reference, lost)
+        .await
+        .inspect_err(|err| tracing::warn!("⚠️ Requery of {} failed: {}", request_reference, err))
+        .ok()
+}
+
+/// Whether the payment advice of `topup` never got an answer and it is older
+/// than `AIRTIME_LOST_AFTER_SECS`, long enough for Quickteller to know it
+/// had it been received.
+pub(crate) fn is_lost(topup: &AirtimeTopup) -> bool {
+    let lost_after = ChronoDuration::seconds(TopupSettings::from_env().lost_after_secs);
+    topup.response_code.is_none()
+        && topup.transaction_ref.is_none()
+        && topup.created_at < Utc::now() - lost_after
+}
+
+/// Stored on its own, before Quickteller is called, so the reference and
+/// vendor exchanges are linked to the transaction.
+async fn create_topup(
+    pool: &PgPool,
+    request: &AirtimeTopupRequest,
+    phone_number: &str,
+    service_id: u32,
+    payment_code: &str,
+) -> Result<AirtimeTopup, sqlx::Error> {
+    let mut db_tx = pool.begin().await?;
+
+    let reference = issue_reference(
+        &mut db_tx,
+        ReferenceProvider::Quickteller,
+        ReferencePurpose::AirtimeTopup,
+        None,
+    )
+    .await?;
+
+    // Airtime is paid from our Quickteller float, so the poller skips it
+    let transaction_id = sqlx::query!(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ($1, $2, $3, $4, 'PREPAID', 'PENDING', $5)
+        RETURNING id
+        "#,
+        reference,
+        phone_number,
+        payment_code,
+        request.amount,
+        Utc::now().timestamp_millis(),
+    )
+    .fetch_one(&mut *db_tx)
+    .await?
+    .id;
+    link_transaction(&mut *db_tx, &reference, transaction_id).await?;
+
+    let topup = sqlx::query_as!(
+        AirtimeTopup,
+        r#"
+        INSERT INTO airtime_topups
+            (transaction_id, request_reference, phone_number, network, service_id, payment_code,
+             amount, status)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING *
+        "#,
+        transaction_id,
+        reference,
+        phone_number,
+        request.network.as_str(),
+        service_id as i32,
+        payment_code,
+        request.amount,
+        TopupStatus::Pending.as_str(),
+    )
+    .fetch_one(&mut *db_tx)
+    .await?;
+
+    db_tx.commit().await?;
+    Ok(topup)
+}
+
+pub(crate) async fn record_outcome(
+    pool: &PgPool,
+    topup: &AirtimeTopup,
+    outcome: Outcome,
+) -> Result<AirtimeTopup, sqlx::Error> {
+    let mut db_tx = pool.begin().await?;
+    let updated = apply_outcome(&mut db_tx, topup, outcome).await?;
+    db_tx.commit().await?;
+    Ok(updated)
+}
+
+/// Stores `outcome` on the top-up and moves its transaction's
+/// `confirm_status` along with it. A top-up that is already final keeps
+/// its result, and the stored row is returned instead.
+async fn apply_outcome(
+    conn: &mut PgConnection,
+    topup: &AirtimeTopup,
+    outcome: Outcome,
+) -> Result<AirtimeTopup, sqlx::Error> {
+    let updated = sqlx::query_as!(
+        AirtimeTopup,
+        r#"
+        UPDATE airtime_topups
+        SET status = $2,
+            transaction_ref = COALESCE($3, transaction_ref),
+            response_code = COALESCE($4, response_code),
+            message = $5,
+            updated_at = NOW()
+        WHERE id = $1 AND status = $6
+        RETURNING *
+        "#,
+        topup.id,
+        outcome.status.as_str(),
+        outcome.transaction_ref,
+        outcome.response_code,
+        outcome.message,
+        TopupStatus::Pending.as_str(),
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some(updated) = updated else {
+        tracing::warn!(
+            "⚠️ Airtime top-up {} is already final, keeping its result",
+            topup.request_reference
+        );
+        return sqlx::query_as!(
+            AirtimeTopup,
+            "SELECT * FROM airtime_topups WHERE id = $1",
+            topup.id
+        )
+        .fetch_one(&mut *conn)
+        .await;
+    };
+
+    sqlx::query!(
+        "UPDATE transactions SET confirm_status = $1 WHERE id = $2",
+        outcome.status.confirm_status(),
+        topup.transaction_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(updated)
+}
+
+/// The top-up sent as `request_reference`, queried once more if it is still
+/// pending.
+pub async fn load_topup(
+    pool: &PgPool,
+    tokens: &TokenManager,
+    request_reference: &str,
+) -> Result<AirtimeTopup, TopupError> {
+    let topup = sqlx::query_as!(
+        AirtimeTopup,
+        "SELECT * FROM airtime_topups WHERE request_reference = $1",
+        request_reference
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| TopupError::NotFound(request_reference.to_string()))?;
+
+    if topup.status != TopupStatus::Pending.as_str() {
+        return Ok(topup);
+    }
+    match requery(pool, tokens, &topup.request_reference, is_lost(&topup)).await {
+        Some(outcome) => Ok(record_outcome(pool, &topup, outcome).await?),
+        None => Ok(topup),
+    }
+}
@@ -1,84 +1,173 @@
+use crate::models::bluecode::{
+    BluecodeCancelRequest, BluecodeCancelResponse, BluecodeRefundRequest,
+    BluecodeRefundResponseWrapper, BluecodeRefundStatusRequest,
+};
 use crate::models::bluecode::{
     BluecodeRegisterRequest, BluecodeRegisterResponse, BluecodeRegisterResponseWrapper,
 };
 use crate::models::bluecode::{BluecodeStatusRequest, BluecodeStatusResponseWrapper};
+use crate::models::money::{Currency, Money};
+use crate::services::vendor_exchanges::{Exchange, VendorProvider};
 use crate::utils::error::ApiError;
-use reqwest::Client;
+use reqwest::{header, Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::PgPool;
 use std::env;
 
+/// Currency Bluecode payments are registered in, `BLUECODE_CURRENCY` or NGN.
+pub fn bluecode_currency() -> Currency {
+    env::var("BLUECODE_CURRENCY")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(Currency::Ngn)
+}
+
+/// Builds a `/v4/register` request from the BLUECODE_* settings. Bluecode
+/// takes `requested_amount` in minor units with the currency code alongside.
+pub fn register_request(merchant_tx_id: String, amount: Money) -> BluecodeRegisterRequest {
+    BluecodeRegisterRequest {
+        merchant_tx_id,
+        branch_ext_id: env::var("BLUECODE_BRANCH_EXT_ID").unwrap_or_default(),
+        scheme: env::var("BLUECODE_SCHEME").unwrap_or("blue_code".into()),
+        requested_amount: amount.minor(),
+        currency: amount.currency().code().to_string(),
+        terminal: env::var("BLUECODE_TERMINAL").unwrap_or("POS001".into()),
+        source: env::var("BLUECODE_SOURCE").unwrap_or("web".into()),
+        merchant_callback_url: env::var("BLUECODE_CALLBACK_URL").unwrap_or_default(),
+        return_url_failure: env::var("BLUECODE_REDIRECT_URL").unwrap_or_default(),
+        return_url_success: env::var("BLUECODE_SUCESS_URL").unwrap_or_default(),
+        return_url_cancel: env::var("BLUECODE_CANCEL_URL").unwrap_or_default(),
+    }
+}
+
 pub async fn initiate_qr_payment(
+    pool: &PgPool,
     req: BluecodeRegisterRequest,
 ) -> Result<BluecodeRegisterResponse, ApiError> {
-    let client = Client::new();
-    let base_url = env::var("BLUECODE_API_BASE_URL")
-        .unwrap_or_else(|_| "https://merchant-api.acq.int.bluecode.ng".to_string());
-
-    let full_url = format!("{}/v4/register", base_url);
-
-    let username = env::var("BLUECODE_MERCHANT_ACCESS")
-        .map_err(|_| ApiError::EnvVarMissing("BLUECODE_MERCHANT_ACCESS".to_string()))?;
-    let password = env::var("BLUECODE_MERCHANT_SECRET")
-        .map_err(|_| ApiError::EnvVarMissing("BLUECODE_MERCHANT_SECRET".to_string()))?;
+    let (status, body) =
+        send_to_bluecode(pool, "register", "/v4/register", &req.merchant_tx_id, &req).await?;
 
-    // 💬 Log what we're sending
-    println!("📤 Sending to Bluecode:");
-    println!("URL: {}", full_url);
-    println!("Payload: {:#?}", req);
-
-    let response = client
-        .post(&full_url)
-        .basic_auth(username, Some(password))
-        .json(&req)
-        .send()
-        .await;
-
-    match response {
-        Ok(resp) => {
-            let status = resp.status();
-            let body = resp.text().await.unwrap_or_default();
-            println!("📥 Status: {}", status);
-            println!("📥 Response body: {}", body);
-
-            if status.is_success() {
-                let parsed = serde_json::from_str::<BluecodeRegisterResponseWrapper>(&body)
-                    .map_err(|_| ApiError::InternalServerError)?;
-
-                Ok(parsed.payment)
-            } else {
-                Err(ApiError::InternalServerError)
-            }
-        }
-        Err(err) => {
-            println!("❌ HTTP error: {}", err);
-            Err(ApiError::RequestError(err))
-        }
+    if !status.is_success() {
+        tracing::error!("❌ Bluecode register failed. HTTP {}: {}", status, body);
+        return Err(ApiError::InternalServerError);
     }
+
+    let parsed = serde_json::from_str::<BluecodeRegisterResponseWrapper>(&body)
+        .map_err(|_| ApiError::InternalServerError)?;
+    Ok(parsed.payment)
 }
 
 pub async fn requery_transaction(
+    pool: &PgPool,
     merchant_tx_id: String,
 ) -> Result<BluecodeStatusResponseWrapper, ApiError> {
-    let client = Client::new();
-    let base_url = env::var("BLUECODE_API_BASE_URL")
-        .unwrap_or_else(|_| "https://merchant-api.acq.int.bluecode.ng".to_string());
+    post_to_bluecode(
+        pool,
+        "status",
+        "/v4/status",
+        &merchant_tx_id,
+        &BluecodeStatusRequest {
+            merchant_tx_id: merchant_tx_id.clone(),
+        },
+    )
+    .await
+}
+
+/// Cancels a payment that was registered but not yet paid.
+pub async fn cancel_payment(
+    pool: &PgPool,
+    merchant_tx_id: String,
+) -> Result<BluecodeCancelResponse, ApiError> {
+    post_to_bluecode(
+        pool,
+        "cancel",
+        "/v4/cancel",
+        &merchant_tx_id,
+        &BluecodeCancelRequest {
+            merchant_tx_id: merchant_tx_id.clone(),
+        },
+    )
+    .await
+}
+
+/// Refunds all or part of an approved payment.
+pub async fn refund_payment(
+    pool: &PgPool,
+    req: BluecodeRefundRequest,
+) -> Result<BluecodeRefundResponseWrapper, ApiError> {
+    post_to_bluecode(pool, "refund", "/v4/refund", &req.merchant_tx_id, &req).await
+}
 
-    let full_url = format!("{}/v4/status", base_url);
+/// Current state of the refund sent as `merchant_refund_id`.
+pub async fn refund_status(
+    pool: &PgPool,
+    req: BluecodeRefundStatusRequest,
+) -> Result<BluecodeRefundResponseWrapper, ApiError> {
+    post_to_bluecode(
+        pool,
+        "refund_status",
+        "/v4/refund/status",
+        &req.merchant_tx_id,
+        &req,
+    )
+    .await
+}
+
+async fn post_to_bluecode<Req, Resp>(
+    pool: &PgPool,
+    operation: &'static str,
+    path: &str,
+    merchant_tx_id: &str,
+    body: &Req,
+) -> Result<Resp, ApiError>
+where
+    Req: Serialize,
+    Resp: DeserializeOwned,
+{
+    let (status, text) = send_to_bluecode(pool, operation, path, merchant_tx_id, body).await?;
+
+    if !status.is_success() {
+        tracing::error!("❌ Bluecode {} failed. HTTP {}: {}", path, status, text);
+        return Err(ApiError::UpstreamStatus { status, body: text });
+    }
 
+    serde_json::from_str(&text).map_err(|e| {
+        tracing::error!("❌ Bluecode {} returned unexpected JSON: {}", path, e);
+        ApiError::ParseError(format!("Parse error: {}", e))
+    })
+}
+
+/// POSTs `body` to Bluecode and records the exchange under `merchant_tx_id`.
+async fn send_to_bluecode<Req: Serialize>(
+    pool: &PgPool,
+    operation: &'static str,
+    path: &str,
+    merchant_tx_id: &str,
+    body: &Req,
+) -> Result<(StatusCode, String), ApiError> {
+    let base_url = env::var("BLUECODE_API_BASE_URL")
+        .unwrap_or_else(|_| "https://merchant-api.acq.int.bluecode.ng".to_string());
     let username = env::var("BLUECODE_MERCHANT_ACCESS")
         .map_err(|_| ApiError::EnvVarMissing("BLUECODE_MERCHANT_ACCESS".to_string()))?;
     let password = env::var("BLUECODE_MERCHANT_SECRET")
         .map_err(|_| ApiError::EnvVarMissing("BLUECODE_MERCHANT_SECRET".to_string()))?;
 
-    let request_body = BluecodeStatusRequest { merchant_tx_id };
+    let url = format!("{}{}", base_url, path);
+    let payload = serde_json::to_string(body).map_err(|e| ApiError::ParseError(e.to_string()))?;
+    let exchange = Exchange::start(pool, VendorProvider::Bluecode, operation, "POST", &url)
+        .correlation_id(merchant_tx_id)
+        .request_body(&payload);
 
-    let response = client
-        .post(&full_url)
-        .basic_auth(username, Some(password))
-        .json(&request_body)
-        .send()
-        .await?
-        .json::<BluecodeStatusResponseWrapper>()
+    let answer = exchange
+        .send(
+            Client::new()
+                .post(&url)
+                .basic_auth(username, Some(password))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(payload),
+        )
         .await?;
 
-    Ok(response)
+    Ok(answer)
 }
@@ -0,0 +1,201 @@
+//! Background requery of Bluecode payments whose callback never arrived, and
+//! of refunds whose outcome is not known yet.
+//!
+//! Each cycle claims its batch by stamping `last_polled_at` under a Postgres
+//! advisory lock, so with several instances deployed only one of them claims
+//! at a time. The lock is released before Bluecode is called, and each
+//! result is written on its own.
+
+use crate::models::bluecode::BluecodePaymentState;
+use crate::services::bluecode::{cancel_payment, requery_transaction};
+use crate::services::checkout::advance_by_merchant_tx_id;
+use crate::services::refunds::resolve_pending_refunds;
+use sqlx::PgPool;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Advisory lock key shared by every instance running the poller ("bluecode")
+const POLLER_LOCK_KEY: i64 = 0x626c_7565_636f_6465;
+
+#[derive(Debug, Clone)]
+pub struct PollerSettings {
+    /// Pause between two polling cycles
+    pub interval_secs: u64,
+    /// A payment is polled once it has been pending (or unpolled) this long
+    pub min_age_secs: i64,
+    /// Maximum payments requeried per cycle
+    pub batch_size: i64,
+    /// Registrations still unpaid after this long are cancelled
+    pub registration_ttl_secs: i64,
+}
+
+impl PollerSettings {
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        PollerSettings {
+            interval_secs: var("BLUECODE_POLL_INTERVAL_SECS", 60),
+            min_age_secs: var("BLUECODE_POLL_MIN_AGE_SECS", 120),
+            batch_size: var("BLUECODE_POLL_BATCH_SIZE", 50),
+            registration_ttl_secs: var("BLUECODE_REGISTRATION_TTL_SECS", 900),
+        }
+    }
+}
+
+pub fn spawn_bluecode_poller(pool: PgPool) -> tokio::task::JoinHandle<()> {
+    let settings = PollerSettings::from_env();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(settings.interval_secs));
+        loop {
+            interval.tick().await;
+            match poll_stale_payments(&pool, &settings).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("🔄 Requeried {} stale Bluecode payments", count),
+                Err(err) => tracing::error!("❌ Bluecode poller failed: {}", err),
+            }
+            match resolve_pending_refunds(&pool, settings.min_age_secs, settings.batch_size).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("💸 Requeried {} unsettled Bluecode refunds", count),
+                Err(err) => tracing::error!("❌ Bluecode refund requery failed: {}", err),
+            }
+        }
+    })
+}
+
+/// Requeries one batch of stale payments. Returns how many were polled, or
+/// 0 when another instance is already claiming a batch.
+pub async fn poll_stale_payments(
+    pool: &PgPool,
+    settings: &PollerSettings,
+) -> Result<usize, sqlx::Error> {
+    let mut db_tx = pool.begin().await?;
+
+    let locked = sqlx::query!(
+        r#"SELECT pg_try_advisory_xact_lock($1) AS "locked!""#,
+        POLLER_LOCK_KEY
+    )
+    .fetch_one(&mut *db_tx)
+    .await?
+    .locked;
+    if !locked {
+        return Ok(0);
+    }
+
+    let stale = sqlx::query!(
+        r#"
+        UPDATE transactions
+        SET last_polled_at = NOW()
+        WHERE id IN (
+            SELECT id
+            FROM transactions
+            WHERE qr_status IN ($1, $2)
+              AND created_at < NOW() - make_interval(secs => $3)
+              AND (last_polled_at IS NULL OR last_polled_at < NOW() - make_interval(secs => $3))
+            ORDER BY last_polled_at NULLS FIRST, created_at
+            LIMIT $5
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, merchant_reference, qr_status,
+                  created_at < NOW() - make_interval(secs => $4) AS "expired!"
+        "#,
+        BluecodePaymentState::Pending.as_str(),
+        BluecodePaymentState::Registered.as_str(),
+        settings.min_age_secs as f64,
+        settings.registration_ttl_secs as f64,
+        settings.batch_size,
+    )
+    .fetch_all(&mut *db_tx)
+    .await?;
+
+    db_tx.commit().await?;
+
+    for row in &stale {
+        let current = BluecodePaymentState::from(row.qr_status.as_str());
+        let state = match requery_transaction(pool, row.merchant_reference.clone()).await {
+            Ok(response) => response.payment.state,
+            // Left for the next cycle; an expired registration is only
+            // cancelled once Bluecode confirms it is still unpaid
+            Err(err) => {
+                tracing::warn!("⚠️ Requery of {} failed: {}", row.merchant_reference, err);
+                continue;
+            }
+        };
+
+        // Unknown states are logged, never stored
+        if let BluecodePaymentState::Unknown(raw) = &state {
+            tracing::warn!(
+                "❓ Bluecode reported unknown state {} for {}",
+                raw,
+                row.merchant_reference
+            );
+            continue;
+        }
+
+        let state = if !state.is_final() && row.expired {
+            match expire_registration(pool, &row.merchant_reference).await {
+                Some(cancelled) => cancelled,
+                None => continue,
+            }
+        } else {
+            state
+        };
+
+        if state == current {
+            continue;
+        }
+
+        // A callback that arrived meanwhile wins over what we polled
+        let updated = sqlx::query!(
+            "UPDATE transactions SET qr_status = $1 WHERE id = $2 AND qr_status = $3",
+            state.as_str(),
+            row.id,
+            current.as_str()
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        if updated == 1 && state.is_final() {
+            tokio::spawn(advance_by_merchant_tx_id(
+                pool.clone(),
+                row.merchant_reference.clone(),
+            ));
+        }
+    }
+
+    Ok(stale.len())
+}
+
+/// Cancels an unpaid registration that outlived its TTL. Returns `None`,
+/// leaving the registration for the next cycle, unless Bluecode
+/// acknowledged the cancellation.
+async fn expire_registration(pool: &PgPool, merchant_tx_id: &str) -> Option<BluecodePaymentState> {
+    tracing::info!("⌛ Bluecode registration {} expired", merchant_tx_id);
+
+    match cancel_payment(pool, merchant_tx_id.to_string()).await {
+        Ok(response) if response.result == "OK" => Some(BluecodePaymentState::Cancelled),
+        Ok(response) => {
+            tracing::warn!(
+                "⚠️ Bluecode did not cancel expired registration {}: {}",
+                merchant_tx_id,
+                response.result
+            );
+            None
+        }
+        Err(err) => {
+            tracing::warn!(
+                "⚠️ Could not cancel expired registration {}: {}",
+                merchant_tx_id,
+                err
+            );
+            None
+        }
+    }
+}
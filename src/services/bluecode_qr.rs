@@ -0,0 +1,207 @@
+//! QR images for Bluecode checkin codes, so web, kiosk and printed receipts
+//! all show the same code.
+//!
+//! The QR encodes the wallet deep link built from `BLUECODE_DEEP_LINK_TEMPLATE`
+//! (`{code}` is replaced by the checkin code). An optional logo is read from
+//! the PNG at `BLUECODE_QR_LOGO_PATH`.
+
+use crate::models::bluecode::{BluecodeRegisterRequest, BluecodeRegisterResponse};
+use base64::engine::general_purpose;
+use base64::Engine;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, Rgba, RgbaImage};
+use qrcode::render::svg;
+use qrcode::{EcLevel, QrCode};
+use serde::Deserialize;
+use sqlx::{PgExecutor, PgPool};
+use std::env;
+use std::io::Cursor;
+use thiserror::Error;
+
+const DEFAULT_DEEP_LINK_TEMPLATE: &str = "bluecode://checkin/{code}";
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 2048;
+
+/// Share of the QR width covered by the logo; small enough for level Q/H
+/// error correction to recover the hidden modules.
+const LOGO_RATIO: u32 = 5;
+
+#[derive(Error, Debug)]
+pub enum QrError {
+    #[error("Size must be between {MIN_SIZE} and {MAX_SIZE} pixels")]
+    InvalidSize,
+
+    #[error("Unknown error correction level: {0}")]
+    InvalidEcLevel(String),
+
+    #[error("Logo is not configured or unreadable: {0}")]
+    Logo(String),
+
+    #[error("Failed to render QR code: {0}")]
+    Render(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QrOptions {
+    #[serde(default)]
+    pub format: QrFormat,
+    /// Minimum width and height in pixels
+    #[serde(default = "default_size")]
+    pub size: u32,
+    /// Error correction level: L, M, Q or H
+    #[serde(default)]
+    pub ecc: Option<String>,
+    #[serde(default)]
+    pub logo: bool,
+}
+
+fn default_size() -> u32 {
+    320
+}
+
+impl QrOptions {
+    fn ec_level(&self) -> Result<EcLevel, QrError> {
+        let level = match self.ecc.as_deref().map(str::to_ascii_uppercase).as_deref() {
+            None if self.logo => EcLevel::H,
+            None => EcLevel::M,
+            Some("L") => EcLevel::L,
+            Some("M") => EcLevel::M,
+            Some("Q") => EcLevel::Q,
+            Some("H") => EcLevel::H,
+            Some(other) => return Err(QrError::InvalidEcLevel(other.to_string())),
+        };
+        Ok(level)
+    }
+}
+
+pub fn deep_link(checkin_code: &str) -> String {
+    if checkin_code.contains("://") {
+        return checkin_code.to_string();
+    }
+
+    env::var("BLUECODE_DEEP_LINK_TEMPLATE")
+        .unwrap_or_else(|_| DEFAULT_DEEP_LINK_TEMPLATE.to_string())
+        .replace("{code}", checkin_code)
+}
+
+/// Remembers the checkin code Bluecode issued for a registration.
+pub async fn record_registration<'e, E: PgExecutor<'e>>(
+    executor: E,
+    request: &BluecodeRegisterRequest,
+    response: &BluecodeRegisterResponse,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO bluecode_registrations (merchant_tx_id, checkin_code, requested_amount, currency)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (merchant_tx_id) DO UPDATE SET checkin_code = EXCLUDED.checkin_code
+        "#,
+        response.merchant_tx_id,
+        response.checkin_code,
+        request.requested_amount,
+        request.currency,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn find_checkin_code(
+    pool: &PgPool,
+    merchant_tx_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT checkin_code FROM bluecode_registrations WHERE merchant_tx_id = $1",
+        merchant_tx_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.checkin_code))
+}
+
+/// Renders `content` as PNG bytes.
+pub fn render_png(content: &str, options: &QrOptions) -> Result<Vec<u8>, QrError> {
+    let code = encode(content, options)?;
+    let mut image: RgbaImage = code
+        .render::<Rgba<u8>>()
+        .min_dimensions(options.size, options.size)
+        .build();
+
+    if options.logo {
+        let logo = load_logo()?;
+        let logo_size = image.width() / LOGO_RATIO;
+        let logo = imageops::resize(&logo, logo_size, logo_size, FilterType::Lanczos3);
+        let offset = ((image.width() - logo_size) / 2) as i64;
+        imageops::overlay(&mut image, &logo, offset, offset);
+    }
+
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(|e| QrError::Render(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Renders `content` as an SVG document. The logo, if requested, is embedded
+/// as a base64 PNG in the centre.
+pub fn render_svg(content: &str, options: &QrOptions) -> Result<String, QrError> {
+    let code = encode(content, options)?;
+    let mut document = code
+        .render::<svg::Color>()
+        .min_dimensions(options.size, options.size)
+        .build();
+
+    if options.logo {
+        let logo = load_logo()?;
+        let mut png = Vec::new();
+        logo.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| QrError::Render(e.to_string()))?;
+
+        let width =
+            svg_width(&document).ok_or_else(|| QrError::Render("SVG has no width".into()))?;
+        let logo_size = width / LOGO_RATIO;
+        let offset = (width - logo_size) / 2;
+        let element = format!(
+            r#"<image x="{offset}" y="{offset}" width="{logo_size}" height="{logo_size}" href="data:image/png;base64,{}"/>"#,
+            general_purpose::STANDARD.encode(png)
+        );
+        let end = document
+            .rfind("</svg>")
+            .ok_or_else(|| QrError::Render("SVG is not closed".into()))?;
+        document.insert_str(end, &element);
+    }
+
+    Ok(document)
+}
+
+fn encode(content: &str, options: &QrOptions) -> Result<QrCode, QrError> {
+    if !(MIN_SIZE..=MAX_SIZE).contains(&options.size) {
+        return Err(QrError::InvalidSize);
+    }
+
+    QrCode::with_error_correction_level(content.as_bytes(), options.ec_level()?)
+        .map_err(|e| QrError::Render(e.to_string()))
+}
+
+fn load_logo() -> Result<RgbaImage, QrError> {
+    let path = env::var("BLUECODE_QR_LOGO_PATH")
+        .map_err(|_| QrError::Logo("BLUECODE_QR_LOGO_PATH not set".into()))?;
+    let logo = image::open(&path).map_err(|e| QrError::Logo(format!("{}: {}", path, e)))?;
+    Ok(logo.to_rgba8())
+}
+
+fn svg_width(document: &str) -> Option<u32> {
+    let start = document.find("width=\"")? + "width=\"".len();
+    let end = start + document[start..].find('"')?;
+    document[start..end].parse().ok()
+}
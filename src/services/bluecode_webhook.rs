@@ -0,0 +1,226 @@
+//! Authenticity checks for Bluecode callbacks.
+//!
+//! Every callback carries:
+//! - `X-Bluecode-Timestamp`: unix seconds at which Bluecode signed the delivery
+//! - `X-Bluecode-Signature`: hex HMAC-SHA256 of `"{timestamp}.{raw body}"`
+//!   keyed with `BLUECODE_WEBHOOK_SECRET` (an optional `sha256=` prefix is accepted)
+//! - `X-Bluecode-Delivery`: delivery id, logged only since it is not signed
+//!
+//! Replays are detected on the signature, which covers both the timestamp and
+//! the body, so a captured callback cannot be resent under another delivery
+//! id. A delivery is claimed before it is processed, so concurrent copies of
+//! it are not both handled.
+
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use ipnet::IpNet;
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::env;
+use std::net::IpAddr;
+use thiserror::Error;
+
+pub const SIGNATURE_HEADER: &str = "x-bluecode-signature";
+pub const TIMESTAMP_HEADER: &str = "x-bluecode-timestamp";
+pub const DELIVERY_HEADER: &str = "x-bluecode-delivery";
+
+const DEFAULT_TOLERANCE_SECS: i64 = 300;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum WebhookError {
+    #[error("BLUECODE_WEBHOOK_SECRET is not configured")]
+    SecretMissing,
+
+    #[error("Missing or malformed header: {0}")]
+    MissingHeader(&'static str),
+
+    #[error("Signature does not match payload")]
+    InvalidSignature,
+
+    #[error("Timestamp {0} is outside the accepted window")]
+    StaleTimestamp(i64),
+
+    #[error("Delivery {0} was already processed")]
+    Replayed(String),
+
+    #[error("Source address {0:?} is not allowed")]
+    SourceNotAllowed(Option<IpAddr>),
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub secret: Option<String>,
+    pub allowed_sources: Vec<IpNet>,
+    pub tolerance_secs: i64,
+    pub trust_forwarded_for: bool,
+}
+
+impl WebhookConfig {
+    pub fn from_env() -> Self {
+        let allowed_sources = env::var("BLUECODE_WEBHOOK_ALLOWED_IPS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| match parse_source(s) {
+                Some(net) => Some(net),
+                None => {
+                    tracing::warn!(
+                        "⚠️ Ignoring invalid BLUECODE_WEBHOOK_ALLOWED_IPS entry: {}",
+                        s
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        WebhookConfig {
+            secret: env::var("BLUECODE_WEBHOOK_SECRET")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            allowed_sources,
+            tolerance_secs: env::var("BLUECODE_WEBHOOK_TOLERANCE_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_TOLERANCE_SECS),
+            trust_forwarded_for: env::var("BLUECODE_WEBHOOK_TRUST_FORWARDED")
+                .map(|v| v == "true")
+                .unwrap_or(false),
+        }
+    }
+}
+
+fn parse_source(s: &str) -> Option<IpNet> {
+    s.parse::<IpNet>()
+        .ok()
+        .or_else(|| s.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Resolves the address the callback came from, honouring `X-Forwarded-For`
+/// only when the deployment sits behind a trusted proxy.
+pub fn source_address(
+    config: &WebhookConfig,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Option<IpAddr> {
+    if config.trust_forwarded_for {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|v| v.trim().parse().ok());
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+    peer
+}
+
+pub fn check_source(config: &WebhookConfig, source: Option<IpAddr>) -> Result<(), WebhookError> {
+    if config.allowed_sources.is_empty() {
+        return Ok(());
+    }
+
+    match source {
+        Some(ip) if config.allowed_sources.iter().any(|net| net.contains(&ip)) => Ok(()),
+        _ => Err(WebhookError::SourceNotAllowed(source)),
+    }
+}
+
+fn signing_mac(secret: &str, timestamp: i64, body: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    hex::encode(signing_mac(secret, timestamp, body).finalize().into_bytes())
+}
+
+/// Checks signature and timestamp freshness. Returns the key to use for
+/// replay detection: the hex signature, which is unique to the signed
+/// timestamp and body.
+pub fn verify(
+    config: &WebhookConfig,
+    headers: &HeaderMap,
+    body: &[u8],
+    now: i64,
+) -> Result<String, WebhookError> {
+    let secret = config
+        .secret
+        .as_deref()
+        .ok_or(WebhookError::SecretMissing)?;
+
+    let timestamp: i64 = header(headers, TIMESTAMP_HEADER)
+        .and_then(|v| v.parse().ok())
+        .ok_or(WebhookError::MissingHeader(TIMESTAMP_HEADER))?;
+    let signature = header(headers, SIGNATURE_HEADER)
+        .map(|v| v.trim_start_matches("sha256="))
+        .and_then(|v| hex::decode(v).ok())
+        .ok_or(WebhookError::MissingHeader(SIGNATURE_HEADER))?;
+
+    signing_mac(secret, timestamp, body)
+        .verify_slice(&signature)
+        .map_err(|_| WebhookError::InvalidSignature)?;
+
+    if (now - timestamp).abs() > config.tolerance_secs {
+        return Err(WebhookError::StaleTimestamp(timestamp));
+    }
+
+    Ok(hex::encode(signature))
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Claims a verified delivery before it is processed. Returns false when it
+/// was already claimed, by an earlier or a concurrent copy. Rows older than
+/// the timestamp window are pruned, since replays of those are already
+/// rejected as stale.
+pub async fn claim_delivery(
+    pool: &PgPool,
+    replay_key: &str,
+    tolerance_secs: i64,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM bluecode_webhook_deliveries
+        WHERE received_at < NOW() - make_interval(secs => $1)
+        "#,
+        (tolerance_secs * 2) as f64
+    )
+    .execute(pool)
+    .await?;
+
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO bluecode_webhook_deliveries (delivery_id)
+        VALUES ($1)
+        ON CONFLICT (delivery_id) DO NOTHING
+        "#,
+        replay_key
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(claimed == 1)
+}
+
+/// Gives up the claim on a delivery that could not be processed, so
+/// Bluecode's retry of it is accepted.
+pub async fn release_delivery(pool: &PgPool, replay_key: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM bluecode_webhook_deliveries WHERE delivery_id = $1",
+        replay_key
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
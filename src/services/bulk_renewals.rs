@@ -0,0 +1,736 @@
+//! Bulk DSTV renewals for corporate customers (hotels, estates, ...).
+//!
+//! An uploaded CSV with `customer_id`, `basket_id` and `amount` columns
+//! (amount in major units, e.g. naira) is validated row by row against the
+//! catalog and with a smartcard lookup, and stored as a preview. Once
+//! approved, valid rows are confirmed with MultiChoice, at most
+//! `DSTV_BULK_CONCURRENCY` at a time, and each row's result is stored as it
+//! arrives. Pending rows are requeried every `DSTV_BULK_REQUERY_SECS` until
+//! MultiChoice settles them.
+
+use crate::config::{vas_product, ConfigError, VasProduct};
+use crate::models::bulk_renewals::{
+    BulkBatchStatus, BulkRenewalBatch, BulkRenewalReport, BulkRenewalRow, BulkRowStatus,
+};
+use crate::models::dstv::{DstvConfirmation, DstvErrorCode, DstvLookupRequest};
+use crate::models::money::{Currency, Money};
+use crate::models::payu_vas::VasOutcome;
+use crate::services::catalog::check_basket_price;
+use crate::services::dstv::{
+    confirm_basket, confirmation_reference, lookup_dstv_account, requery_dstv_confirmation,
+    DstvError,
+};
+use crate::services::payu_vas::VasError;
+use crate::services::references::{issue_reference, ReferenceProvider, ReferencePurpose};
+use futures::stream::{self, StreamExt};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::env;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// `error_code` of rows that could not be read
+pub const INVALID_ROW: &str = "INVALID_ROW";
+/// `error_code` of a smartcard listed more than once in the same file
+pub const DUPLICATE_ROW: &str = "DUPLICATE_ROW";
+
+#[derive(Error, Debug)]
+pub enum BulkRenewalError {
+    #[error("Invalid CSV: {0}")]
+    Csv(String),
+
+    #[error("The CSV has no rows")]
+    Empty,
+
+    #[error("The CSV has {0} rows, more than the {1} allowed")]
+    TooManyRows(usize, usize),
+
+    #[error("Bulk renewal {0} not found")]
+    NotFound(Uuid),
+
+    #[error("Bulk renewal {id} is {status}")]
+    InvalidState { id: Uuid, status: String },
+
+    #[error("Bulk renewal {0} has no valid rows")]
+    NothingToRenew(Uuid),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// A CSV line, with why it cannot be renewed if it can't.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvRow {
+    pub line_number: i32,
+    pub customer_id: String,
+    pub basket_id: String,
+    pub amount: Option<Money>,
+    pub account_name: Option<String>,
+    pub error: Option<RowError>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    pub code: String,
+    pub message: String,
+}
+
+impl RowError {
+    fn new(code: &str, message: impl Into<String>) -> Self {
+        RowError {
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+fn concurrency() -> usize {
+    env::var("DSTV_BULK_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(4)
+}
+
+fn lease_secs() -> i64 {
+    env::var("DSTV_BULK_LEASE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300)
+}
+
+fn requery_secs() -> u64 {
+    env::var("DSTV_BULK_REQUERY_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60)
+}
+
+fn max_rows() -> usize {
+    env::var("DSTV_BULK_MAX_ROWS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1000)
+}
+
+/// Reads the rows of `csv`. Columns are found by header name, in any order;
+/// amounts are in major units of `currency`. Unreadable rows, such as ones
+/// that are not UTF-8, are returned with an [`INVALID_ROW`] error rather
+/// than failing the file.
+pub fn parse_csv(csv: &[u8], currency: Currency) -> Result<Vec<CsvRow>, BulkRenewalError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| BulkRenewalError::Csv(e.to_string()))?
+        .clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(name))
+            .ok_or_else(|| BulkRenewalError::Csv(format!("missing {} column", name)))
+    };
+    let (customer_col, basket_col, amount_col) = (
+        column("customer_id")?,
+        column("basket_id")?,
+        column("amount")?,
+    );
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                rows.push(CsvRow {
+                    line_number: err.position().map_or(0, |p| p.line() as i32),
+                    customer_id: String::new(),
+                    basket_id: String::new(),
+                    amount: None,
+                    account_name: None,
+                    error: Some(RowError::new(
+                        INVALID_ROW,
+                        format!("unreadable row: {}", err),
+                    )),
+                });
+                continue;
+            }
+        };
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+
+        let line_number = record.position().map_or(0, |p| p.line() as i32);
+        let field = |col: usize| record.get(col).unwrap_or_default().to_string();
+        let (customer_id, basket_id, amount) =
+            (field(customer_col), field(basket_col), field(amount_col));
+
+        let parsed_amount = Money::parse_major(&amount.replace(',', ""), currency);
+        let error = if customer_id.is_empty() {
+            Some(RowError::new(INVALID_ROW, "customer_id is empty"))
+        } else if basket_id.is_empty() {
+            Some(RowError::new(INVALID_ROW, "basket_id is empty"))
+        } else {
+            match &parsed_amount {
+                Ok(money) if money.is_positive() => None,
+                Ok(_) => Some(RowError::new(INVALID_ROW, "amount must be positive")),
+                Err(_) => Some(RowError::new(
+                    INVALID_ROW,
+                    format!("amount {:?} is not a number", amount),
+                )),
+            }
+        };
+
+        rows.push(CsvRow {
+            line_number,
+            customer_id,
+            basket_id,
+            amount: parsed_amount.ok(),
+            account_name: None,
+            error,
+        });
+    }
+
+    Ok(rows)
+}
+
+/// Validates `csv` and stores it as a batch waiting for approval.
+pub async fn create_batch(
+    pool: &PgPool,
+    product: Option<&str>,
+    name: Option<String>,
+    csv: &[u8],
+) -> Result<BulkRenewalReport, BulkRenewalError> {
+    let product = vas_product(product)?;
+    let mut rows = parse_csv(csv, product.currency)?;
+    if rows.is_empty() {
+        return Err(BulkRenewalError::Empty);
+    }
+    if rows.len() > max_rows() {
+        return Err(BulkRenewalError::TooManyRows(rows.len(), max_rows()));
+    }
+
+    let mut seen = HashSet::new();
+    for row in rows.iter_mut().filter(|r| r.error.is_none()) {
+        if !seen.insert(row.customer_id.clone()) {
+            row.error = Some(RowError::new(
+                DUPLICATE_ROW,
+                format!("{} is listed more than once", row.customer_id),
+            ));
+        }
+    }
+
+    let rows: Vec<CsvRow> = stream::iter(rows)
+        .map(|row| validate_row(pool, &product, row))
+        .buffered(concurrency())
+        .collect()
+        .await;
+
+    let id = Uuid::new_v4();
+    let valid_rows = rows.iter().filter(|r| r.error.is_none()).count() as i32;
+    let mut db_tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO dstv_bulk_batches (id, name, vas_product, status, total_rows, valid_rows)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        id,
+        name,
+        product.id,
+        BulkBatchStatus::Previewed.as_str(),
+        rows.len() as i32,
+        valid_rows,
+    )
+    .execute(&mut *db_tx)
+    .await?;
+
+    for row in &rows {
+        let (status, reference) = match row.error {
+            Some(_) => (BulkRowStatus::Invalid, None),
+            None => (
+                BulkRowStatus::Valid,
+                Some(
+                    issue_reference(
+                        &mut db_tx,
+                        ReferenceProvider::PayuVas,
+                        ReferencePurpose::BulkRenewal,
+                        None,
+                    )
+                    .await?,
+                ),
+            ),
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO dstv_bulk_rows
+                (batch_id, line_number, customer_id, basket_id, amount, reference, account_name,
+                 status, error_code, error)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            id,
+            row.line_number,
+            row.customer_id,
+            row.basket_id,
+            row.amount.map(|a| a.minor()),
+            reference,
+            row.account_name,
+            status.as_str(),
+            row.error.as_ref().map(|e| e.code.as_str()),
+            row.error.as_ref().map(|e| e.message.as_str()),
+        )
+        .execute(&mut *db_tx)
+        .await?;
+    }
+
+    db_tx.commit().await?;
+    tracing::info!(
+        "📋 Bulk renewal {} previewed: {}/{} rows valid",
+        id,
+        valid_rows,
+        rows.len()
+    );
+
+    load_report(pool, id).await
+}
+
+async fn validate_row(pool: &PgPool, product: &VasProduct, mut row: CsvRow) -> CsvRow {
+    let Some(amount) = row.amount.filter(|_| row.error.is_none()) else {
+        return row;
+    };
+
+    if let Err(err) = check_basket_price(pool, &product.id, &row.basket_id, amount.minor()).await {
+        row.error = Some(dstv_row_error(DstvError::from(err)));
+        return row;
+    }
+
+    let lookup = lookup_dstv_account(
+        pool,
+        DstvLookupRequest {
+            customer_id: row.customer_id.clone(),
+            product: Some(product.id.clone()),
+            refresh: false,
+        },
+    )
+    .await;
+    match lookup {
+        Ok(account) => row.account_name = account.account_name,
+        Err(err) => row.error = Some(dstv_row_error(err)),
+    }
+    row
+}
+
+fn dstv_row_error(err: DstvError) -> RowError {
+    match err.error_code() {
+        Some(code) => RowError::new(code.as_str(), code.customer_message()),
+        None => RowError::new(INVALID_ROW, err.to_string()),
+    }
+}
+
+/// Starts confirming the valid rows of a previewed batch in the background.
+pub async fn approve_batch(pool: &PgPool, id: Uuid) -> Result<BulkRenewalReport, BulkRenewalError> {
+    let approved = sqlx::query!(
+        r#"
+        UPDATE dstv_bulk_batches
+        SET status = $2, approved_at = NOW()
+        WHERE id = $1 AND status = $3 AND valid_rows > 0
+        "#,
+        id,
+        BulkBatchStatus::Running.as_str(),
+        BulkBatchStatus::Previewed.as_str(),
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if approved == 0 {
+        let batch = load_report(pool, id).await?.batch;
+        return Err(if batch.status != BulkBatchStatus::Previewed.as_str() {
+            BulkRenewalError::InvalidState {
+                id,
+                status: batch.status,
+            }
+        } else {
+            BulkRenewalError::NothingToRenew(id)
+        });
+    }
+
+    tracing::info!("✅ Bulk renewal {} approved", id);
+    tokio::spawn(run_batch(pool.clone(), id));
+    load_report(pool, id).await
+}
+
+/// Restarts batches left running by a previous process.
+pub async fn resume_bulk_renewals(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let ids = sqlx::query_scalar!(
+        "SELECT id FROM dstv_bulk_batches WHERE status = $1",
+        BulkBatchStatus::Running.as_str()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for id in &ids {
+        tracing::info!("🔁 Resuming bulk renewal {}", id);
+        tokio::spawn(run_batch(pool.clone(), *id));
+    }
+    Ok(ids.len())
+}
+
+async fn run_batch(pool: PgPool, id: Uuid) {
+    if let Err(err) = confirm_rows(&pool, id).await {
+        tracing::error!("❌ Bulk renewal {} stopped: {}", id, err);
+    }
+}
+
+/// Confirms the batch's rows until none is left unsettled. Rows are claimed
+/// a few at a time, so a second runner of the same batch takes other rows;
+/// rows MultiChoice left pending are requeried, not paid again, and the
+/// batch completes only once every row has a final result.
+async fn confirm_rows(pool: &PgPool, id: Uuid) -> Result<(), sqlx::Error> {
+    let vas_product = sqlx::query_scalar!(
+        "SELECT vas_product FROM dstv_bulk_batches WHERE id = $1",
+        id
+    )
+    .fetch_one(pool)
+    .await?;
+    let vas_product = vas_product.as_str();
+
+    loop {
+        loop {
+            let rows = claim_rows(pool, id).await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            let results: Vec<Result<(), sqlx::Error>> = stream::iter(rows)
+                .map(|row| confirm_row(pool, id, vas_product, row))
+                .buffer_unordered(concurrency())
+                .collect()
+                .await;
+            for err in results.into_iter().filter_map(Result::err) {
+                tracing::error!("❌ Failed to store bulk renewal {} row: {}", id, err);
+            }
+        }
+
+        requery_pending_rows(pool, id, vas_product).await?;
+        if complete_batch(pool, id).await? {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(requery_secs())).await;
+    }
+}
+
+/// Marks up to `DSTV_BULK_CONCURRENCY` rows `PROCESSING` and returns them.
+/// Rows left `PROCESSING` longer than `DSTV_BULK_LEASE_SECS`, by a crash,
+/// are sent again; MultiChoice sees the same confirmation reference.
+async fn claim_rows(pool: &PgPool, id: Uuid) -> Result<Vec<ClaimedRow>, sqlx::Error> {
+    sqlx::query_as!(
+        ClaimedRow,
+        r#"
+        UPDATE dstv_bulk_rows
+        SET status = $2, updated_at = NOW()
+        WHERE batch_id = $1 AND line_number IN (
+            SELECT line_number
+            FROM dstv_bulk_rows
+            WHERE batch_id = $1
+              AND (status = $3 OR (status = $2 AND updated_at < NOW() - make_interval(secs => $4)))
+            ORDER BY line_number
+            LIMIT $5
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING line_number, customer_id, basket_id, amount AS "amount!", reference AS "reference!"
+        "#,
+        id,
+        BulkRowStatus::Processing.as_str(),
+        BulkRowStatus::Valid.as_str(),
+        lease_secs() as f64,
+        concurrency() as i64,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+struct ClaimedRow {
+    line_number: i32,
+    customer_id: String,
+    basket_id: String,
+    amount: i64,
+    reference: String,
+}
+
+async fn confirm_row(
+    pool: &PgPool,
+    id: Uuid,
+    vas_product: &str,
+    row: ClaimedRow,
+) -> Result<(), sqlx::Error> {
+    let merchant_reference =
+        confirmation_reference(&mut *pool.acquire().await?, &row.reference).await?;
+    let result = confirm_basket(
+        pool,
+        Some(vas_product),
+        &merchant_reference,
+        &row.customer_id,
+        &row.basket_id,
+        row.amount,
+    )
+    .await;
+
+    let (status, error, receipt) = match result {
+        Ok(confirmation) => row_result(confirmation),
+        // Nothing was sent to MultiChoice
+        Err(err @ DstvError::Vas(VasError::Config(_))) => {
+            (BulkRowStatus::Failed, Some(dstv_row_error(err)), None)
+        }
+        // Neither the payment nor its requery answered, so the row may have
+        // been paid: leave it for the next requery
+        Err(err) => {
+            tracing::warn!(
+                "⚠️ Outcome of bulk renewal {} line {} is unknown: {}",
+                id,
+                row.line_number,
+                err
+            );
+            (BulkRowStatus::Pending, Some(dstv_row_error(err)), None)
+        }
+    };
+
+    set_row_result(
+        pool,
+        id,
+        row.line_number,
+        BulkRowStatus::Processing,
+        status,
+        error,
+        receipt,
+    )
+    .await
+}
+
+/// Asks MultiChoice how each `PENDING` row ended. Rows it still has not
+/// settled, or that could not be requeried, stay `PENDING`.
+async fn requery_pending_rows(
+    pool: &PgPool,
+    id: Uuid,
+    vas_product: &str,
+) -> Result<(), sqlx::Error> {
+    let pending = sqlx::query!(
+        r#"
+        SELECT line_number, reference AS "reference!"
+        FROM dstv_bulk_rows
+        WHERE batch_id = $1 AND status = $2
+        ORDER BY line_number
+        "#,
+        id,
+        BulkRowStatus::Pending.as_str(),
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for row in pending {
+        let confirmation =
+            match requery_dstv_confirmation(pool, Some(vas_product), &row.reference).await {
+                Ok(confirmation) if confirmation.outcome != VasOutcome::Pending => confirmation,
+                Ok(_) => continue,
+                Err(err) => {
+                    tracing::warn!(
+                        "⚠️ Could not requery bulk renewal {} line {}: {}",
+                        id,
+                        row.line_number,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+        let (status, error, receipt) = row_result(confirmation);
+        set_row_result(
+            pool,
+            id,
+            row.line_number,
+            BulkRowStatus::Pending,
+            status,
+            error,
+            receipt,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+fn row_result(confirmation: DstvConfirmation) -> (BulkRowStatus, Option<RowError>, Option<String>) {
+    match confirmation.outcome {
+        VasOutcome::Success => (BulkRowStatus::Confirmed, None, confirmation.receipt_number),
+        VasOutcome::Pending => (
+            BulkRowStatus::Pending,
+            Some(RowError::new(
+                DstvErrorCode::Pending.as_str(),
+                confirmation.message,
+            )),
+            None,
+        ),
+        VasOutcome::Failure => {
+            let code = confirmation.error_code.unwrap_or(DstvErrorCode::Unknown);
+            (
+                BulkRowStatus::Failed,
+                Some(RowError::new(code.as_str(), confirmation.message)),
+                None,
+            )
+        }
+    }
+}
+
+/// Completes the batch once no row is waiting for a result. Also `true`
+/// when another runner completed it first.
+async fn complete_batch(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+    let unsettled = [
+        BulkRowStatus::Valid,
+        BulkRowStatus::Processing,
+        BulkRowStatus::Pending,
+    ]
+    .map(|status| status.as_str());
+
+    let completed = sqlx::query!(
+        r#"
+        UPDATE dstv_bulk_batches
+        SET status = $2, completed_at = NOW()
+        WHERE id = $1 AND status = $3
+          AND NOT EXISTS (
+              SELECT 1 FROM dstv_bulk_rows WHERE batch_id = $1 AND status = ANY($4)
+          )
+        "#,
+        id,
+        BulkBatchStatus::Completed.as_str(),
+        BulkBatchStatus::Running.as_str(),
+        &unsettled[..] as &[&str],
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if completed == 1 {
+        tracing::info!("🏁 Bulk renewal {} completed", id);
+        return Ok(true);
+    }
+
+    let status = sqlx::query_scalar!("SELECT status FROM dstv_bulk_batches WHERE id = $1", id)
+        .fetch_one(pool)
+        .await?;
+    Ok(status != BulkBatchStatus::Running.as_str())
+}
+
+/// Stores a row's result unless another runner already moved it on from
+/// `from`.
+async fn set_row_result(
+    pool: &PgPool,
+    batch_id: Uuid,
+    line_number: i32,
+    from: BulkRowStatus,
+    status: BulkRowStatus,
+    error: Option<RowError>,
+    receipt_number: Option<String>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE dstv_bulk_rows
+        SET status = $3, error_code = $4, error = $5, receipt_number = $6, updated_at = NOW()
+        WHERE batch_id = $1 AND line_number = $2 AND status = $7
+        "#,
+        batch_id,
+        line_number,
+        status.as_str(),
+        error.as_ref().map(|e| e.code.as_str()),
+        error.as_ref().map(|e| e.message.as_str()),
+        receipt_number,
+        from.as_str(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn load_report(pool: &PgPool, id: Uuid) -> Result<BulkRenewalReport, BulkRenewalError> {
+    let batch = sqlx::query_as!(
+        BulkRenewalBatch,
+        r#"
+        SELECT id, name, vas_product, status, total_rows, valid_rows, created_at, approved_at,
+               completed_at
+        FROM dstv_bulk_batches
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(BulkRenewalError::NotFound(id))?;
+
+    let rows = sqlx::query_as!(
+        BulkRenewalRow,
+        r#"
+        SELECT line_number, customer_id, basket_id, amount, account_name, status, error_code,
+               error, receipt_number
+        FROM dstv_bulk_rows
+        WHERE batch_id = $1
+        ORDER BY line_number
+        "#,
+        id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(BulkRenewalReport { batch, rows })
+}
+
+/// Every row with its result, as CSV with amounts in major units.
+pub async fn results_csv(pool: &PgPool, id: Uuid) -> Result<String, BulkRenewalError> {
+    let report = load_report(pool, id).await?;
+    let currency = vas_product(Some(&report.batch.vas_product))?.currency;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let csv_error = |e: csv::Error| BulkRenewalError::Csv(e.to_string());
+    writer
+        .write_record([
+            "line_number",
+            "customer_id",
+            "basket_id",
+            "amount",
+            "account_name",
+            "status",
+            "error_code",
+            "error",
+            "receipt_number",
+        ])
+        .map_err(csv_error)?;
+
+    for row in report.rows {
+        let amount = row
+            .amount
+            .map(|a| Money::from_minor(a, currency).to_major_string())
+            .unwrap_or_default();
+        writer
+            .write_record([
+                row.line_number.to_string(),
+                row.customer_id,
+                row.basket_id,
+                amount,
+                row.account_name.unwrap_or_default(),
+                row.status,
+                row.error_code.unwrap_or_default(),
+                row.error.unwrap_or_default(),
+                row.receipt_number.unwrap_or_default(),
+            ])
+            .map_err(csv_error)?;
+    }
+
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| BulkRenewalError::Csv(e.to_string()))?;
+    String::from_utf8(bytes).map_err(|e| BulkRenewalError::Csv(e.to_string()))
+}
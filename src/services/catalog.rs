@@ -0,0 +1,270 @@
+//! DSTV product catalog: bouquets and add-ons with their basket IDs and
+//! prices, per VAS product.
+//!
+//! The catalog is loaded from the source configured in `DSTV_CATALOG_URL` (a
+//! JSON endpoint) or `DSTV_CATALOG_FILE` (a JSON file), stored in Postgres
+//! and cached in memory for `DSTV_CATALOG_CACHE_SECS`. Refreshing from
+//! MultiChoice lookup data is not supported; the configured source is the
+//! only one.
+//!
+//! Prices are checked against the catalog, so with no catalog loaded every
+//! basket is refused unless `DSTV_SKIP_PRICE_CHECK=true`.
+
+use crate::models::catalog::{DstvProduct, ProductKind};
+use sqlx::PgPool;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CatalogError {
+    #[error("Unknown basket {0}")]
+    UnknownBasket(String),
+
+    #[error("Amount {actual} does not match the {expected} price of basket {basket_id}")]
+    PriceMismatch {
+        basket_id: String,
+        expected: i64,
+        actual: i64,
+    },
+
+    #[error("DSTV catalog is empty, basket prices cannot be checked")]
+    Empty,
+
+    #[error("Catalog source error: {0}")]
+    Source(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+struct CachedCatalog {
+    loaded_at: Instant,
+    products: Arc<Vec<DstvProduct>>,
+}
+
+static CACHE: RwLock<Option<CachedCatalog>> = RwLock::new(None);
+
+fn cache_ttl() -> Duration {
+    let secs = env::var("DSTV_CATALOG_CACHE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300);
+    Duration::from_secs(secs)
+}
+
+/// Active products, served from the in-memory cache when it is fresh.
+pub async fn list_products(pool: &PgPool) -> Result<Arc<Vec<DstvProduct>>, sqlx::Error> {
+    if let Some(cached) = CACHE.read().unwrap().as_ref() {
+        if cached.loaded_at.elapsed() < cache_ttl() {
+            return Ok(cached.products.clone());
+        }
+    }
+
+    let products = Arc::new(load_products(pool).await?);
+    *CACHE.write().unwrap() = Some(CachedCatalog {
+        loaded_at: Instant::now(),
+        products: products.clone(),
+    });
+
+    Ok(products)
+}
+
+pub fn invalidate_cache() {
+    *CACHE.write().unwrap() = None;
+}
+
+pub async fn find_product(
+    pool: &PgPool,
+    vas_product: &str,
+    basket_id: &str,
+) -> Result<Option<DstvProduct>, sqlx::Error> {
+    Ok(list_products(pool)
+        .await?
+        .iter()
+        .find(|p| p.vas_product == vas_product && p.basket_id == basket_id)
+        .cloned())
+}
+
+/// Checks that `amount`, in minor units of the product's currency, is the
+/// price of `basket_id` under the VAS product `vas_product`.
+///
+/// Fails with [`CatalogError::Empty`] when no catalog has been loaded, and
+/// returns `Ok(None)` without checking anything when `DSTV_SKIP_PRICE_CHECK`
+/// is set.
+pub async fn check_basket_price(
+    pool: &PgPool,
+    vas_product: &str,
+    basket_id: &str,
+    amount: i64,
+) -> Result<Option<DstvProduct>, CatalogError> {
+    if price_check_skipped() {
+        tracing::warn!(
+            "⚠️ DSTV_SKIP_PRICE_CHECK is set, not checking price of {}",
+            basket_id
+        );
+        return Ok(None);
+    }
+
+    let products = list_products(pool).await?;
+    if products.is_empty() {
+        return Err(CatalogError::Empty);
+    }
+
+    let product = products
+        .iter()
+        .find(|p| p.vas_product == vas_product && p.basket_id == basket_id)
+        .ok_or_else(|| CatalogError::UnknownBasket(format!("{}/{}", vas_product, basket_id)))?;
+
+    if product.price != amount {
+        return Err(CatalogError::PriceMismatch {
+            basket_id: basket_id.to_string(),
+            expected: product.price,
+            actual: amount,
+        });
+    }
+
+    Ok(Some(product.clone()))
+}
+
+fn price_check_skipped() -> bool {
+    env::var("DSTV_SKIP_PRICE_CHECK")
+        .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+        .unwrap_or(false)
+}
+
+/// Replaces the stored catalog with the configured source. Products missing
+/// from the source are deactivated rather than deleted.
+pub async fn refresh_catalog(pool: &PgPool) -> Result<usize, CatalogError> {
+    let products = fetch_source().await?;
+    if products.is_empty() {
+        return Err(CatalogError::Source("catalog source is empty".into()));
+    }
+    if let Some(product) = products.iter().find(|p| p.price <= 0) {
+        return Err(CatalogError::Source(format!(
+            "basket {} has no price",
+            product.basket_id
+        )));
+    }
+
+    let mut db_tx = pool.begin().await?;
+
+    for product in &products {
+        sqlx::query!(
+            r#"
+            INSERT INTO dstv_products (vas_product, basket_id, name, kind, price, validity_days, active, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, TRUE, NOW())
+            ON CONFLICT (vas_product, basket_id) DO UPDATE
+            SET name = EXCLUDED.name, kind = EXCLUDED.kind, price = EXCLUDED.price,
+                validity_days = EXCLUDED.validity_days, active = TRUE, updated_at = NOW()
+            "#,
+            product.vas_product,
+            product.basket_id,
+            product.name,
+            product.kind.as_str(),
+            product.price,
+            product.validity_days,
+        )
+        .execute(&mut *db_tx)
+        .await?;
+    }
+
+    let vas_products: Vec<String> = products.iter().map(|p| p.vas_product.clone()).collect();
+    let basket_ids: Vec<String> = products.iter().map(|p| p.basket_id.clone()).collect();
+    sqlx::query!(
+        r#"
+        UPDATE dstv_products SET active = FALSE, updated_at = NOW()
+        WHERE active
+          AND (vas_product, basket_id) NOT IN (SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[]))
+        "#,
+        &vas_products,
+        &basket_ids
+    )
+    .execute(&mut *db_tx)
+    .await?;
+
+    db_tx.commit().await?;
+    invalidate_cache();
+
+    tracing::info!("📺 DSTV catalog refreshed with {} products", products.len());
+    Ok(products.len())
+}
+
+/// Refreshes the catalog on startup and then every `DSTV_CATALOG_REFRESH_SECS`,
+/// when a source is configured.
+pub fn spawn_catalog_refresher(pool: PgPool) -> Option<tokio::task::JoinHandle<()>> {
+    if env::var("DSTV_CATALOG_URL").is_err() && env::var("DSTV_CATALOG_FILE").is_err() {
+        tracing::warn!("⚠️ No DSTV catalog source configured");
+        return None;
+    }
+
+    let interval_secs = env::var("DSTV_CATALOG_REFRESH_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(6 * 60 * 60);
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(err) = refresh_catalog(&pool).await {
+                tracing::error!("❌ DSTV catalog refresh failed: {}", err);
+            }
+        }
+    }))
+}
+
+async fn load_products(pool: &PgPool) -> Result<Vec<DstvProduct>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT vas_product, basket_id, name, kind, price, validity_days
+        FROM dstv_products
+        WHERE active
+        ORDER BY vas_product, kind DESC, price
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| match ProductKind::from_str(&row.kind) {
+            Ok(kind) => Some(DstvProduct {
+                vas_product: row.vas_product,
+                basket_id: row.basket_id,
+                name: row.name,
+                kind,
+                price: row.price,
+                validity_days: row.validity_days,
+            }),
+            Err(err) => {
+                tracing::warn!("⚠️ Skipping DSTV product {}: {}", row.basket_id, err);
+                None
+            }
+        })
+        .collect())
+}
+
+async fn fetch_source() -> Result<Vec<DstvProduct>, CatalogError> {
+    let body = if let Ok(url) = env::var("DSTV_CATALOG_URL") {
+        reqwest::get(&url)
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| CatalogError::Source(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| CatalogError::Source(e.to_string()))?
+    } else if let Ok(path) = env::var("DSTV_CATALOG_FILE") {
+        tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| CatalogError::Source(format!("{}: {}", path, e)))?
+    } else {
+        return Err(CatalogError::Source(
+            "neither DSTV_CATALOG_URL nor DSTV_CATALOG_FILE is set".into(),
+        ));
+    };
+
+    serde_json::from_str(&body).map_err(|e| CatalogError::Source(e.to_string()))
+}
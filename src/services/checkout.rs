@@ -0,0 +1,678 @@
+//! Server-side DSTV checkout: register a Bluecode QR payment, wait for it to
+//! be approved, then confirm the subscription with MultiChoice.
+//!
+//! Every step is persisted in `dstv_checkouts` before moving on, so a restart
+//! picks each checkout up where it stopped. A step is claimed by pushing the
+//! checkout's `next_attempt_at` a lease ahead, which keeps the callback
+//! trigger and the background worker from confirming the same checkout twice
+//! without holding a connection or a row lock across provider calls. A
+//! worker that dies mid-step leaves the lease to expire.
+
+use crate::config::{vas_product, ConfigError, VasProduct};
+use crate::models::bluecode::BluecodePaymentState;
+use crate::models::checkout::{CheckoutStep, DstvCheckout, StartCheckoutRequest};
+use crate::models::dstv::DstvLookupRequest;
+use crate::models::money::{Money, MoneyError};
+use crate::models::payu_vas::VasOutcome;
+use crate::services::bluecode::{bluecode_currency, initiate_qr_payment, register_request};
+use crate::services::bluecode_qr::record_registration;
+use crate::services::catalog::{check_basket_price, CatalogError};
+use crate::services::dstv::{
+    confirm_basket, confirmation_reference, lookup_dstv_account, requery_dstv_confirmation,
+};
+use crate::services::references::{
+    issue_reference, link_transaction, ReferenceProvider, ReferencePurpose,
+};
+use sqlx::{PgConnection, PgPool};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum CheckoutError {
+    #[error("Checkout {0} not found")]
+    NotFound(Uuid),
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(i64),
+
+    #[error("DSTV lookup failed: {0}")]
+    Lookup(String),
+
+    #[error(transparent)]
+    Catalog(#[from] CatalogError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Money(#[from] MoneyError),
+
+    #[error("Checkout has unknown step: {0}")]
+    UnknownStep(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckoutSettings {
+    /// Attempts per step before the checkout is given up
+    pub max_attempts: i32,
+    /// First retry delay; doubled after every failed attempt
+    pub retry_base_secs: i64,
+    /// How often the background worker looks for checkouts to resume
+    pub worker_interval_secs: u64,
+    /// How long a claimed step keeps other tasks away from the checkout
+    pub lease_secs: i64,
+}
+
+impl CheckoutSettings {
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        CheckoutSettings {
+            max_attempts: var("DSTV_CHECKOUT_MAX_ATTEMPTS", 5),
+            retry_base_secs: var("DSTV_CHECKOUT_RETRY_BASE_SECS", 30),
+            worker_interval_secs: var("DSTV_CHECKOUT_WORKER_INTERVAL_SECS", 30),
+            lease_secs: var("DSTV_CHECKOUT_LEASE_SECS", 300),
+        }
+    }
+
+    fn retry_delay_secs(&self, attempts: i32) -> f64 {
+        let exponent = attempts.clamp(1, 10) - 1;
+        (self.retry_base_secs * 2i64.pow(exponent as u32)).min(3600) as f64
+    }
+}
+
+/// Validates the smartcard, stores the checkout and registers its QR payment.
+pub async fn start_checkout(
+    pool: &PgPool,
+    req: StartCheckoutRequest,
+) -> Result<DstvCheckout, CheckoutError> {
+    if req.amount <= 0 {
+        return Err(CheckoutError::InvalidAmount(req.amount));
+    }
+    let product = vas_product(req.product.as_deref())?;
+    check_basket_price(pool, &product.id, &req.basket_id, req.amount).await?;
+    payment_amount(&product, req.amount)?;
+
+    let lookup = lookup_dstv_account(
+        pool,
+        DstvLookupRequest {
+            customer_id: req.customer_id.clone(),
+            product: Some(product.id.clone()),
+            refresh: false,
+        },
+    )
+    .await
+    .map_err(|e| {
+        CheckoutError::Lookup(match e.error_code() {
+            Some(code) => code.customer_message().to_string(),
+            None => e.to_string(),
+        })
+    })?;
+    if !lookup.success {
+        return Err(CheckoutError::Lookup(lookup.message));
+    }
+
+    let id = Uuid::new_v4();
+    let mut db_tx = pool.begin().await?;
+
+    let merchant_tx_id = issue_reference(
+        &mut db_tx,
+        ReferenceProvider::Bluecode,
+        ReferencePurpose::Registration,
+        None,
+    )
+    .await?;
+
+    let transaction_id = sqlx::query!(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ($1, $2, $3, $4, 'PENDING', 'PENDING', $5)
+        RETURNING id
+        "#,
+        merchant_tx_id,
+        req.customer_id,
+        req.basket_id,
+        req.amount,
+        chrono::Utc::now().timestamp_millis(),
+    )
+    .fetch_one(&mut *db_tx)
+    .await?
+    .id;
+    link_transaction(&mut *db_tx, &merchant_tx_id, transaction_id).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO dstv_checkouts (id, merchant_tx_id, transaction_id, customer_id, basket_id, amount, vas_product, account_name, step)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+        id,
+        merchant_tx_id,
+        transaction_id,
+        req.customer_id,
+        req.basket_id,
+        req.amount,
+        product.id,
+        lookup.account_name,
+        CheckoutStep::Created.as_str(),
+    )
+    .execute(&mut *db_tx)
+    .await?;
+
+    db_tx.commit().await?;
+    tracing::info!("🛒 Started DSTV checkout {} ({})", id, merchant_tx_id);
+
+    advance_checkout(pool, id).await?;
+    load_checkout(pool, id).await
+}
+
+pub async fn load_checkout(pool: &PgPool, id: Uuid) -> Result<DstvCheckout, CheckoutError> {
+    sqlx::query_as!(
+        DstvCheckout,
+        r#"
+        SELECT id, merchant_tx_id, transaction_id, customer_id, basket_id, amount, vas_product,
+               account_name, checkin_code, step, attempts, last_error, next_attempt_at, created_at, updated_at
+        FROM dstv_checkouts
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(CheckoutError::NotFound(id))
+}
+
+/// Runs as many steps of the checkout as can complete right now. Returns
+/// without doing anything when another task holds the checkout or its next
+/// retry is not due yet.
+pub async fn advance_checkout(pool: &PgPool, id: Uuid) -> Result<(), CheckoutError> {
+    let settings = CheckoutSettings::from_env();
+
+    loop {
+        let Some(checkout) = claim_checkout(pool, id, &settings).await? else {
+            return Ok(());
+        };
+
+        let step = CheckoutStep::from_str(&checkout.step).map_err(CheckoutError::UnknownStep)?;
+        let next = match step {
+            CheckoutStep::Created => register_payment(pool, &checkout, &settings).await?,
+            CheckoutStep::PaymentRegistered => check_payment(pool, &checkout).await?,
+            CheckoutStep::PaymentApproved => {
+                confirm_subscription(pool, &checkout, &settings).await?
+            }
+            CheckoutStep::ConfirmationPending => {
+                requery_confirmation(pool, &checkout, &settings).await?
+            }
+            // Only unfinished checkouts are claimed; the final steps are
+            // listed to keep the match exhaustive
+            CheckoutStep::Confirmed
+            | CheckoutStep::PaymentFailed
+            | CheckoutStep::RefundRequired => step,
+        };
+
+        if next == step || next.is_terminal() {
+            return Ok(());
+        }
+        tracing::info!("➡️ Checkout {} moved from {} to {}", id, step, next);
+    }
+}
+
+/// Takes the lease on an unfinished checkout whose next attempt is due.
+async fn claim_checkout(
+    pool: &PgPool,
+    id: Uuid,
+    settings: &CheckoutSettings,
+) -> Result<Option<DstvCheckout>, sqlx::Error> {
+    let unfinished = CheckoutStep::UNFINISHED.map(|step| step.as_str());
+
+    sqlx::query_as!(
+        DstvCheckout,
+        r#"
+        UPDATE dstv_checkouts
+        SET next_attempt_at = NOW() + make_interval(secs => $2), updated_at = NOW()
+        WHERE id = (
+            SELECT id
+            FROM dstv_checkouts
+            WHERE id = $1
+              AND step = ANY($3)
+              AND (next_attempt_at IS NULL OR next_attempt_at <= NOW())
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, merchant_tx_id, transaction_id, customer_id, basket_id, amount, vas_product,
+                  account_name, checkin_code, step, attempts, last_error, next_attempt_at, created_at, updated_at
+        "#,
+        id,
+        settings.lease_secs as f64,
+        &unfinished[..] as &[&str],
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Resumes the checkout paid through `merchant_tx_id`, if there is one.
+pub async fn advance_by_merchant_tx_id(pool: PgPool, merchant_tx_id: String) {
+    let id = match sqlx::query!(
+        "SELECT id FROM dstv_checkouts WHERE merchant_tx_id = $1",
+        merchant_tx_id
+    )
+    .fetch_optional(&pool)
+    .await
+    {
+        Ok(Some(row)) => row.id,
+        Ok(None) => return,
+        Err(err) => {
+            tracing::error!("❌ Failed to find checkout for {}: {}", merchant_tx_id, err);
+            return;
+        }
+    };
+
+    if let Err(err) = advance_checkout(&pool, id).await {
+        tracing::error!("❌ Failed to advance checkout {}: {}", id, err);
+    }
+}
+
+/// Advances every unfinished checkout whose next attempt is due.
+pub async fn resume_checkouts(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let unfinished = CheckoutStep::UNFINISHED.map(|step| step.as_str());
+    let ids = sqlx::query!(
+        r#"
+        SELECT id
+        FROM dstv_checkouts
+        WHERE step = ANY($1)
+          AND (next_attempt_at IS NULL OR next_attempt_at <= NOW())
+        ORDER BY updated_at
+        LIMIT 100
+        "#,
+        &unfinished[..] as &[&str],
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for row in &ids {
+        if let Err(err) = advance_checkout(pool, row.id).await {
+            tracing::error!("❌ Failed to resume checkout {}: {}", row.id, err);
+        }
+    }
+
+    Ok(ids.len())
+}
+
+/// Resumes unfinished checkouts on startup and then periodically.
+pub fn spawn_checkout_worker(pool: PgPool) -> tokio::task::JoinHandle<()> {
+    let settings = CheckoutSettings::from_env();
+
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(settings.worker_interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(err) = resume_checkouts(&pool).await {
+                tracing::error!("❌ Checkout worker failed: {}", err);
+            }
+        }
+    })
+}
+
+async fn register_payment(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+    settings: &CheckoutSettings,
+) -> Result<CheckoutStep, sqlx::Error> {
+    let amount = vas_product(Some(&checkout.vas_product))
+        .map_err(CheckoutError::from)
+        .and_then(|product| Ok(payment_amount(&product, checkout.amount)?));
+    let amount = match amount {
+        Ok(amount) => amount,
+        Err(err) => {
+            return record_failure(
+                pool,
+                checkout,
+                &err.to_string(),
+                CheckoutStep::PaymentFailed,
+                settings,
+            )
+            .await
+        }
+    };
+    let request = register_request(checkout.merchant_tx_id.clone(), amount);
+
+    match initiate_qr_payment(pool, request.clone()).await {
+        Ok(payment) => {
+            let mut db_tx = pool.begin().await?;
+            record_registration(&mut *db_tx, &request, &payment).await?;
+
+            // Unknown states are logged, never stored
+            if let BluecodePaymentState::Unknown(raw) = &payment.state {
+                tracing::warn!(
+                    "❓ Bluecode registered {} with unknown state {}",
+                    checkout.merchant_tx_id,
+                    raw
+                );
+            }
+            sqlx::query!(
+                "UPDATE transactions SET qr_status = COALESCE($1, qr_status) WHERE id = $2",
+                payment.state.known().map(|s| s.as_str()),
+                checkout.transaction_id
+            )
+            .execute(&mut *db_tx)
+            .await?;
+
+            let next = set_step(
+                &mut db_tx,
+                checkout,
+                CheckoutStep::PaymentRegistered,
+                Some(&payment.checkin_code),
+            )
+            .await?;
+            db_tx.commit().await?;
+            Ok(next)
+        }
+        Err(err) => {
+            record_failure(
+                pool,
+                checkout,
+                &err.to_string(),
+                CheckoutStep::PaymentFailed,
+                settings,
+            )
+            .await
+        }
+    }
+}
+
+/// `amount` in the currency of `product`, which must be the one Bluecode
+/// registers payments in.
+fn payment_amount(product: &VasProduct, amount: i64) -> Result<Money, MoneyError> {
+    let amount = product.money(amount);
+    amount.expect_currency(bluecode_currency())?;
+    Ok(amount)
+}
+
+async fn check_payment(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+) -> Result<CheckoutStep, sqlx::Error> {
+    let qr_status = sqlx::query!(
+        "SELECT qr_status FROM transactions WHERE id = $1",
+        checkout.transaction_id
+    )
+    .fetch_one(pool)
+    .await?
+    .qr_status;
+
+    let mut conn = pool.acquire().await?;
+    match BluecodePaymentState::from(qr_status) {
+        BluecodePaymentState::Approved => {
+            set_step(&mut conn, checkout, CheckoutStep::PaymentApproved, None).await
+        }
+        state if state.is_final() => {
+            tracing::warn!(
+                "💳 Checkout {} payment ended as {}",
+                checkout.id,
+                state.as_str()
+            );
+            set_step(&mut conn, checkout, CheckoutStep::PaymentFailed, None).await
+        }
+        // Still waiting for the customer: give the lease back so the next
+        // callback can advance the checkout straight away
+        _ => set_step(&mut conn, checkout, CheckoutStep::PaymentRegistered, None).await,
+    }
+}
+
+async fn confirm_subscription(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+    settings: &CheckoutSettings,
+) -> Result<CheckoutStep, sqlx::Error> {
+    let merchant_reference =
+        confirmation_reference(&mut *pool.acquire().await?, &checkout.merchant_tx_id).await?;
+    let result = confirm_basket(
+        pool,
+        Some(&checkout.vas_product),
+        &merchant_reference,
+        &checkout.customer_id,
+        &checkout.basket_id,
+        checkout.amount,
+    )
+    .await;
+
+    match result {
+        Ok(confirmation) if confirmation.outcome == VasOutcome::Success => {
+            mark_confirmed(pool, checkout).await
+        }
+        // MultiChoice has the payment; paying again would only be refused as
+        // a duplicate, so requery it until it settles
+        Ok(confirmation) if confirmation.outcome == VasOutcome::Pending => {
+            tracing::warn!(
+                "⏳ Checkout {} confirmation is pending ({})",
+                checkout.id,
+                confirmation.result_code
+            );
+            reschedule(
+                pool,
+                checkout,
+                CheckoutStep::ConfirmationPending,
+                1,
+                &format!(
+                    "MultiChoice is still processing ({})",
+                    confirmation.result_code
+                ),
+                settings,
+            )
+            .await
+        }
+        Ok(confirmation) => {
+            let error = format!(
+                "MultiChoice reported {:?} ({}): {}",
+                confirmation.outcome, confirmation.result_code, confirmation.message
+            );
+            give_up_or_retry(pool, checkout, &error, settings).await
+        }
+        Err(err) => give_up_or_retry(pool, checkout, &err.to_string(), settings).await,
+    }
+}
+
+/// Requeries a pending confirmation. The checkout stays pending, however
+/// long it takes, until MultiChoice reports success or failure.
+async fn requery_confirmation(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+    settings: &CheckoutSettings,
+) -> Result<CheckoutStep, sqlx::Error> {
+    let result =
+        requery_dstv_confirmation(pool, Some(&checkout.vas_product), &checkout.merchant_tx_id)
+            .await;
+
+    let error = match result {
+        Ok(confirmation) => match confirmation.outcome {
+            VasOutcome::Success => return mark_confirmed(pool, checkout).await,
+            VasOutcome::Failure => {
+                let error = format!(
+                    "MultiChoice reported {:?} ({}): {}",
+                    confirmation.outcome, confirmation.result_code, confirmation.message
+                );
+                return flag_for_refund(pool, checkout, &error).await;
+            }
+            VasOutcome::Pending => "MultiChoice is still processing".to_string(),
+        },
+        Err(err) => err.to_string(),
+    };
+
+    record_failure(
+        pool,
+        checkout,
+        &error,
+        CheckoutStep::ConfirmationPending,
+        settings,
+    )
+    .await
+}
+
+async fn mark_confirmed(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+) -> Result<CheckoutStep, sqlx::Error> {
+    let mut db_tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE transactions SET confirm_status = 'CONFIRMED' WHERE id = $1",
+        checkout.transaction_id
+    )
+    .execute(&mut *db_tx)
+    .await?;
+
+    let next = set_step(&mut db_tx, checkout, CheckoutStep::Confirmed, None).await?;
+    db_tx.commit().await?;
+    Ok(next)
+}
+
+/// Retries a failed confirmation, or flags the checkout for refund once the
+/// attempts are exhausted.
+async fn give_up_or_retry(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+    error: &str,
+    settings: &CheckoutSettings,
+) -> Result<CheckoutStep, sqlx::Error> {
+    if checkout.attempts + 1 >= settings.max_attempts {
+        return flag_for_refund(pool, checkout, error).await;
+    }
+
+    record_failure(
+        pool,
+        checkout,
+        error,
+        CheckoutStep::RefundRequired,
+        settings,
+    )
+    .await
+}
+
+async fn flag_for_refund(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+    error: &str,
+) -> Result<CheckoutStep, sqlx::Error> {
+    let mut db_tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE transactions SET confirm_status = 'FAILED' WHERE id = $1",
+        checkout.transaction_id
+    )
+    .execute(&mut *db_tx)
+    .await?;
+
+    let next = set_step(&mut db_tx, checkout, CheckoutStep::RefundRequired, None).await?;
+    sqlx::query!(
+        "UPDATE dstv_checkouts SET last_error = $1 WHERE id = $2 AND step = $3",
+        error,
+        checkout.id,
+        next.as_str()
+    )
+    .execute(&mut *db_tx)
+    .await?;
+    db_tx.commit().await?;
+
+    tracing::error!(
+        "🚩 Checkout {} ({}) could not be confirmed and is flagged for refund: {}",
+        checkout.id,
+        checkout.merchant_tx_id,
+        error
+    );
+    Ok(next)
+}
+
+/// Moves a claimed checkout to `step` and releases it. Nothing is written if
+/// the checkout left the step it was claimed at.
+async fn set_step(
+    conn: &mut PgConnection,
+    checkout: &DstvCheckout,
+    step: CheckoutStep,
+    checkin_code: Option<&str>,
+) -> Result<CheckoutStep, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE dstv_checkouts
+        SET step = $1, checkin_code = COALESCE($2, checkin_code), attempts = 0,
+            last_error = NULL, next_attempt_at = NULL, updated_at = NOW()
+        WHERE id = $3 AND step = $4
+        "#,
+        step.as_str(),
+        checkin_code,
+        checkout.id,
+        checkout.step,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(step)
+}
+
+/// Schedules another attempt of the current step, or moves the checkout to
+/// `give_up` once the attempts are exhausted.
+async fn record_failure(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+    error: &str,
+    give_up: CheckoutStep,
+    settings: &CheckoutSettings,
+) -> Result<CheckoutStep, sqlx::Error> {
+    let attempts = checkout.attempts + 1;
+    let current = CheckoutStep::from_str(&checkout.step).unwrap_or(CheckoutStep::Created);
+    let next = if attempts >= settings.max_attempts {
+        give_up
+    } else {
+        current
+    };
+
+    tracing::warn!(
+        "🔁 Checkout {} attempt {}/{} at {} failed: {}",
+        checkout.id,
+        attempts,
+        settings.max_attempts,
+        current,
+        error
+    );
+
+    reschedule(pool, checkout, next, attempts, error, settings).await
+}
+
+/// Moves a claimed checkout to `step` and sets its next attempt after the
+/// backoff for `attempts`.
+async fn reschedule(
+    pool: &PgPool,
+    checkout: &DstvCheckout,
+    step: CheckoutStep,
+    attempts: i32,
+    error: &str,
+    settings: &CheckoutSettings,
+) -> Result<CheckoutStep, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE dstv_checkouts
+        SET step = $1, attempts = $2, last_error = $3,
+            next_attempt_at = NOW() + make_interval(secs => $4), updated_at = NOW()
+        WHERE id = $5 AND step = $6
+        "#,
+        step.as_str(),
+        attempts,
+        error,
+        settings.retry_delay_secs(attempts),
+        checkout.id,
+        checkout.step,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(step)
+}
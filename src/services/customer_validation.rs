@@ -0,0 +1,214 @@
+//! Customer validation for Quickteller billers that need it (electricity,
+//! cable TV, betting, ...).
+//!
+//! Each validation is stored for `QUICKTELLER_VALIDATION_SECS` (30 minutes
+//! by default) and pruned by [`spawn_validation_pruner`] once it expires.
+//! Before a customer of such a biller is charged, [`require_validation`]
+//! checks that the same customer was validated for the same payment item.
+//!
+//! Nothing charges those billers yet: airtime is the only Quickteller
+//! payment and needs no validation. [`require_validation`] is left for the
+//! bill payment flow to call before it sends a payment advice.
+
+use crate::models::billers::{
+    CustomerValidation, CustomerValidationRequest, SUCCESS_RESPONSE_CODE,
+};
+use crate::services::airtime::get_biller_payment_items;
+use crate::services::billers::validate_customers;
+use crate::services::quickteller_auth::TokenManager;
+use crate::utils::error::ApiError;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use std::env;
+use std::time::Duration as StdDuration;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum ValidationError {
+    #[error("Customer id is required")]
+    MissingCustomerId,
+
+    #[error("Biller {service_id} has no payment item {payment_code}")]
+    UnknownPaymentItem {
+        service_id: u32,
+        payment_code: String,
+    },
+
+    #[error("Quickteller rejected customer {customer_id} ({code}): {message}")]
+    Rejected {
+        customer_id: String,
+        code: String,
+        message: String,
+    },
+
+    #[error("Customer {customer_id} was not validated for {payment_code}")]
+    NotValidated {
+        payment_code: String,
+        customer_id: String,
+    },
+
+    #[error(transparent)]
+    Quickteller(#[from] ApiError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+fn validation_secs() -> i64 {
+    env::var("QUICKTELLER_VALIDATION_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1800)
+}
+
+/// Validates `request.customer_id` with the biller `service_id` and
+/// remembers the result.
+pub async fn validate_customer(
+    pool: &PgPool,
+    tokens: &TokenManager,
+    service_id: u32,
+    request: CustomerValidationRequest,
+) -> Result<CustomerValidation, ValidationError> {
+    let customer_id = request.customer_id.trim();
+    if customer_id.is_empty() {
+        return Err(ValidationError::MissingCustomerId);
+    }
+    let payment_code = request.payment_code.trim();
+
+    let items = tokens
+        .with_token(pool, |token| async move {
+            get_biller_payment_items(pool, &token, service_id).await
+        })
+        .await?;
+    let item = items
+        .payment_items
+        .iter()
+        .find(|item| item.payment_code == payment_code)
+        .ok_or_else(|| ValidationError::UnknownPaymentItem {
+            service_id,
+            payment_code: payment_code.to_string(),
+        })?;
+
+    let reply = tokens
+        .with_token(pool, |token| async move {
+            validate_customers(pool, &token, payment_code, customer_id).await
+        })
+        .await?;
+    let Some(customer) = reply.customers.into_iter().next() else {
+        return Err(ValidationError::Rejected {
+            customer_id: customer_id.to_string(),
+            code: reply.response_code.unwrap_or_default(),
+            message: "Quickteller returned no customer".to_string(),
+        });
+    };
+    if customer.response_code.as_deref() != Some(SUCCESS_RESPONSE_CODE) {
+        tracing::warn!(
+            "⚠️ Quickteller rejected customer {} for {}: {:?}",
+            customer_id,
+            payment_code,
+            customer.response_description
+        );
+        return Err(ValidationError::Rejected {
+            customer_id: customer_id.to_string(),
+            code: customer.response_code.unwrap_or_default(),
+            message: customer
+                .response_description
+                .unwrap_or_else(|| "Customer validation failed".to_string()),
+        });
+    }
+
+    let required_fields: Vec<String> = item.consumer_id_field.iter().cloned().collect();
+    let validation = sqlx::query_as!(
+        CustomerValidation,
+        r#"
+        INSERT INTO quickteller_customer_validations
+            (id, service_id, payment_code, customer_id, customer_name, balance, amount_type,
+             required_fields, response_code, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING *
+        "#,
+        Uuid::new_v4(),
+        service_id as i32,
+        payment_code,
+        customer_id,
+        customer.full_name,
+        customer.amount,
+        customer.amount_type_description,
+        &required_fields,
+        customer.response_code,
+        Utc::now() + Duration::seconds(validation_secs()),
+    )
+    .fetch_one(pool)
+    .await?;
+
+    tracing::info!(
+        "✅ Customer {} validated for {} as {:?}",
+        customer_id,
+        payment_code,
+        validation.customer_name
+    );
+    Ok(validation)
+}
+
+/// The latest unexpired validation of `customer_id` for `payment_code` of
+/// the biller `service_id`. Payments to billers that need validation must
+/// not go ahead without one.
+pub async fn require_validation(
+    pool: &PgPool,
+    service_id: u32,
+    payment_code: &str,
+    customer_id: &str,
+) -> Result<CustomerValidation, ValidationError> {
+    let customer_id = customer_id.trim();
+    sqlx::query_as!(
+        CustomerValidation,
+        r#"
+        SELECT * FROM quickteller_customer_validations
+        WHERE service_id = $1 AND payment_code = $2 AND customer_id = $3
+          AND expires_at > NOW()
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+        service_id as i32,
+        payment_code,
+        customer_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| ValidationError::NotValidated {
+        payment_code: payment_code.to_string(),
+        customer_id: customer_id.to_string(),
+    })
+}
+
+/// Deletes validations that have expired. Returns how many were deleted.
+pub async fn prune_customer_validations(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let deleted =
+        sqlx::query!("DELETE FROM quickteller_customer_validations WHERE expires_at <= NOW()")
+            .execute(pool)
+            .await?
+            .rows_affected();
+
+    Ok(deleted)
+}
+
+/// Prunes expired validations every `QUICKTELLER_VALIDATION_PRUNE_SECS`.
+pub fn spawn_validation_pruner(pool: PgPool) -> tokio::task::JoinHandle<()> {
+    let interval_secs = env::var("QUICKTELLER_VALIDATION_PRUNE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3600);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(StdDuration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match prune_customer_validations(&pool).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("🧹 Pruned {} expired customer validations", count),
+                Err(err) => tracing::error!("❌ Failed to prune customer validations: {}", err),
+            }
+        }
+    })
+}
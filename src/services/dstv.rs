@@ -1,370 +1,356 @@
-use crate::models::dstv::{DstvLookupRequest, DstvLookupResponse};
-use crate::utils::error::ApiError;
-use anyhow::{Context, Result};
-use base64::{engine::general_purpose, Engine as _};
-use quick_xml::de::from_str;
-use quick_xml::se::to_string;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::env;
-
-#[derive(Serialize)]
-#[serde(rename = "PayUVasRequest")]
-struct PayUVasRequest<'a> {
-    #[serde(rename = "@Ver")]
-    version: &'a str,
-    MerchantId: &'a str,
-    MerchantReference: &'a str,
-    TransactionType: &'a str,
-    VasId: &'a str,
-    CountryCode: &'a str,
-    AmountInCents: u32,
-    CustomerId: &'a str,
-    CustomFields: CustomFields<'a>,
-}
-
-#[derive(Serialize)]
-struct CustomFields<'a> {
-    #[serde(rename = "Customfield")]
-    field: Vec<CustomField<'a>>,
+use crate::config::{vas_product, VasProduct};
+use crate::models::dstv::{
+    DstvConfirmPaymentRequest, DstvConfirmPaymentResponse, DstvConfirmation, DstvErrorCode,
+    DstvLookupRequest, DstvLookupResponse,
+};
+use crate::models::payu_vas::VasOutcome;
+use crate::services::catalog::{check_basket_price, CatalogError};
+use crate::services::lookup_cache::{cached_lookup, record_refresh, store_lookup};
+use crate::services::payu_vas::{account_lookup, requery, single_payment, VasError};
+use crate::services::references::{
+    issue_reference, reference_for, ReferenceProvider, ReferencePurpose,
+};
+use axum::{extract::State, http::StatusCode, Json};
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DstvError {
+    #[error(transparent)]
+    Catalog(#[from] CatalogError),
+
+    #[error(transparent)]
+    Vas(#[from] VasError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
 }
 
-#[derive(Serialize)]
-struct CustomField<'a> {
-    #[serde(rename = "@Key")]
-    key: &'a str,
-    #[serde(rename = "@Value")]
-    value: &'a str,
-}
+impl DstvError {
+    /// The MultiChoice error behind this failure, if it came from
+    /// MultiChoice or concerns the basket price.
+    pub fn error_code(&self) -> Option<DstvErrorCode> {
+        match self {
+            DstvError::Catalog(CatalogError::PriceMismatch { .. }) => {
+                Some(DstvErrorCode::AmountMismatch)
+            }
+            DstvError::Vas(VasError::Rejected { code, message }) => {
+                Some(DstvErrorCode::from_result(code, message).unwrap_or(DstvErrorCode::Unknown))
+            }
+            DstvError::Vas(
+                VasError::Request(_)
+                | VasError::Http { .. }
+                | VasError::EmptyResponse
+                | VasError::Parse(_),
+            ) => Some(DstvErrorCode::UpstreamUnavailable),
+            DstvError::Vas(VasError::NotFound(_)) => Some(DstvErrorCode::Unknown),
+            _ => None,
+        }
+    }
 
-fn get_dstv_auth() -> String {
-    std::env::var("DSTV_AUTH").unwrap_or_else(|_| {
-        format!(
-            "Basic {}",
-            general_purpose::STANDARD.encode("test:NeRWNtWQMS")
-        )
-    })
+    /// HTTP status for a request that failed with this error.
+    fn status(&self) -> StatusCode {
+        match (self, self.error_code()) {
+            (_, Some(DstvErrorCode::UpstreamUnavailable)) => StatusCode::BAD_GATEWAY,
+            (_, Some(DstvErrorCode::DuplicateReference)) => StatusCode::CONFLICT,
+            (_, Some(DstvErrorCode::Pending)) => StatusCode::ACCEPTED,
+            (_, Some(_))
+            | (DstvError::Catalog(CatalogError::UnknownBasket(_)), None)
+            | (DstvError::Vas(VasError::Config(_) | VasError::InvalidAmount(_)), None) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            (DstvError::Vas(_), None) => StatusCode::BAD_GATEWAY,
+            (DstvError::Catalog(_) | DstvError::Database(_), None) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
 }
 
+/// Confirms a paid basket with MultiChoice after checking `amount` is the
+/// catalog price of `basket_id`. See [`confirm_basket`].
+///
+/// `amount` is in minor units of the product's currency. `product` is the
+/// VAS product id, `dstv-ng` when `None`.
 pub async fn confirm_dstv_payment(
-    merchant_reference: String,
+    pool: &PgPool,
+    product: Option<&str>,
+    transaction_reference: String,
     customer_id: String,
     basket_id: String,
-    amount: u32,
-) -> Result<String> {
-    let xml_payload = PayUVasRequest {
-        version: "1.0",
-        MerchantId: "Bluecode",
-        MerchantReference: &merchant_reference,
-        TransactionType: "SINGLE",
-        VasId: "MCA_ACCOUNT_SQ_NG",
-        CountryCode: "NG",
-        AmountInCents: amount / 100,
-        CustomerId: &customer_id,
-        CustomFields: CustomFields {
-            field: vec![CustomField {
-                key: "BasketId",
-                value: &basket_id,
-            }],
-        },
-    };
+    amount: i64,
+) -> Result<DstvConfirmation, DstvError> {
+    let product_id = vas_product(product).map_err(VasError::from)?.id;
+    check_basket_price(pool, &product_id, &basket_id, amount).await?;
+    let merchant_reference =
+        confirmation_reference(&mut *pool.acquire().await?, &transaction_reference).await?;
+    confirm_basket(
+        pool,
+        product,
+        &merchant_reference,
+        &customer_id,
+        &basket_id,
+        amount,
+    )
+    .await
+}
 
-    let xml_string = format!(
-        r#"<?xml version="1.0" encoding="UTF-8"?>{}"#,
-        to_string(&xml_payload).context("Failed to serialize XML")?
+/// The reference MultiChoice sees for the confirmation of
+/// `transaction_reference`, the same one on every retry. Pass the caller's
+/// transaction when it holds one.
+pub async fn confirmation_reference(
+    conn: &mut PgConnection,
+    transaction_reference: &str,
+) -> Result<String, sqlx::Error> {
+    let merchant_reference = reference_for(
+        conn,
+        ReferenceProvider::PayuVas,
+        ReferencePurpose::Confirmation,
+        transaction_reference,
+    )
+    .await?;
+    tracing::info!(
+        "🔖 Confirming {} as {}",
+        transaction_reference,
+        merchant_reference
     );
+    Ok(merchant_reference)
+}
 
-    tracing::info!("📤 Final XML body:\n{}", xml_string);
-
-    let client = Client::new();
-    let auth = get_dstv_auth();
-
-    let encoded = [("xml", xml_string.clone())];
-
-    let base_url = std::env::var("DSTV_BASE_URL")
-        .unwrap_or_else(|_| "https://mcapi-demo.herokuapp.com".to_string());
-    let url = format!("{}/vendor/singlepayment", base_url);
-
-    let res = client
-        .post(&url) // lowercase like curl
-        .header("Content-Type", "application/x-www-form-urlencoded") // explicit content-type
-        .header("Authorization", auth)
-        .form(&encoded)
-        .send()
-        .await;
-
-    match res {
-        Ok(res) => {
-            let status = res.status();
-            let text = res.text().await.unwrap_or_default();
-
-            tracing::info!("📡 HTTP Status: {}", status);
-            tracing::info!("📨 Raw XML from Multichoice:\n{}", text);
-
-            if status.is_success() && !text.trim().is_empty() {
-                Ok(text)
-            } else {
+/// Confirms a paid basket with MultiChoice for an `amount` whose price was
+/// checked when the customer paid, so a catalog change in between does not
+/// refuse a basket already paid for. When the payment call itself fails, or
+/// MultiChoice reports the reference as a duplicate, the transaction is
+/// requeried, since PayU may have processed it anyway. Callers must check
+/// [`DstvConfirmation::outcome`].
+///
+/// `merchant_reference` comes from [`confirmation_reference`]. `pool` only
+/// records the vendor exchanges, so no connection is held across the call.
+pub async fn confirm_basket(
+    pool: &PgPool,
+    product: Option<&str>,
+    merchant_reference: &str,
+    customer_id: &str,
+    basket_id: &str,
+    amount: i64,
+) -> Result<DstvConfirmation, DstvError> {
+    let product = vas_product(product).map_err(VasError::from)?;
+    let amount = product.money(amount);
+
+    let confirmation = match single_payment(
+        pool,
+        &product,
+        merchant_reference,
+        customer_id,
+        basket_id,
+        amount,
+    )
+    .await
+    {
+        Ok(response) => match DstvConfirmation::from(response) {
+            // Only the requery says whether the earlier submission was paid
+            duplicate if duplicate.error_code == Some(DstvErrorCode::DuplicateReference) => {
                 tracing::warn!(
-                    "❌ Confirmation failed. Status: {} - Body: {}",
-                    status,
-                    text
+                    "🔁 {} was already submitted, requerying...",
+                    merchant_reference
                 );
-                tracing::warn!("🔁 Falling back to requery...");
-                requery_dstv_confirmation(&merchant_reference).await
+                DstvConfirmation::from(requery(pool, &product, merchant_reference).await?)
             }
-        }
+            confirmation => confirmation,
+        },
         Err(err) => {
-            tracing::error!("❌ HTTP Request failed: {:?}", err);
-            tracing::warn!("🔁 Falling back to requery due to network error...");
-            requery_dstv_confirmation(&merchant_reference).await
+            tracing::warn!("❌ Confirmation failed: {}", err);
+            tracing::warn!("🔁 Falling back to requery...");
+            DstvConfirmation::from(requery(pool, &product, merchant_reference).await?)
         }
+    };
+
+    match confirmation.outcome {
+        VasOutcome::Success => tracing::info!("✅ DSTV payment {} confirmed", merchant_reference),
+        VasOutcome::Pending => tracing::warn!("⏳ DSTV payment {} is pending", merchant_reference),
+        VasOutcome::Failure => tracing::error!(
+            "❌ MultiChoice rejected {} ({}): {}",
+            merchant_reference,
+            confirmation.result_code,
+            confirmation.message
+        ),
     }
+
+    Ok(confirmation)
 }
 
-use crate::models::dstv::DstvConfirmPaymentRequest;
-use axum::{extract::State, Json};
-use sqlx::PgPool;
+/// Asks MultiChoice how the confirmation of `transaction_reference` ended,
+/// without paying again. Used while a confirmation is pending.
+pub async fn requery_dstv_confirmation(
+    pool: &PgPool,
+    product: Option<&str>,
+    transaction_reference: &str,
+) -> Result<DstvConfirmation, DstvError> {
+    let product = vas_product(product).map_err(VasError::from)?;
+    let merchant_reference = reference_for(
+        &mut *pool.acquire().await?,
+        ReferenceProvider::PayuVas,
+        ReferencePurpose::Confirmation,
+        transaction_reference,
+    )
+    .await?;
 
-pub async fn retry_dstv_confirmation(
-    State(_pool): State<PgPool>, // You can extend this if DB is needed
-    Json(body): Json<DstvConfirmPaymentRequest>,
-) -> impl axum::response::IntoResponse {
-    tracing::info!("🔁 Manual retry DSTV payment with: {:?}", body);
+    let confirmation = DstvConfirmation::from(requery(pool, &product, &merchant_reference).await?);
+    tracing::info!(
+        "🔎 Requery of {} reported {:?}",
+        merchant_reference,
+        confirmation.outcome
+    );
+    Ok(confirmation)
+}
 
-    match confirm_dstv_payment(
-        body.merchant_reference.clone(),
-        body.customer_id.clone(),
-        body.basket_id.clone(),
-        body.amount,
-    )
-    .await
-    {
-        Ok(xml_response) => {
-            tracing::info!("✅ Manual retry success: {}", xml_response);
-            Json({
-                serde_json::json!({
-                    "success": true,
-                    "message": "Manual confirmation sent.",
-                    "raw_xml": xml_response
-                })
-            })
+/// HTTP reply for a confirmation attempt: 200 when confirmed, 202 while
+/// pending, 422 when MultiChoice refused it and 502 when it could not be
+/// reached. Failures carry a [`DstvErrorCode`] and its customer message.
+pub fn confirmation_reply(
+    result: Result<DstvConfirmation, DstvError>,
+) -> (StatusCode, Json<DstvConfirmPaymentResponse>) {
+    match result {
+        Ok(confirmation) => {
+            let status = match confirmation.outcome {
+                VasOutcome::Success => StatusCode::OK,
+                VasOutcome::Pending => StatusCode::ACCEPTED,
+                VasOutcome::Failure => StatusCode::UNPROCESSABLE_ENTITY,
+            };
+            let message = match confirmation.error_code {
+                None => "Payment confirmed successfully",
+                Some(code) => code.customer_message(),
+            };
+            (
+                status,
+                Json(DstvConfirmPaymentResponse {
+                    success: confirmation.outcome == VasOutcome::Success,
+                    outcome: Some(confirmation.outcome),
+                    error_code: confirmation.error_code,
+                    confirmation: Some(confirmation),
+                    message: message.to_string(),
+                }),
+            )
         }
         Err(err) => {
-            tracing::error!("❌ Manual retry failed: {:?}", err);
-            Json({
-                serde_json::json!({
-                    "success": false,
-                    "message": "Manual retry failed."
-                })
-            })
+            tracing::error!("❌ Failed to confirm DSTV payment: {}", err);
+            let error_code = err.error_code();
+            let message = match (&err, error_code) {
+                (_, Some(code)) => code.customer_message().to_string(),
+                (
+                    DstvError::Catalog(CatalogError::UnknownBasket(_))
+                    | DstvError::Vas(VasError::Config(_) | VasError::InvalidAmount(_)),
+                    None,
+                ) => err.to_string(),
+                _ => "DSTV payment confirmation failed".to_string(),
+            };
+            (
+                err.status(),
+                Json(DstvConfirmPaymentResponse {
+                    success: false,
+                    outcome: None,
+                    confirmation: None,
+                    message,
+                    error_code,
+                }),
+            )
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct RequeryItem {
-    merchantreference: String,
-    smartcard: String,
-    status: i32,
-    basketid: String,
-}
-
-async fn requery_dstv_confirmation(reference: &str) -> Result<String> {
-    let client = Client::new();
-    let base_url = std::env::var("DSTV_BASE_URL")
-        .unwrap_or_else(|_| "https://mcapi-demo.herokuapp.com".to_string());
-    let url = format!("{}/transactions/single/{}", base_url, reference);
-    //let url = format!("https://mcapi-demo.herokuapp.com/transactions/single/{}",reference);
-
-    let auth = get_dstv_auth();
-    let response = client
-        .get(&url)
-        .header("Authorization", auth)
-        .send()
-        .await
-        .context("Failed to send requery request")?
-        .text()
-        .await?;
-
-    tracing::info!("📥 Raw requery response: {}", response);
-
-    if response.trim().is_empty() {
-        tracing::error!("❌ Requery response was empty");
-        return Err(anyhow::anyhow!("Empty requery response"));
-    }
-
-    let items: Vec<RequeryItem> =
-        serde_json::from_str(&response).context("Failed to parse requery JSON")?;
-
-    if let Some(item) = items.first() {
-        tracing::info!("✅ Requery result: {:?}", item);
-        if item.status == 1 {
-            // ✅ Success
-            Ok(response)
-        } else if item.status == -1 {
-            // ⏳ Pending, but not a hard failure
-            Err(anyhow::anyhow!("Transaction is still pending"))
-        } else {
-            // ❌ Failure
-            Err(anyhow::anyhow!(
-                "Requery returned failed status: {}",
-                item.status
-            ))
+/// HTTP reply for a smartcard lookup: 200 with the account, or the status
+/// and [`DstvErrorCode`] of the failure.
+pub fn lookup_reply(
+    result: Result<DstvLookupResponse, DstvError>,
+) -> (StatusCode, Json<DstvLookupResponse>) {
+    match result {
+        Ok(lookup) => (StatusCode::OK, Json(lookup)),
+        Err(err) => {
+            tracing::error!("❌ DSTV lookup failed: {}", err);
+            let error_code = err.error_code();
+            let message = match (&err, error_code) {
+                (_, Some(code)) => code.customer_message().to_string(),
+                (DstvError::Vas(VasError::Config(_)), None) => err.to_string(),
+                _ => "Lookup failed".to_string(),
+            };
+            (
+                err.status(),
+                Json(DstvLookupResponse {
+                    account_name: None,
+                    customer_id: None,
+                    message,
+                    success: false,
+                    custom_fields: None,
+                    error_code,
+                }),
+            )
         }
-    } else {
-        Err(anyhow::anyhow!("Requery returned empty array"))
     }
 }
 
-pub async fn lookup_dstv_account(req: DstvLookupRequest) -> Result<DstvLookupResponse, ApiError> {
-    let client = Client::new();
-    let merchant_id = std::env::var("DSTV_MERCHANT_ID").unwrap_or_else(|_| "test".to_string());
-    let base_url = std::env::var("DSTV_BASE_URL")
-        .unwrap_or_else(|_| "https://mcapi-demo.herokuapp.com".to_string());
-    let url = format!("{}/vendor/lookup", base_url);
-
-    //let url = std::env::var("DSTV_LOOKUP_URL").unwrap_or_else(|_| "https://mcapi-demo.herokuapp.com/vendor/lookup".to_string());
-
-    let xml = format!(
-        r#"<PayUVasRequest>
-            <MerchantId>{}</MerchantId>
-            <MerchantReference>ref-334592934</MerchantReference>
-            <TransactionType>ACCOUNT_LOOKUP</TransactionType>
-            <VasId>MCA_ACCOUNT_SQ_NG</VasId>
-            <CountryCode>NG</CountryCode>
-            <CustomerId>{}</CustomerId>
-        </PayUVasRequest>"#,
-        merchant_id, req.customer_id
-    );
-
-    tracing::info!("🧾  URL: {}", url);
-    tracing::info!("🧾 Merchant ID: {}", merchant_id);
-    tracing::info!("🧾 XML: {}", xml);
-
-    let encoded = [("xml", xml)];
-
-    let auth = get_dstv_auth();
-
-    let response = client
-        .post(url)
-        .header("Authorization", auth)
-        .form(&encoded)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    if response.trim().is_empty() {
-        tracing::warn!("Requery response is empty – likely a backend outage or invalid request");
-        return Err(ApiError::InternalServerError.into());
-    }
-
-    println!("💬 DSTV API Raw XML Response:\n{}", response);
+/// Manual retry of a confirmation, answered like `/dstv/confirm-payment`
+/// (see [`confirmation_reply`]) rather than with the 200 it always returned.
+pub async fn retry_dstv_confirmation(
+    State(pool): State<PgPool>,
+    Json(body): Json<DstvConfirmPaymentRequest>,
+) -> impl axum::response::IntoResponse {
+    tracing::info!("🔁 Manual retry DSTV payment with: {:?}", body);
 
-    #[derive(Debug, Deserialize)]
-    struct CustomField {
-        #[serde(rename = "@Key")]
-        key: String,
-        #[serde(rename = "@Value")]
-        value: String,
-    }
+    let result = confirm_dstv_payment(
+        &pool,
+        body.product.as_deref(),
+        body.merchant_reference,
+        body.customer_id,
+        body.basket_id,
+        body.amount,
+    )
+    .await;
 
-    #[derive(Debug, Deserialize)]
-    struct PayUVasResponse {
-        #[serde(rename = "ResultCode")]
-        result_code: String,
-        #[serde(rename = "ResultMessage")]
-        result_message: String,
-        #[serde(rename = "CustomFields")]
-        custom_fields: Option<CustomFields>,
-    }
+    confirmation_reply(result)
+}
 
-    #[derive(Debug, Deserialize)]
-    struct CustomFields {
-        #[serde(rename = "Customfield")]
-        fields: Vec<CustomField>,
+/// Looks up a smartcard, answering from the lookup cache unless
+/// `req.refresh` is set.
+pub async fn lookup_dstv_account(
+    pool: &PgPool,
+    req: DstvLookupRequest,
+) -> Result<DstvLookupResponse, DstvError> {
+    let product = vas_product(req.product.as_deref()).map_err(VasError::from)?;
+    if req.refresh {
+        record_refresh();
+    } else if let Some(cached) = cached_lookup(pool, &product.id, &req.customer_id).await {
+        tracing::info!("📦 DSTV lookup for {} served from cache", req.customer_id);
+        return cached;
     }
 
-    let parsed: PayUVasResponse = from_str(&response).map_err(|e| {
-        println!("❌ XML Parse Error: {:?}", e);
-        ApiError::InternalServerError
-    })?;
-
-    let mut account_name = None;
-    let mut customer_id = None;
-    let mut extracted_fields = HashMap::new();
+    let result = fetch_dstv_account(pool, &product, &req.customer_id).await;
+    store_lookup(pool, &product.id, &req.customer_id, &result).await;
+    result
+}
 
-    if let Some(customs) = parsed.custom_fields {
-        for field in customs.fields {
-            extracted_fields.insert(field.key.clone(), field.value.clone());
+async fn fetch_dstv_account(
+    pool: &PgPool,
+    product: &VasProduct,
+    customer_id: &str,
+) -> Result<DstvLookupResponse, DstvError> {
+    let merchant_reference = issue_reference(
+        &mut *pool.acquire().await?,
+        ReferenceProvider::PayuVas,
+        ReferencePurpose::Lookup,
+        None,
+    )
+    .await?;
+    let response = account_lookup(pool, product, &merchant_reference, customer_id).await?;
 
-            match field.key.as_str() {
-                "SURNAME" => account_name = Some(field.value.clone()),
-                "DSTV_CUSTOMER_NUMBER" => customer_id = Some(field.value.clone()),
-                _ => {}
-            }
-        }
-    }
-    tracing::info!("✅ Custom Fields: {:?}", extracted_fields);
+    let custom_fields = response.fields();
+    tracing::info!("✅ Custom Fields: {:?}", custom_fields);
 
     Ok(DstvLookupResponse {
-        account_name,
-        customer_id,
+        account_name: response.field("SURNAME").map(str::to_string),
+        customer_id: response.field("DSTV_CUSTOMER_NUMBER").map(str::to_string),
         message: "Success".to_string(),
         success: true,
-        custom_fields: Some(extracted_fields),
+        custom_fields: Some(custom_fields),
+        error_code: None,
     })
 }
-
-#[derive(Serialize)]
-pub struct SinglePaymentRequest {
-    pub amount: i64,
-    pub customer_id: String,
-    pub product_code: String,
-    pub merchant_reference: String,
-}
-
-pub async fn pay_dstv_bill(req: SinglePaymentRequest) -> Result<String, ApiError> {
-    let client = Client::new();
-    let merchant_id = std::env::var("DSTV_MERCHANT_ID").unwrap_or_else(|_| "test".to_string());
-
-    let base_url =
-        env::var("DSTV_BASE_URL").map_err(|_| ApiError::EnvVarMissing("DSTV_BASE_URL".into()))?;
-    let url = format!("{}/vendor/singlepayment", base_url);
-    //let url = std::env::var("DSTV_PAYMENT_URL").unwrap_or_else(|_| "https://mcapi-demo.herokuapp.com/vendor/singlepayment".to_string());
-
-    let xml = format!(
-        r#"<PayUVasRequest>
-            <MerchantId>{}</MerchantId>
-            <MerchantReference>{}</MerchantReference>
-            <TransactionType>SINGLE_PAYMENT</TransactionType>
-            <VasId>MCA_ACCOUNT_SQ_NG</VasId>
-            <CountryCode>NG</CountryCode>
-            <CustomerId>{}</CustomerId>
-            <Amount>{}</Amount>
-            <ProductCode>{}</ProductCode>
-        </PayUVasRequest>"#,
-        merchant_id, req.merchant_reference, req.customer_id, req.amount, req.product_code
-    );
-
-    use std::fs;
-    fs::write("debug_outgoing.xml", &xml).unwrap();
-
-    let encoded = [("xml", xml)];
-
-    let auth = get_dstv_auth();
-
-    let response = client
-        .post(url)
-        .header("Authorization", auth)
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .form(&encoded)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    tracing::info!("✅ Payment response XML: {}", response);
-    Ok(response)
-}
@@ -0,0 +1,265 @@
+//! Cache of DSTV smartcard lookups, keyed by VAS product and customer id.
+//!
+//! Lookups are kept in memory for `DSTV_LOOKUP_CACHE_SECS` (0 disables the
+//! cache). With `DSTV_LOOKUP_CACHE_STORE=postgres` they are also written to
+//! `dstv_lookup_cache`, so every instance shares them. Only definitive
+//! MultiChoice rejections (invalid or suspended smartcards, ...) are cached,
+//! for `DSTV_LOOKUP_FAILURE_CACHE_SECS`; outages and transport errors never
+//! are. The cache never fails a lookup: storage errors are logged and the
+//! lookup goes to MultiChoice.
+
+use crate::models::dstv::{DstvErrorCode, DstvLookupResponse};
+use crate::services::dstv::DstvError;
+use crate::services::payu_vas::VasError;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Memory entries kept before expired ones are swept
+const SWEEP_AFTER_ENTRIES: usize = 10_000;
+
+/// `(vas_product, customer_id)`
+type CacheKey = (String, String);
+
+static CACHE: RwLock<Option<HashMap<CacheKey, CachedLookup>>> = RwLock::new(None);
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static STORE_HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static REFRESHES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone)]
+pub struct LookupCacheSettings {
+    pub ttl_secs: i64,
+    pub failure_ttl_secs: i64,
+    /// Also keep lookups in Postgres
+    pub persist: bool,
+}
+
+impl LookupCacheSettings {
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        LookupCacheSettings {
+            ttl_secs: var("DSTV_LOOKUP_CACHE_SECS", 300),
+            failure_ttl_secs: var("DSTV_LOOKUP_FAILURE_CACHE_SECS", 30),
+            persist: env::var("DSTV_LOOKUP_CACHE_STORE")
+                .is_ok_and(|v| v.eq_ignore_ascii_case("postgres")),
+        }
+    }
+}
+
+/// What MultiChoice answered, as cached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "SCREAMING_SNAKE_CASE")]
+enum CachedOutcome {
+    Found(DstvLookupResponse),
+    Rejected { code: String, message: String },
+}
+
+impl CachedOutcome {
+    fn into_result(self) -> Result<DstvLookupResponse, DstvError> {
+        match self {
+            CachedOutcome::Found(lookup) => Ok(lookup),
+            CachedOutcome::Rejected { code, message } => {
+                Err(DstvError::Vas(VasError::Rejected { code, message }))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedLookup {
+    outcome: CachedOutcome,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LookupCacheStats {
+    /// Lookups answered from memory
+    pub hits: u64,
+    /// Lookups answered from Postgres
+    pub store_hits: u64,
+    /// Lookups sent to MultiChoice because nothing was cached
+    pub misses: u64,
+    /// Lookups sent to MultiChoice because a refresh was asked for
+    pub refreshes: u64,
+    /// Entries in memory, expired ones included until they are swept
+    pub entries: usize,
+}
+
+/// The cached answer for `customer_id`, if it has not expired.
+pub async fn cached_lookup(
+    pool: &PgPool,
+    vas_product: &str,
+    customer_id: &str,
+) -> Option<Result<DstvLookupResponse, DstvError>> {
+    let settings = LookupCacheSettings::from_env();
+    if settings.ttl_secs <= 0 {
+        return None;
+    }
+
+    let key = (vas_product.to_string(), customer_id.to_string());
+    let now = Utc::now();
+    let in_memory = CACHE
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|cache| cache.get(&key))
+        .filter(|entry| entry.expires_at > now)
+        .map(|entry| entry.outcome.clone());
+    if let Some(outcome) = in_memory {
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return Some(outcome.into_result());
+    }
+
+    if settings.persist {
+        match load_stored(pool, &key).await {
+            Ok(Some(entry)) => {
+                STORE_HITS.fetch_add(1, Ordering::Relaxed);
+                let outcome = entry.outcome.clone();
+                remember(key, entry);
+                return Some(outcome.into_result());
+            }
+            Ok(None) => {}
+            Err(err) => tracing::warn!("⚠️ Failed to read DSTV lookup cache: {}", err),
+        }
+    }
+
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    None
+}
+
+/// Counts a lookup that skipped the cache on purpose.
+pub fn record_refresh() {
+    REFRESHES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Caches the result of a lookup sent to MultiChoice, if it may be cached.
+pub async fn store_lookup(
+    pool: &PgPool,
+    vas_product: &str,
+    customer_id: &str,
+    result: &Result<DstvLookupResponse, DstvError>,
+) {
+    let settings = LookupCacheSettings::from_env();
+    if settings.ttl_secs <= 0 {
+        return;
+    }
+
+    let (outcome, ttl_secs) = match result {
+        Ok(lookup) if lookup.success => (CachedOutcome::Found(lookup.clone()), settings.ttl_secs),
+        Err(err @ DstvError::Vas(VasError::Rejected { code, message }))
+            if !matches!(
+                err.error_code(),
+                Some(DstvErrorCode::UpstreamUnavailable | DstvErrorCode::Pending)
+            ) =>
+        {
+            (
+                CachedOutcome::Rejected {
+                    code: code.clone(),
+                    message: message.clone(),
+                },
+                settings.failure_ttl_secs,
+            )
+        }
+        _ => return,
+    };
+    if ttl_secs <= 0 {
+        return;
+    }
+
+    let key = (vas_product.to_string(), customer_id.to_string());
+    let entry = CachedLookup {
+        outcome,
+        expires_at: Utc::now() + Duration::seconds(ttl_secs),
+    };
+
+    if settings.persist {
+        if let Err(err) = save_stored(pool, &key, &entry).await {
+            tracing::warn!("⚠️ Failed to store DSTV lookup in cache: {}", err);
+        }
+    }
+    remember(key, entry);
+}
+
+fn remember(key: CacheKey, entry: CachedLookup) {
+    let mut cache = CACHE.write().unwrap();
+    let cache = cache.get_or_insert_with(HashMap::new);
+    if cache.len() >= SWEEP_AFTER_ENTRIES {
+        let now = Utc::now();
+        cache.retain(|_, entry| entry.expires_at > now);
+    }
+    cache.insert(key, entry);
+}
+
+async fn load_stored(pool: &PgPool, key: &CacheKey) -> Result<Option<CachedLookup>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT outcome AS "outcome: Json<CachedOutcome>", expires_at
+        FROM dstv_lookup_cache
+        WHERE vas_product = $1 AND customer_id = $2 AND expires_at > NOW()
+        "#,
+        key.0,
+        key.1
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|row| CachedLookup {
+        outcome: row.outcome.0,
+        expires_at: row.expires_at,
+    }))
+}
+
+async fn save_stored(
+    pool: &PgPool,
+    key: &CacheKey,
+    entry: &CachedLookup,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO dstv_lookup_cache (vas_product, customer_id, outcome, expires_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (vas_product, customer_id)
+        DO UPDATE SET outcome = EXCLUDED.outcome, expires_at = EXCLUDED.expires_at, created_at = NOW()
+        "#,
+        key.0,
+        key.1,
+        Json(&entry.outcome) as _,
+        entry.expires_at,
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Drops every cached lookup, in memory and in Postgres.
+pub async fn clear_lookup_cache(pool: &PgPool) -> Result<(), sqlx::Error> {
+    *CACHE.write().unwrap() = None;
+    sqlx::query!("DELETE FROM dstv_lookup_cache")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub fn lookup_cache_stats() -> LookupCacheStats {
+    LookupCacheStats {
+        hits: HITS.load(Ordering::Relaxed),
+        store_hits: STORE_HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        refreshes: REFRESHES.load(Ordering::Relaxed),
+        entries: CACHE.read().unwrap().as_ref().map_or(0, HashMap::len),
+    }
+}
@@ -1,5 +1,22 @@
 pub mod airtime;
+pub mod airtime_topup;
 pub mod billers;
 pub mod bluecode;
+pub mod bluecode_poller;
+pub mod bluecode_qr;
+pub mod bluecode_webhook;
+pub mod bulk_renewals;
+pub mod catalog;
+pub mod checkout;
+pub mod customer_validation;
 pub mod dstv;
+pub mod lookup_cache;
 pub mod payments;
+pub mod payu_vas;
+pub mod quickteller_auth;
+pub mod quickteller_requery;
+pub mod references;
+pub mod refunds;
+pub mod subscriptions;
+pub mod transactions;
+pub mod vendor_exchanges;
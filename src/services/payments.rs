@@ -1,9 +1,51 @@
-use crate::models::payments::PaymentRequest;
-use reqwest::Client;
+use crate::models::money::{Currency, MoneyError};
+use crate::models::payments::{PaymentRequest, QuicktellerPaymentRequest};
+use crate::services::vendor_exchanges::{Exchange, VendorProvider};
+use reqwest::{header, Client};
+use sqlx::PgPool;
+use thiserror::Error;
 
-pub async fn process_payment(payment: PaymentRequest) -> Result<String, reqwest::Error> {
-    let client = Client::new();
+#[derive(Error, Debug)]
+pub enum PaymentError {
+    #[error("Request error: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Serialize error: {0}")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(#[from] MoneyError),
+}
+
+/// Quickteller only settles NGN, in kobo.
+pub fn quickteller_request(
+    payment: &PaymentRequest,
+) -> Result<QuicktellerPaymentRequest, PaymentError> {
+    payment.amount.expect_currency(Currency::Ngn)?;
+
+    Ok(QuicktellerPaymentRequest {
+        biller_id: payment.biller_id.clone(),
+        amount: payment.amount.minor(),
+    })
+}
+
+pub async fn process_payment(
+    pool: &PgPool,
+    payment: PaymentRequest,
+) -> Result<String, PaymentError> {
+    let request = quickteller_request(&payment)?;
     let url = "https://api.example.com/pay";
-    let response = client.post(url).json(&payment).send().await?;
-    Ok(response.text().await?)
+    let body = serde_json::to_string(&request)?;
+
+    let exchange = Exchange::start(pool, VendorProvider::Quickteller, "payment", "POST", url)
+        .request_body(&body);
+    let (_, response) = exchange
+        .send(
+            Client::new()
+                .post(url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(body),
+        )
+        .await?;
+    Ok(response)
 }
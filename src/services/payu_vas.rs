@@ -0,0 +1,234 @@
+//! Client for the PayU VAS API that fronts MultiChoice (DSTV, GOtv, ...).
+//!
+//! Merchant settings and endpoints come from the [`VasProduct`] being sold.
+//! Every request is serialized from [`PayUVasRequest`], so user-supplied
+//! values such as smartcard numbers are always XML-escaped, and every
+//! response is parsed into [`PayUVasResponse`]. Every call is recorded in
+//! the vendor audit log under its merchant reference.
+//!
+//! Despite its name, `AmountInCents` is in the minor unit of the product's
+//! currency (kobo for NGN), so [`Money::minor`] is sent as is.
+
+use crate::config::{ConfigError, VasProduct};
+use crate::models::money::{Money, MoneyError};
+use crate::models::payu_vas::{
+    PayUVasRequest, PayUVasResponse, VasCustomFields, VasRequeryItem, VasTransactionType,
+};
+use crate::services::vendor_exchanges::{Exchange, VendorProvider};
+use quick_xml::de::from_str;
+use quick_xml::se::to_string;
+use reqwest::Client;
+use sqlx::PgPool;
+use std::sync::OnceLock;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum VasError {
+    #[error("Request error: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("Failed to serialize VAS request: {0}")]
+    Serialize(#[from] quick_xml::SeError),
+
+    #[error("Failed to parse VAS response: {0}")]
+    Parse(String),
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(#[from] MoneyError),
+
+    #[error("PayU returned an empty response")]
+    EmptyResponse,
+
+    #[error("PayU returned HTTP {status}: {body}")]
+    Http { status: u16, body: String },
+
+    #[error("PayU rejected the request ({code}): {message}")]
+    Rejected { code: String, message: String },
+
+    #[error("Requery found no transaction {0}")]
+    NotFound(String),
+}
+
+/// Builds a request for `product`.
+pub fn vas_request(
+    product: &VasProduct,
+    transaction_type: VasTransactionType,
+    merchant_reference: &str,
+    customer_id: &str,
+    amount_in_cents: Option<u32>,
+    custom_fields: Option<VasCustomFields>,
+) -> PayUVasRequest {
+    PayUVasRequest {
+        version: "1.0".to_string(),
+        merchant_id: product.merchant_id.clone(),
+        merchant_reference: merchant_reference.to_string(),
+        transaction_type,
+        vas_id: product.vas_id.clone(),
+        country_code: product.country_code.clone(),
+        amount_in_cents,
+        customer_id: customer_id.to_string(),
+        custom_fields,
+    }
+}
+
+pub fn to_xml(request: &PayUVasRequest) -> Result<String, VasError> {
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>{}"#,
+        to_string(request)?
+    ))
+}
+
+pub fn parse_response(body: &str) -> Result<PayUVasResponse, VasError> {
+    if body.trim().is_empty() {
+        return Err(VasError::EmptyResponse);
+    }
+    from_str(body).map_err(|e| VasError::Parse(e.to_string()))
+}
+
+/// `ACCOUNT_LOOKUP` for a smartcard or customer number.
+pub async fn account_lookup(
+    pool: &PgPool,
+    product: &VasProduct,
+    merchant_reference: &str,
+    customer_id: &str,
+) -> Result<PayUVasResponse, VasError> {
+    let request = vas_request(
+        product,
+        VasTransactionType::AccountLookup,
+        merchant_reference,
+        customer_id,
+        None,
+        None,
+    );
+    let response = send(pool, product, &product.lookup_url(), &request).await?;
+
+    if !response.is_success() {
+        return Err(VasError::Rejected {
+            code: response.result_code,
+            message: response.result_message,
+        });
+    }
+    Ok(response)
+}
+
+/// `SINGLE` payment of a basket. `amount` must be in the product's currency.
+/// The response is returned whatever its result code; see
+/// [`PayUVasResponse::outcome`].
+pub async fn single_payment(
+    pool: &PgPool,
+    product: &VasProduct,
+    merchant_reference: &str,
+    customer_id: &str,
+    basket_id: &str,
+    amount: Money,
+) -> Result<PayUVasResponse, VasError> {
+    let request = payment_request(product, merchant_reference, customer_id, basket_id, amount)?;
+    send(pool, product, &product.payment_url(), &request).await
+}
+
+/// Builds the `SINGLE` request for `amount`, checking it fits
+/// `AmountInCents`.
+pub fn payment_request(
+    product: &VasProduct,
+    merchant_reference: &str,
+    customer_id: &str,
+    basket_id: &str,
+    amount: Money,
+) -> Result<PayUVasRequest, VasError> {
+    amount.expect_currency(product.currency)?;
+    if !amount.is_positive() {
+        return Err(MoneyError::OutOfRange(amount.to_string()).into());
+    }
+
+    Ok(vas_request(
+        product,
+        VasTransactionType::Single,
+        merchant_reference,
+        customer_id,
+        Some(amount.minor_u32()?),
+        Some(VasCustomFields::single("BasketId", basket_id)),
+    ))
+}
+
+/// Looks up a payment; see
+/// [`VasOutcome::from_requery_status`](crate::models::payu_vas::VasOutcome::from_requery_status).
+pub async fn requery(
+    pool: &PgPool,
+    product: &VasProduct,
+    merchant_reference: &str,
+) -> Result<VasRequeryItem, VasError> {
+    let url = product.requery_url(merchant_reference)?;
+    let exchange = Exchange::start(pool, VendorProvider::PayuVas, "requery", "GET", &url)
+        .correlation_id(merchant_reference);
+
+    let (status, body) = exchange
+        .send(
+            http_client()
+                .get(&url)
+                .header("Authorization", product.auth_header()),
+        )
+        .await?;
+
+    if !status.is_success() {
+        return Err(VasError::Http {
+            status: status.as_u16(),
+            body,
+        });
+    }
+    if body.trim().is_empty() {
+        return Err(VasError::EmptyResponse);
+    }
+
+    let items: Vec<VasRequeryItem> =
+        serde_json::from_str(&body).map_err(|e| VasError::Parse(e.to_string()))?;
+    let item = items
+        .into_iter()
+        .next()
+        .ok_or_else(|| VasError::NotFound(merchant_reference.to_string()))?;
+
+    tracing::info!("✅ Requery result: {:?}", item);
+    Ok(item)
+}
+
+/// Shared so connections to PayU are reused from one call to the next.
+fn http_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(Client::new)
+}
+
+async fn send(
+    pool: &PgPool,
+    product: &VasProduct,
+    url: &str,
+    request: &PayUVasRequest,
+) -> Result<PayUVasResponse, VasError> {
+    let xml = to_xml(request)?;
+    let operation = match request.transaction_type {
+        VasTransactionType::AccountLookup => "account_lookup",
+        VasTransactionType::Single => "single_payment",
+    };
+    let exchange = Exchange::start(pool, VendorProvider::PayuVas, operation, "POST", url)
+        .correlation_id(&request.merchant_reference)
+        .request_body(&xml);
+
+    let (status, body) = exchange
+        .send(
+            http_client()
+                .post(url)
+                .header("Authorization", product.auth_header())
+                .form(&[("xml", xml)]),
+        )
+        .await?;
+
+    if !status.is_success() {
+        return Err(VasError::Http {
+            status: status.as_u16(),
+            body,
+        });
+    }
+
+    parse_response(&body)
+}
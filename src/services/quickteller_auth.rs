@@ -0,0 +1,234 @@
+//! OAuth access tokens for the Quickteller APIs.
+//!
+//! One [`TokenManager`], created at startup, serves every call: handlers
+//! receive it as an axum `Extension` and background workers are handed a
+//! clone. Tokens are reused until
+//! shortly before `expires_in`; once they enter the last
+//! `QUICKTELLER_TOKEN_REFRESH_SECS` of their life a replacement is fetched in
+//! the background while the current one keeps being served. Only one fetch
+//! runs at a time, however many callers are waiting. Client credentials and
+//! tokens are never logged.
+
+use crate::services::vendor_exchanges::{Exchange, VendorProvider};
+use crate::utils::error::ApiError;
+use base64::engine::general_purpose;
+use base64::Engine;
+use reqwest::Client;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+
+const DEFAULT_PASSPORT_URL: &str = "https://apps.qa.interswitchng.com/passport/oauth/token";
+
+/// Tokens are dropped this long before Quickteller expires them, so a token
+/// never expires in flight
+const EXPIRY_SKEW: Duration = Duration::from_secs(30);
+
+fn passport_url() -> String {
+    env::var("QUICKTELLER_PASSPORT_URL").unwrap_or_else(|_| DEFAULT_PASSPORT_URL.to_string())
+}
+
+fn scope() -> String {
+    env::var("QUICKTELLER_SCOPE").unwrap_or_else(|_| "profile".to_string())
+}
+
+fn refresh_ahead() -> Duration {
+    let secs = env::var("QUICKTELLER_TOKEN_REFRESH_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300);
+    Duration::from_secs(secs)
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// Seconds; Quickteller tokens usually last a day
+    expires_in: Option<u64>,
+}
+
+#[derive(Clone)]
+struct CachedToken {
+    access_token: String,
+    /// Served until then, refreshed in the background after `refresh_at`
+    expires_at: Instant,
+    refresh_at: Instant,
+}
+
+impl CachedToken {
+    fn new(response: TokenResponse) -> Self {
+        let now = Instant::now();
+        let lifetime =
+            Duration::from_secs(response.expires_in.unwrap_or(3600)).saturating_sub(EXPIRY_SKEW);
+        CachedToken {
+            access_token: response.access_token,
+            expires_at: now + lifetime,
+            refresh_at: now + lifetime.saturating_sub(refresh_ahead()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    token: RwLock<Option<CachedToken>>,
+    /// Held while a token is being fetched
+    refreshing: Mutex<()>,
+}
+
+/// Caches one Quickteller access token and refreshes it.
+#[derive(Clone, Default)]
+pub struct TokenManager {
+    inner: Arc<Inner>,
+}
+
+impl TokenManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A valid token, fetched only if none is cached or it has expired.
+    pub async fn token(&self, pool: &PgPool) -> Result<String, ApiError> {
+        let now = Instant::now();
+        if let Some(cached) = self.inner.token.read().await.clone() {
+            if now < cached.expires_at {
+                if now >= cached.refresh_at {
+                    self.refresh_in_background(pool);
+                }
+                return Ok(cached.access_token);
+            }
+        }
+
+        self.refresh(pool, None).await
+    }
+
+    /// Drops `rejected`, which an upstream answered with 401, and returns a
+    /// fresh token. Callers that hit 401 together share one refresh.
+    pub async fn refresh_after_unauthorized(
+        &self,
+        pool: &PgPool,
+        rejected: &str,
+    ) -> Result<String, ApiError> {
+        tracing::warn!("🔑 Quickteller rejected the access token, refreshing it");
+        self.refresh(pool, Some(rejected)).await
+    }
+
+    /// Forgets the cached token.
+    pub async fn clear(&self) {
+        *self.inner.token.write().await = None;
+    }
+
+    /// Fetches a token unless another task did while this one waited for the
+    /// lock. `stale` is a token known to be bad, never returned again.
+    async fn refresh(&self, pool: &PgPool, stale: Option<&str>) -> Result<String, ApiError> {
+        let seen = self.cached_token().await;
+        let _guard = self.inner.refreshing.lock().await;
+
+        if let Some(cached) = self.inner.token.read().await.as_ref() {
+            let now = Instant::now();
+            let replaced = seen.as_deref() != Some(cached.access_token.as_str());
+            let usable = now < cached.expires_at && stale != Some(cached.access_token.as_str());
+            if usable && (replaced || now < cached.refresh_at) {
+                return Ok(cached.access_token.clone());
+            }
+        }
+
+        let cached = CachedToken::new(fetch_token(pool).await?);
+        let access_token = cached.access_token.clone();
+        *self.inner.token.write().await = Some(cached);
+        Ok(access_token)
+    }
+
+    async fn cached_token(&self) -> Option<String> {
+        self.inner
+            .token
+            .read()
+            .await
+            .as_ref()
+            .map(|cached| cached.access_token.clone())
+    }
+
+    fn refresh_in_background(&self, pool: &PgPool) {
+        // A refresh already running will replace the token
+        if self.inner.refreshing.try_lock().is_err() {
+            return;
+        }
+
+        let manager = self.clone();
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            match manager.refresh(&pool, None).await {
+                Ok(_) => tracing::info!("🔑 Quickteller access token refreshed ahead of expiry"),
+                Err(err) => tracing::warn!("⚠️ Quickteller token refresh failed: {}", err),
+            }
+        });
+    }
+
+    /// Runs `call` with the current token. When it fails with
+    /// [`ApiError::Unauthorized`] the token is refreshed and `call` runs once
+    /// more.
+    pub async fn with_token<T, F, Fut>(&self, pool: &PgPool, call: F) -> Result<T, ApiError>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let token = self.token(pool).await?;
+        match call(token.clone()).await {
+            Err(ApiError::Unauthorized(_)) => {
+                let token = self.refresh_after_unauthorized(pool, &token).await?;
+                call(token).await
+            }
+            result => result,
+        }
+    }
+}
+
+async fn fetch_token(pool: &PgPool) -> Result<TokenResponse, ApiError> {
+    let client_id = env::var("QUICKTELLER_CLIENT_ID")
+        .map_err(|_| ApiError::EnvVarMissing("QUICKTELLER_CLIENT_ID".into()))?;
+    let secret_key = env::var("QUICKTELLER_SECRET_KEY")
+        .map_err(|_| ApiError::EnvVarMissing("QUICKTELLER_SECRET_KEY".into()))?;
+
+    let credentials = format!("{}:{}", client_id, secret_key);
+    let auth_header = format!("Basic {}", general_purpose::STANDARD.encode(credentials));
+
+    let url = passport_url();
+    let scope = scope();
+    let form = [("grant_type", "client_credentials"), ("scope", &scope)];
+    let exchange = Exchange::start(
+        pool,
+        VendorProvider::Quickteller,
+        "access_token",
+        "POST",
+        &url,
+    )
+    .request_body(&format!("grant_type=client_credentials&scope={}", scope));
+    let (status, text) = exchange
+        .send(
+            Client::new()
+                .post(&url)
+                .header("Authorization", &auth_header)
+                .form(&form),
+        )
+        .await
+        .map_err(ApiError::RequestError)?;
+
+    // The body holds the token, so only the redacted audit copy is kept
+    if !status.is_success() {
+        tracing::error!("❌ Quickteller token request failed. HTTP {}", status);
+        return Err(ApiError::HttpError(format!("Status {}", status)));
+    }
+
+    // serde errors can quote the offending value, so only its position is logged
+    serde_json::from_str(&text).map_err(|e| {
+        tracing::error!(
+            "❌ Failed to parse token response JSON at line {} column {}",
+            e.line(),
+            e.column()
+        );
+        ApiError::ParseError("Invalid Quickteller token response".into())
+    })
+}
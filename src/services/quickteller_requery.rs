@@ -0,0 +1,148 @@
+//! Final status of Quickteller transactions whose payment advice timed out or
+//! came back pending.
+//!
+//! [`requery_transaction`] asks Quickteller about one transaction on demand.
+//! [`spawn_quickteller_requery`] does the same in the background for every
+//! pending one, claiming a batch at a time so instances running side by side
+//! never requery the same transaction. Response codes are mapped by
+//! [`TopupStatus::from_response`] and the transaction's `confirm_status`
+//! follows the top-up.
+
+use crate::models::airtime::{AirtimeTopup, TopupStatus};
+use crate::services::airtime_topup::{is_lost, query_outcome, record_outcome, requery, TopupError};
+use crate::services::quickteller_auth::TokenManager;
+use sqlx::PgPool;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RequerySettings {
+    /// Pause between two requery cycles
+    pub interval_secs: u64,
+    /// A transaction is requeried once it has been pending (or unrequeried)
+    /// this long
+    pub min_age_secs: i64,
+    /// Maximum transactions requeried per cycle
+    pub batch_size: i64,
+}
+
+impl RequerySettings {
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        RequerySettings {
+            interval_secs: var("QUICKTELLER_REQUERY_INTERVAL_SECS", 60),
+            min_age_secs: var("QUICKTELLER_REQUERY_MIN_AGE_SECS", 120),
+            batch_size: var("QUICKTELLER_REQUERY_BATCH_SIZE", 50),
+        }
+    }
+}
+
+pub fn spawn_quickteller_requery(
+    pool: PgPool,
+    tokens: TokenManager,
+) -> tokio::task::JoinHandle<()> {
+    let settings = RequerySettings::from_env();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(settings.interval_secs));
+        loop {
+            interval.tick().await;
+            match resolve_pending_transactions(&pool, &tokens, &settings).await {
+                Ok(0) => {}
+                Ok(count) => {
+                    tracing::info!("🔄 Requeried {} pending Quickteller transactions", count)
+                }
+                Err(err) => tracing::error!("❌ Quickteller requery failed: {}", err),
+            }
+        }
+    })
+}
+
+/// Requeries one batch of pending transactions. Returns how many were
+/// requeried.
+///
+/// The batch is claimed by moving `last_requeried_at`, so other instances
+/// skip it until `min_age_secs` has passed again. No row stays locked while
+/// Quickteller is asked; each outcome is stored in its own transaction.
+pub async fn resolve_pending_transactions(
+    pool: &PgPool,
+    tokens: &TokenManager,
+    settings: &RequerySettings,
+) -> Result<usize, sqlx::Error> {
+    let claimed = sqlx::query_as!(
+        AirtimeTopup,
+        r#"
+        UPDATE airtime_topups SET last_requeried_at = NOW()
+        WHERE id IN (
+            SELECT id FROM airtime_topups
+            WHERE status = $1
+              AND created_at < NOW() - make_interval(secs => $2)
+              AND (last_requeried_at IS NULL OR last_requeried_at < NOW() - make_interval(secs => $2))
+            ORDER BY last_requeried_at NULLS FIRST, created_at
+            LIMIT $3
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+        TopupStatus::Pending.as_str(),
+        settings.min_age_secs as f64,
+        settings.batch_size,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for topup in &claimed {
+        let Some(outcome) = requery(pool, tokens, &topup.request_reference, is_lost(topup)).await
+        else {
+            continue;
+        };
+        match outcome.status {
+            TopupStatus::Successful => tracing::info!(
+                "✅ Quickteller transaction {} succeeded",
+                topup.request_reference
+            ),
+            TopupStatus::Failed => tracing::warn!(
+                "❌ Quickteller transaction {} failed: {}",
+                topup.request_reference,
+                outcome.message
+            ),
+            TopupStatus::Pending => {}
+        }
+        record_outcome(pool, topup, outcome).await?;
+    }
+
+    Ok(claimed.len())
+}
+
+/// Asks Quickteller for the final status of the transaction sent as
+/// `request_reference` and records it. Successful transactions are final and
+/// returned as stored; failures to reach Quickteller are returned rather than
+/// left for the next requery.
+pub async fn requery_transaction(
+    pool: &PgPool,
+    tokens: &TokenManager,
+    request_reference: &str,
+) -> Result<AirtimeTopup, TopupError> {
+    let topup = sqlx::query_as!(
+        AirtimeTopup,
+        "SELECT * FROM airtime_topups WHERE request_reference = $1",
+        request_reference
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| TopupError::NotFound(request_reference.to_string()))?;
+
+    if topup.status == TopupStatus::Successful.as_str() {
+        return Ok(topup);
+    }
+
+    let outcome = query_outcome(pool, tokens, &topup.request_reference, is_lost(&topup)).await?;
+    Ok(record_outcome(pool, &topup, outcome).await?)
+}
@@ -0,0 +1,189 @@
+//! Merchant references for every call we make to MultiChoice, Bluecode and
+//! Quickteller.
+//!
+//! References are `{prefix}-{uuid v7}`: unique, sortable by issue time and
+//! within each provider's length limit. Each one is stored in
+//! `merchant_references` with its purpose and the transaction it belongs to,
+//! so a reference quoted by provider support can be traced back to us.
+
+use sqlx::{PgConnection, PgExecutor};
+use uuid::Uuid;
+
+/// Attempts before giving up on a colliding reference
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceProvider {
+    /// `merchant_tx_id` of a Bluecode registration
+    Bluecode,
+    /// `MerchantReference` of a PayU VAS (MultiChoice) request
+    PayuVas,
+    /// `requestReference` of a Quickteller bill payment
+    Quickteller,
+}
+
+impl ReferenceProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferenceProvider::Bluecode => "BLUECODE",
+            ReferenceProvider::PayuVas => "PAYU_VAS",
+            ReferenceProvider::Quickteller => "QUICKTELLER",
+        }
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ReferenceProvider::Bluecode => "TXN",
+            ReferenceProvider::PayuVas => "VAS",
+            ReferenceProvider::Quickteller => "QTL",
+        }
+    }
+
+    /// Longest reference the provider accepts.
+    pub fn max_len(&self) -> usize {
+        match self {
+            ReferenceProvider::Bluecode => 36,
+            ReferenceProvider::PayuVas => 36,
+            ReferenceProvider::Quickteller => 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferencePurpose {
+    Lookup,
+    Registration,
+    Confirmation,
+    /// Parent reference of a bulk renewal row, under which its confirmation
+    /// is issued
+    BulkRenewal,
+    /// Parent reference of a prepaid scheduled renewal
+    ScheduledRenewal,
+    AirtimeTopup,
+    /// `merchant_refund_id` of a Bluecode refund
+    Refund,
+}
+
+impl ReferencePurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferencePurpose::Lookup => "LOOKUP",
+            ReferencePurpose::Registration => "REGISTRATION",
+            ReferencePurpose::Confirmation => "CONFIRMATION",
+            ReferencePurpose::BulkRenewal => "BULK_RENEWAL",
+            ReferencePurpose::ScheduledRenewal => "SCHEDULED_RENEWAL",
+            ReferencePurpose::AirtimeTopup => "AIRTIME_TOPUP",
+            ReferencePurpose::Refund => "REFUND",
+        }
+    }
+}
+
+/// A fresh reference for `provider`, not yet recorded.
+pub fn generate_reference(provider: ReferenceProvider) -> String {
+    let mut reference = format!("{}-{}", provider.prefix(), Uuid::now_v7().simple());
+    // The timestamp comes first, so truncating keeps references sortable
+    reference.truncate(provider.max_len());
+    reference
+}
+
+/// Issues and records a reference for a call that is not tied to an earlier
+/// payment, such as a lookup or a new registration.
+pub async fn issue_reference(
+    conn: &mut PgConnection,
+    provider: ReferenceProvider,
+    purpose: ReferencePurpose,
+    transaction_id: Option<i32>,
+) -> Result<String, sqlx::Error> {
+    for _ in 0..MAX_ATTEMPTS {
+        let reference = generate_reference(provider);
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO merchant_references (reference, provider, purpose, transaction_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            "#,
+            reference,
+            provider.as_str(),
+            purpose.as_str(),
+            transaction_id,
+        )
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+
+        if inserted == 1 {
+            return Ok(reference);
+        }
+    }
+
+    Err(sqlx::Error::Protocol(format!(
+        "could not issue a unique {} reference",
+        provider.as_str()
+    )))
+}
+
+/// The reference for `purpose` on the payment `parent_reference`, issued on
+/// first use and returned unchanged afterwards, so retries reach the
+/// provider under the same reference. The row is linked to the transaction
+/// whose `merchant_reference` is `parent_reference`, if any.
+pub async fn reference_for(
+    conn: &mut PgConnection,
+    provider: ReferenceProvider,
+    purpose: ReferencePurpose,
+    parent_reference: &str,
+) -> Result<String, sqlx::Error> {
+    for _ in 0..MAX_ATTEMPTS {
+        let reference = generate_reference(provider);
+        sqlx::query!(
+            r#"
+            INSERT INTO merchant_references (reference, provider, purpose, parent_reference, transaction_id)
+            VALUES ($1, $2, $3, $4,
+                    (SELECT id FROM transactions WHERE merchant_reference = $4 ORDER BY id LIMIT 1))
+            ON CONFLICT DO NOTHING
+            "#,
+            reference,
+            provider.as_str(),
+            purpose.as_str(),
+            parent_reference,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let existing = sqlx::query_scalar!(
+            r#"
+            SELECT reference FROM merchant_references
+            WHERE parent_reference = $1 AND provider = $2 AND purpose = $3
+            "#,
+            parent_reference,
+            provider.as_str(),
+            purpose.as_str(),
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        if let Some(existing) = existing {
+            return Ok(existing);
+        }
+    }
+
+    Err(sqlx::Error::Protocol(format!(
+        "could not issue a unique {} reference",
+        provider.as_str()
+    )))
+}
+
+/// Links a reference issued before its transaction existed.
+pub async fn link_transaction<'e, E: PgExecutor<'e>>(
+    executor: E,
+    reference: &str,
+    transaction_id: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE merchant_references SET transaction_id = $1 WHERE reference = $2",
+        transaction_id,
+        reference
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,361 @@
+use crate::models::bluecode::{
+    BluecodeCancelResponse, BluecodePaymentState, BluecodeRefund, BluecodeRefundRequest,
+    BluecodeRefundStatusRequest,
+};
+use crate::services::bluecode::{cancel_payment, refund_payment, refund_status};
+use crate::services::references::{issue_reference, ReferenceProvider, ReferencePurpose};
+use crate::utils::error::ApiError;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use thiserror::Error;
+
+/// `qr_status` of a transaction whose payment was refunded in full. This is
+/// our own marker, Bluecode keeps reporting such payments as approved.
+pub const REFUNDED_QR_STATUS: &str = "REFUNDED";
+
+/// Refund states. PENDING and UNKNOWN refunds count towards the refunded
+/// amount until a requery settles them; only FAILED ones do not.
+pub const REFUND_PENDING: &str = "PENDING";
+pub const REFUND_UNKNOWN: &str = "UNKNOWN";
+pub const REFUND_APPROVED: &str = "APPROVED";
+pub const REFUND_FAILED: &str = "FAILED";
+
+#[derive(Error, Debug)]
+pub enum RefundError {
+    #[error("Transaction {0} not found")]
+    NotFound(String),
+
+    #[error("Transaction is {actual}, expected {expected}")]
+    InvalidState {
+        actual: String,
+        expected: &'static str,
+    },
+
+    #[error("Refund amount must be positive")]
+    InvalidAmount,
+
+    #[error("Requested {requested} exceeds refundable amount {refundable}")]
+    ExceedsRefundable { requested: i64, refundable: i64 },
+
+    #[error("Bluecode error: {0}")]
+    Upstream(#[from] ApiError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Refunds an approved QR payment, fully when `amount` is `None`.
+///
+/// The refund is recorded as PENDING under its own reference before Bluecode
+/// is called, so concurrent refunds cannot exceed the original amount and the
+/// transaction row is not locked during the call. Refunds whose outcome is
+/// unknown (timeouts, 5xx) stay UNKNOWN and keep counting towards the
+/// refunded amount until [`resolve_pending_refunds`] requeries them.
+pub async fn refund_transaction(
+    pool: &PgPool,
+    merchant_tx_id: &str,
+    amount: Option<i64>,
+    reason: String,
+) -> Result<BluecodeRefund, RefundError> {
+    let mut db_tx = pool.begin().await?;
+
+    let transaction = sqlx::query!(
+        r#"
+        SELECT id, amount, qr_status
+        FROM transactions
+        WHERE merchant_reference = $1
+        FOR UPDATE
+        "#,
+        merchant_tx_id
+    )
+    .fetch_optional(&mut *db_tx)
+    .await?
+    .ok_or_else(|| RefundError::NotFound(merchant_tx_id.to_string()))?;
+
+    if BluecodePaymentState::from(transaction.qr_status.as_str()) != BluecodePaymentState::Approved
+    {
+        return Err(RefundError::InvalidState {
+            actual: transaction.qr_status,
+            expected: "APPROVED",
+        });
+    }
+
+    let already_refunded = sqlx::query!(
+        r#"
+        SELECT COALESCE(SUM(amount), 0)::BIGINT AS "refunded!"
+        FROM bluecode_refunds
+        WHERE transaction_id = $1 AND state <> $2
+        "#,
+        transaction.id,
+        REFUND_FAILED,
+    )
+    .fetch_one(&mut *db_tx)
+    .await?
+    .refunded;
+
+    let refundable = transaction.amount - already_refunded;
+    let amount = amount.unwrap_or(refundable);
+    if amount <= 0 {
+        return Err(RefundError::InvalidAmount);
+    }
+    if amount > refundable {
+        return Err(RefundError::ExceedsRefundable {
+            requested: amount,
+            refundable,
+        });
+    }
+
+    let refund_reference = issue_reference(
+        &mut db_tx,
+        ReferenceProvider::Bluecode,
+        ReferencePurpose::Refund,
+        Some(transaction.id),
+    )
+    .await?;
+    let refund = sqlx::query_as!(
+        BluecodeRefund,
+        r#"
+        INSERT INTO bluecode_refunds (transaction_id, merchant_tx_id, amount, reason, state, refund_reference)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *
+        "#,
+        transaction.id,
+        merchant_tx_id,
+        amount,
+        reason,
+        REFUND_PENDING,
+        refund_reference,
+    )
+    .fetch_one(&mut *db_tx)
+    .await?;
+
+    db_tx.commit().await?;
+
+    let result = refund_payment(
+        pool,
+        BluecodeRefundRequest {
+            merchant_tx_id: merchant_tx_id.to_string(),
+            merchant_refund_id: refund_reference,
+            amount,
+            reason,
+        },
+    )
+    .await;
+
+    let (state, refund_tx_id, error) = match result {
+        Ok(response) => (
+            refund_state(&response.refund.state),
+            response.refund.refund_tx_id,
+            None,
+        ),
+        Err(err) => {
+            tracing::error!("❌ Bluecode refund for {} failed: {}", merchant_tx_id, err);
+            (failed_refund_state(&err), None, Some(err))
+        }
+    };
+
+    let refund = record_refund_state(pool, &refund, state, refund_tx_id).await?;
+    match error {
+        Some(err) if state == REFUND_FAILED => Err(RefundError::Upstream(err)),
+        _ => Ok(refund),
+    }
+}
+
+/// Our state for a refund Bluecode reported as `state`. Refunds Bluecode has
+/// not settled yet stay PENDING.
+fn refund_state(state: &str) -> &'static str {
+    match BluecodePaymentState::from(state) {
+        BluecodePaymentState::Approved => REFUND_APPROVED,
+        BluecodePaymentState::Declined
+        | BluecodePaymentState::Failure
+        | BluecodePaymentState::Cancelled => REFUND_FAILED,
+        _ => REFUND_PENDING,
+    }
+}
+
+/// Our state for a refund whose call failed: only a 4xx from Bluecode means
+/// the refund was not made. Timeouts and 5xx leave it UNKNOWN.
+fn failed_refund_state(err: &ApiError) -> &'static str {
+    match err {
+        ApiError::UpstreamStatus { status, .. } if status.is_client_error() => REFUND_FAILED,
+        _ => REFUND_UNKNOWN,
+    }
+}
+
+/// Stores the new state of `refund`. The payment is marked refunded once its
+/// approved refunds cover the whole amount.
+async fn record_refund_state(
+    pool: &PgPool,
+    refund: &BluecodeRefund,
+    state: &str,
+    refund_tx_id: Option<String>,
+) -> Result<BluecodeRefund, sqlx::Error> {
+    let mut db_tx = pool.begin().await?;
+
+    let updated = sqlx::query_as!(
+        BluecodeRefund,
+        r#"
+        UPDATE bluecode_refunds
+        SET state = $2, refund_tx_id = COALESCE($3, refund_tx_id), updated_at = NOW()
+        WHERE id = $1
+        RETURNING *
+        "#,
+        refund.id,
+        state,
+        refund_tx_id,
+    )
+    .fetch_one(&mut *db_tx)
+    .await?;
+
+    if state == REFUND_APPROVED {
+        sqlx::query!(
+            r#"
+            UPDATE transactions SET qr_status = $1
+            WHERE id = $2
+              AND amount <= (SELECT COALESCE(SUM(amount), 0) FROM bluecode_refunds
+                             WHERE transaction_id = $2 AND state = $3)
+            "#,
+            REFUNDED_QR_STATUS,
+            refund.transaction_id,
+            REFUND_APPROVED,
+        )
+        .execute(&mut *db_tx)
+        .await?;
+    }
+
+    db_tx.commit().await?;
+    Ok(updated)
+}
+
+/// Requeries one batch of refunds still PENDING or UNKNOWN after
+/// `min_age_secs`. Returns how many were requeried.
+///
+/// Refunds are claimed by bumping `updated_at` and the claim is committed
+/// before Bluecode is called, so no lock is held during the requeries.
+pub async fn resolve_pending_refunds(
+    pool: &PgPool,
+    min_age_secs: i64,
+    batch_size: i64,
+) -> Result<usize, sqlx::Error> {
+    let claimed = sqlx::query_as!(
+        BluecodeRefund,
+        r#"
+        UPDATE bluecode_refunds SET updated_at = NOW()
+        WHERE id IN (
+            SELECT id FROM bluecode_refunds
+            WHERE state IN ($1, $2)
+              AND refund_reference IS NOT NULL
+              AND updated_at < NOW() - make_interval(secs => $3)
+            ORDER BY updated_at
+            LIMIT $4
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+        REFUND_PENDING,
+        REFUND_UNKNOWN,
+        min_age_secs as f64,
+        batch_size,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for refund in &claimed {
+        let Some(refund_reference) = refund.refund_reference.clone() else {
+            continue;
+        };
+        let result = refund_status(
+            pool,
+            BluecodeRefundStatusRequest {
+                merchant_tx_id: refund.merchant_tx_id.clone(),
+                merchant_refund_id: refund_reference,
+            },
+        )
+        .await;
+
+        let (state, refund_tx_id) = match result {
+            Ok(response) => (
+                refund_state(&response.refund.state),
+                response.refund.refund_tx_id,
+            ),
+            // Bluecode never received the refund
+            Err(ApiError::UpstreamStatus { status, .. }) if status == StatusCode::NOT_FOUND => {
+                (REFUND_FAILED, None)
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "⚠️ Requery of refund {} for {} failed: {}",
+                    refund.id,
+                    refund.merchant_tx_id,
+                    err
+                );
+                continue;
+            }
+        };
+
+        if state != refund.state {
+            tracing::info!(
+                "💸 Refund {} for {} is now {}",
+                refund.id,
+                refund.merchant_tx_id,
+                state
+            );
+        }
+        record_refund_state(pool, refund, state, refund_tx_id).await?;
+    }
+
+    Ok(claimed.len())
+}
+
+/// Cancels a registered QR payment before the customer pays it.
+pub async fn cancel_transaction(
+    pool: &PgPool,
+    merchant_tx_id: &str,
+) -> Result<BluecodeCancelResponse, RefundError> {
+    let qr_status = sqlx::query!(
+        "SELECT qr_status FROM transactions WHERE merchant_reference = $1",
+        merchant_tx_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| row.qr_status);
+
+    if let Some(actual) = qr_status.filter(|s| {
+        s == REFUNDED_QR_STATUS
+            || BluecodePaymentState::from(s.as_str()) == BluecodePaymentState::Approved
+    }) {
+        return Err(RefundError::InvalidState {
+            actual,
+            expected: "REGISTERED",
+        });
+    }
+
+    let response = cancel_payment(pool, merchant_tx_id.to_string()).await?;
+
+    sqlx::query!(
+        "UPDATE transactions SET qr_status = $1 WHERE merchant_reference = $2",
+        BluecodePaymentState::Cancelled.as_str(),
+        merchant_tx_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(response)
+}
+
+pub async fn list_refunds(
+    pool: &PgPool,
+    merchant_tx_id: &str,
+) -> Result<Vec<BluecodeRefund>, sqlx::Error> {
+    sqlx::query_as!(
+        BluecodeRefund,
+        r#"
+        SELECT * FROM bluecode_refunds
+        WHERE merchant_tx_id = $1
+        ORDER BY created_at
+        "#,
+        merchant_tx_id
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,910 @@
+//! Recurring DSTV renewals.
+//!
+//! A subscription stores what to renew, when, and how it is paid for. The
+//! scheduler picks up subscriptions whose `next_run_at` has passed, renews
+//! them and records every attempt in `dstv_subscription_runs`. Attempts that
+//! fail for a temporary reason are retried every `DSTV_SUBSCRIPTION_RETRY_SECS`
+//! until `DSTV_SUBSCRIPTION_RETRY_WINDOW_SECS` after the renewal was due;
+//! after that the subscription moves on to its next renewal.
+//!
+//! A `PREPAID` attempt is recorded as a `PENDING` run before MultiChoice is
+//! called. If it stays pending, the next attempt requeries it rather than
+//! paying for the renewal again.
+//!
+//! `PREPAID` renewals are confirmed with MultiChoice directly. `BLUECODE`
+//! renewals start a DSTV checkout, whose own worker confirms the renewal once
+//! the customer pays; the run is updated when the checkout finishes.
+
+use crate::config::{vas_product, ConfigError};
+use crate::models::checkout::{CheckoutStep, StartCheckoutRequest};
+use crate::models::dstv::{DstvErrorCode, DstvLookupRequest, DstvLookupResponse};
+use crate::models::payu_vas::VasOutcome;
+use crate::models::subscriptions::{
+    next_monthly_run, parse_due_date, CreateSubscriptionRequest, DstvSubscription, FundingSource,
+    RunStatus, Schedule, SubscriptionDetails, SubscriptionRun, SubscriptionStatus,
+};
+use crate::services::catalog::{check_basket_price, CatalogError};
+use crate::services::checkout::{start_checkout, CheckoutError};
+use crate::services::dstv::{
+    confirm_basket, confirmation_reference, lookup_dstv_account, requery_dstv_confirmation,
+};
+use crate::services::references::{
+    issue_reference, link_transaction, ReferenceProvider, ReferencePurpose,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Months, NaiveDate, TimeZone, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Runs returned with a subscription
+const RECENT_RUNS: i64 = 24;
+
+#[derive(Error, Debug)]
+pub enum SubscriptionError {
+    #[error("Subscription {0} not found")]
+    NotFound(Uuid),
+
+    #[error("Not allowed to manage subscription {0}")]
+    Forbidden(Uuid),
+
+    #[error("Subscription {id} is {status}")]
+    InvalidState { id: Uuid, status: String },
+
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(String),
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(i64),
+
+    #[error("DSTV lookup failed: {0}")]
+    Lookup(String),
+
+    #[error("MultiChoice did not return a due date for {0}")]
+    DueDateUnknown(String),
+
+    #[error(transparent)]
+    Catalog(#[from] CatalogError),
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionSettings {
+    /// Pause between two scheduler cycles
+    pub interval_secs: u64,
+    /// Maximum subscriptions renewed per cycle
+    pub batch_size: i64,
+    /// Delay before a failed renewal is attempted again
+    pub retry_secs: i64,
+    /// How long after its due time a renewal keeps being retried
+    pub retry_window_secs: i64,
+    /// How long a renewal being attempted is left before it is picked up again
+    pub lease_secs: i64,
+    /// Lookup custom field holding the subscription's due date
+    pub due_date_field: String,
+}
+
+impl SubscriptionSettings {
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str, default: T) -> T {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+
+        SubscriptionSettings {
+            interval_secs: var("DSTV_SUBSCRIPTION_INTERVAL_SECS", 60),
+            batch_size: var("DSTV_SUBSCRIPTION_BATCH_SIZE", 50),
+            retry_secs: var("DSTV_SUBSCRIPTION_RETRY_SECS", 3600),
+            retry_window_secs: var("DSTV_SUBSCRIPTION_RETRY_WINDOW_SECS", 3 * 24 * 3600),
+            lease_secs: var("DSTV_SUBSCRIPTION_LEASE_SECS", 600),
+            due_date_field: var("DSTV_DUE_DATE_FIELD", "DUE_DATE".to_string()),
+        }
+    }
+}
+
+/// Checks the smartcard and basket and stores an active subscription.
+pub async fn create_subscription(
+    pool: &PgPool,
+    req: CreateSubscriptionRequest,
+) -> Result<SubscriptionDetails, SubscriptionError> {
+    let settings = SubscriptionSettings::from_env();
+
+    req.schedule
+        .validate()
+        .map_err(SubscriptionError::InvalidSchedule)?;
+    if req.amount <= 0 || u32::try_from(req.amount).is_err() {
+        return Err(SubscriptionError::InvalidAmount(req.amount));
+    }
+    let product = vas_product(req.product.as_deref())?;
+    check_basket_price(pool, &product.id, &req.basket_id, req.amount).await?;
+
+    let lookup = lookup_dstv_account(
+        pool,
+        DstvLookupRequest {
+            customer_id: req.customer_id.clone(),
+            product: Some(product.id.clone()),
+            refresh: false,
+        },
+    )
+    .await
+    .map_err(|e| {
+        SubscriptionError::Lookup(match e.error_code() {
+            Some(code) => code.customer_message().to_string(),
+            None => e.to_string(),
+        })
+    })?;
+
+    let now = Utc::now();
+    let first_run = match req.schedule {
+        Schedule::Monthly { day } => next_monthly_run(day, now),
+        Schedule::BeforeDueDate { days_before } => {
+            let due = due_date(&lookup, &settings.due_date_field)
+                .ok_or_else(|| SubscriptionError::DueDateUnknown(req.customer_id.clone()))?;
+            run_before(due, days_before).max(now)
+        }
+    };
+
+    let id = Uuid::new_v4();
+    let manage_token = Uuid::new_v4().simple().to_string();
+    sqlx::query!(
+        r#"
+        INSERT INTO dstv_subscriptions
+            (id, customer_id, basket_id, amount, vas_product, account_name, schedule,
+             funding_source, status, cycle_scheduled_for, next_run_at, manage_token_hash)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $10, $11)
+        "#,
+        id,
+        req.customer_id,
+        req.basket_id,
+        req.amount,
+        product.id,
+        lookup.account_name,
+        Json(req.schedule) as _,
+        req.funding_source.as_str(),
+        SubscriptionStatus::Active.as_str(),
+        first_run,
+        token_hash(&manage_token),
+    )
+    .execute(pool)
+    .await?;
+
+    tracing::info!(
+        "📅 DSTV subscription {} for {} created, first renewal at {}",
+        id,
+        req.customer_id,
+        first_run
+    );
+    Ok(SubscriptionDetails {
+        manage_token: Some(manage_token),
+        ..load_subscription(pool, id).await?
+    })
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Checks the token returned when the subscription was created.
+pub async fn authorize_subscription(
+    pool: &PgPool,
+    id: Uuid,
+    token: &str,
+) -> Result<(), SubscriptionError> {
+    let stored = sqlx::query_scalar!(
+        "SELECT manage_token_hash FROM dstv_subscriptions WHERE id = $1",
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(SubscriptionError::NotFound(id))?;
+
+    match stored {
+        Some(hash) if !token.is_empty() && hash == token_hash(token) => Ok(()),
+        _ => Err(SubscriptionError::Forbidden(id)),
+    }
+}
+
+fn due_date(lookup: &DstvLookupResponse, field: &str) -> Option<NaiveDate> {
+    lookup
+        .custom_fields
+        .as_ref()?
+        .get(field)
+        .and_then(|value| parse_due_date(value))
+}
+
+fn run_before(due: NaiveDate, days_before: u32) -> DateTime<Utc> {
+    let day = due - ChronoDuration::days(days_before.into());
+    Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).unwrap())
+}
+
+pub async fn load_subscription(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<SubscriptionDetails, SubscriptionError> {
+    let subscription = sqlx::query_as!(
+        DstvSubscription,
+        r#"
+        SELECT id, customer_id, basket_id, amount, vas_product, account_name,
+               schedule AS "schedule: Json<Schedule>", funding_source, status,
+               cycle_scheduled_for, next_run_at, created_at, updated_at, cancelled_at
+        FROM dstv_subscriptions
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(SubscriptionError::NotFound(id))?;
+
+    let runs = sqlx::query_as!(
+        SubscriptionRun,
+        r#"
+        SELECT id, scheduled_for, status, transaction_id, checkout_id, error_code, error,
+               receipt_number, created_at, updated_at
+        FROM dstv_subscription_runs
+        WHERE subscription_id = $1
+        ORDER BY id DESC
+        LIMIT $2
+        "#,
+        id,
+        RECENT_RUNS
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(SubscriptionDetails {
+        subscription,
+        runs,
+        manage_token: None,
+    })
+}
+
+/// Stops renewing until the subscription is resumed.
+pub async fn pause_subscription(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<SubscriptionDetails, SubscriptionError> {
+    let paused = sqlx::query!(
+        r#"
+        UPDATE dstv_subscriptions SET status = $2, updated_at = NOW()
+        WHERE id = $1 AND status = $3
+        "#,
+        id,
+        SubscriptionStatus::Paused.as_str(),
+        SubscriptionStatus::Active.as_str(),
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if paused == 0 {
+        return Err(invalid_state(pool, id).await);
+    }
+    tracing::info!("⏸️ DSTV subscription {} paused", id);
+    load_subscription(pool, id).await
+}
+
+/// Reactivates a paused subscription. A renewal that fell due while it was
+/// paused is still attempted if its retry window has not passed; otherwise
+/// the subscription moves on to its next renewal.
+pub async fn resume_subscription(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<SubscriptionDetails, SubscriptionError> {
+    let settings = SubscriptionSettings::from_env();
+    let subscription = load_subscription(pool, id).await?.subscription;
+    if subscription.status != SubscriptionStatus::Paused.as_str() {
+        return Err(SubscriptionError::InvalidState {
+            id,
+            status: subscription.status,
+        });
+    }
+
+    let now = Utc::now();
+    let (cycle, next_run) = if now < retry_deadline(&subscription, &settings) {
+        (
+            subscription.cycle_scheduled_for,
+            subscription.next_run_at.max(now),
+        )
+    } else {
+        let next = next_cycle(pool, &subscription, &settings).await;
+        (next, next)
+    };
+
+    let resumed = sqlx::query!(
+        r#"
+        UPDATE dstv_subscriptions
+        SET status = $2, cycle_scheduled_for = $4, next_run_at = $5, updated_at = NOW()
+        WHERE id = $1 AND status = $3
+        "#,
+        id,
+        SubscriptionStatus::Active.as_str(),
+        SubscriptionStatus::Paused.as_str(),
+        cycle,
+        next_run,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if resumed == 0 {
+        return Err(invalid_state(pool, id).await);
+    }
+    tracing::info!(
+        "▶️ DSTV subscription {} resumed, next renewal at {}",
+        id,
+        next_run
+    );
+    load_subscription(pool, id).await
+}
+
+/// Stops the subscription for good. Renewals already requested are not
+/// withdrawn.
+pub async fn cancel_subscription(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<SubscriptionDetails, SubscriptionError> {
+    let cancelled = sqlx::query!(
+        r#"
+        UPDATE dstv_subscriptions SET status = $2, cancelled_at = NOW(), updated_at = NOW()
+        WHERE id = $1 AND status IN ($3, $4)
+        "#,
+        id,
+        SubscriptionStatus::Cancelled.as_str(),
+        SubscriptionStatus::Active.as_str(),
+        SubscriptionStatus::Paused.as_str(),
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if cancelled == 0 {
+        return Err(invalid_state(pool, id).await);
+    }
+    tracing::info!("🛑 DSTV subscription {} cancelled", id);
+    load_subscription(pool, id).await
+}
+
+async fn invalid_state(pool: &PgPool, id: Uuid) -> SubscriptionError {
+    match load_subscription(pool, id).await {
+        Ok(details) => SubscriptionError::InvalidState {
+            id,
+            status: details.subscription.status,
+        },
+        Err(err) => err,
+    }
+}
+
+fn retry_deadline(
+    subscription: &DstvSubscription,
+    settings: &SubscriptionSettings,
+) -> DateTime<Utc> {
+    subscription.cycle_scheduled_for + ChronoDuration::seconds(settings.retry_window_secs)
+}
+
+/// When the renewal after the current one is due. Due-date schedules ask
+/// MultiChoice for the new due date; until it has moved past the current
+/// renewal, the next one is a month after it.
+async fn next_cycle(
+    pool: &PgPool,
+    subscription: &DstvSubscription,
+    settings: &SubscriptionSettings,
+) -> DateTime<Utc> {
+    let now = Utc::now();
+    let after = subscription.cycle_scheduled_for.max(now);
+
+    match subscription.schedule.0 {
+        Schedule::Monthly { day } => next_monthly_run(day, after),
+        Schedule::BeforeDueDate { days_before } => {
+            let lookup = lookup_dstv_account(
+                pool,
+                DstvLookupRequest {
+                    customer_id: subscription.customer_id.clone(),
+                    product: Some(subscription.vas_product.clone()),
+                    refresh: true,
+                },
+            )
+            .await;
+
+            match lookup
+                .ok()
+                .and_then(|l| due_date(&l, &settings.due_date_field))
+                .map(|due| run_before(due, days_before))
+            {
+                Some(run) if run > after => run,
+                _ => subscription
+                    .cycle_scheduled_for
+                    .checked_add_months(Months::new(1))
+                    .unwrap_or(after)
+                    .max(now),
+            }
+        }
+    }
+}
+
+/// Renews due subscriptions every `DSTV_SUBSCRIPTION_INTERVAL_SECS`.
+pub fn spawn_subscription_scheduler(pool: PgPool) -> tokio::task::JoinHandle<()> {
+    let settings = SubscriptionSettings::from_env();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(settings.interval_secs));
+        loop {
+            interval.tick().await;
+            match run_due_subscriptions(&pool, &settings).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("📅 Ran {} DSTV subscription renewals", count),
+                Err(err) => tracing::error!("❌ Subscription scheduler failed: {}", err),
+            }
+        }
+    })
+}
+
+/// Records finished checkouts on their runs, then renews one batch of due
+/// subscriptions. Returns how many were renewed.
+pub async fn run_due_subscriptions(
+    pool: &PgPool,
+    settings: &SubscriptionSettings,
+) -> Result<usize, sqlx::Error> {
+    sync_checkout_runs(pool).await?;
+
+    let ids = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM dstv_subscriptions
+        WHERE status = $1 AND next_run_at <= NOW()
+        ORDER BY next_run_at
+        LIMIT $2
+        "#,
+        SubscriptionStatus::Active.as_str(),
+        settings.batch_size,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut renewed = 0;
+    for id in ids {
+        match run_subscription(pool, id, settings).await {
+            Ok(true) => renewed += 1,
+            Ok(false) => {}
+            Err(err) => tracing::error!("❌ Failed to renew DSTV subscription {}: {}", id, err),
+        }
+    }
+    Ok(renewed)
+}
+
+/// Outcome of one renewal attempt, before retries are decided.
+struct Attempt {
+    status: RunStatus,
+    transaction_id: Option<i32>,
+    checkout_id: Option<Uuid>,
+    error_code: Option<String>,
+    error: Option<String>,
+    receipt_number: Option<String>,
+    retryable: bool,
+}
+
+impl Attempt {
+    fn failed(code: Option<DstvErrorCode>, error: String, retryable: bool) -> Self {
+        Attempt {
+            status: RunStatus::Failed,
+            transaction_id: None,
+            checkout_id: None,
+            error_code: code.map(|c| c.as_str().to_string()),
+            error: Some(error),
+            receipt_number: None,
+            retryable,
+        }
+    }
+}
+
+/// Renews the subscription if it is still active and due. It is claimed by
+/// moving `next_run_at` past the lease, so the row is only locked while the
+/// attempt is recorded, never across MultiChoice or Bluecode calls.
+async fn run_subscription(
+    pool: &PgPool,
+    id: Uuid,
+    settings: &SubscriptionSettings,
+) -> Result<bool, sqlx::Error> {
+    let mut db_tx = pool.begin().await?;
+
+    let Some(subscription) = sqlx::query_as!(
+        DstvSubscription,
+        r#"
+        SELECT id, customer_id, basket_id, amount, vas_product, account_name,
+               schedule AS "schedule: Json<Schedule>", funding_source, status,
+               cycle_scheduled_for, next_run_at, created_at, updated_at, cancelled_at
+        FROM dstv_subscriptions
+        WHERE id = $1 AND status = $2 AND next_run_at <= NOW()
+        FOR UPDATE SKIP LOCKED
+        "#,
+        id,
+        SubscriptionStatus::Active.as_str(),
+    )
+    .fetch_optional(&mut *db_tx)
+    .await?
+    else {
+        return Ok(false);
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE dstv_subscriptions
+        SET next_run_at = NOW() + make_interval(secs => $2), updated_at = NOW()
+        WHERE id = $1
+        "#,
+        subscription.id,
+        settings.lease_secs as f64,
+    )
+    .execute(&mut *db_tx)
+    .await?;
+
+    let funding_source = FundingSource::from_str(&subscription.funding_source);
+    let prepaid = match funding_source {
+        Ok(FundingSource::Prepaid) => Some(record_prepaid_run(&mut db_tx, &subscription).await?),
+        _ => None,
+    };
+    db_tx.commit().await?;
+
+    let mut attempt = match (funding_source, &prepaid) {
+        (Ok(FundingSource::Prepaid), Some(run)) => renew_prepaid(pool, &subscription, run).await,
+        (Ok(_), _) => renew_with_bluecode(pool, &subscription).await,
+        (Err(err), _) => Attempt::failed(None, err, false),
+    };
+
+    let retry_at = Utc::now() + ChronoDuration::seconds(settings.retry_secs);
+    let retry = matches!(attempt.status, RunStatus::Pending | RunStatus::Failed)
+        && attempt.retryable
+        && retry_at < retry_deadline(&subscription, settings);
+
+    let (cycle, next_run) = if retry {
+        if attempt.status == RunStatus::Failed {
+            attempt.status = RunStatus::Retrying;
+        }
+        (subscription.cycle_scheduled_for, retry_at)
+    } else {
+        let next = next_cycle(pool, &subscription, settings).await;
+        (next, next)
+    };
+
+    let mut db_tx = pool.begin().await?;
+
+    match &prepaid {
+        Some(run) => {
+            sqlx::query!(
+                r#"
+                UPDATE dstv_subscription_runs
+                SET status = $2, error_code = $3, error = $4, receipt_number = $5,
+                    updated_at = NOW()
+                WHERE id = $1
+                "#,
+                run.run_id,
+                attempt.status.as_str(),
+                attempt.error_code,
+                attempt.error,
+                attempt.receipt_number,
+            )
+            .execute(&mut *db_tx)
+            .await?;
+        }
+        None => {
+            sqlx::query!(
+                r#"
+                INSERT INTO dstv_subscription_runs
+                    (subscription_id, scheduled_for, status, transaction_id, checkout_id,
+                     error_code, error, receipt_number)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                "#,
+                subscription.id,
+                subscription.cycle_scheduled_for,
+                attempt.status.as_str(),
+                attempt.transaction_id,
+                attempt.checkout_id,
+                attempt.error_code,
+                attempt.error,
+                attempt.receipt_number,
+            )
+            .execute(&mut *db_tx)
+            .await?;
+        }
+    }
+
+    // A resume that moved the subscription on in the meantime wins
+    sqlx::query!(
+        r#"
+        UPDATE dstv_subscriptions
+        SET cycle_scheduled_for = $2, next_run_at = $3, updated_at = NOW()
+        WHERE id = $1 AND cycle_scheduled_for = $4
+        "#,
+        subscription.id,
+        cycle,
+        next_run,
+        subscription.cycle_scheduled_for,
+    )
+    .execute(&mut *db_tx)
+    .await?;
+
+    let confirm_status = match attempt.status {
+        RunStatus::Confirmed if prepaid.is_some() => Some("CONFIRMED"),
+        RunStatus::Failed => Some("FAILED"),
+        _ => None,
+    };
+    if let (Some(confirm_status), Some(transaction_id)) = (confirm_status, attempt.transaction_id) {
+        sqlx::query!(
+            "UPDATE transactions SET confirm_status = $2 WHERE id = $1",
+            transaction_id,
+            confirm_status,
+        )
+        .execute(&mut *db_tx)
+        .await?;
+    }
+
+    db_tx.commit().await?;
+    tracing::info!(
+        "📅 DSTV subscription {} run {}, next at {}",
+        subscription.id,
+        attempt.status,
+        next_run
+    );
+    Ok(true)
+}
+
+/// A prepaid renewal attempt, stored before MultiChoice is called.
+struct PrepaidRun {
+    run_id: i64,
+    transaction_id: i32,
+    /// The transaction's own reference
+    reference: String,
+    /// The reference MultiChoice is paid under
+    merchant_reference: String,
+    /// The previous attempt was left pending, so it is requeried instead of
+    /// being paid again
+    requery: bool,
+}
+
+/// Records a `PENDING` run for the attempt. Every attempt for the same
+/// renewal reuses its transaction, so MultiChoice sees one confirmation
+/// reference, and a run left pending by an earlier attempt, or by a crash
+/// mid-call, is requeried.
+async fn record_prepaid_run(
+    db_tx: &mut Transaction<'_, Postgres>,
+    subscription: &DstvSubscription,
+) -> Result<PrepaidRun, sqlx::Error> {
+    let previous = sqlx::query!(
+        r#"
+        SELECT r.status, t.id, t.merchant_reference
+        FROM dstv_subscription_runs r
+        JOIN transactions t ON t.id = r.transaction_id
+        WHERE r.subscription_id = $1 AND r.scheduled_for = $2
+        ORDER BY r.id DESC
+        LIMIT 1
+        "#,
+        subscription.id,
+        subscription.cycle_scheduled_for,
+    )
+    .fetch_optional(&mut **db_tx)
+    .await?;
+
+    let (transaction_id, reference, requery) = match previous {
+        Some(row) => (
+            row.id,
+            row.merchant_reference,
+            row.status == RunStatus::Pending.as_str(),
+        ),
+        None => {
+            let (id, reference) = create_prepaid_transaction(db_tx, subscription).await?;
+            (id, reference, false)
+        }
+    };
+    let merchant_reference = confirmation_reference(db_tx, &reference).await?;
+
+    let run_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO dstv_subscription_runs
+            (subscription_id, scheduled_for, status, transaction_id)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#,
+        subscription.id,
+        subscription.cycle_scheduled_for,
+        RunStatus::Pending.as_str(),
+        transaction_id,
+    )
+    .fetch_one(&mut **db_tx)
+    .await?;
+
+    Ok(PrepaidRun {
+        run_id,
+        transaction_id,
+        reference,
+        merchant_reference,
+        requery,
+    })
+}
+
+/// Confirms the renewal with MultiChoice, or asks how a pending one ended.
+async fn renew_prepaid(
+    pool: &PgPool,
+    subscription: &DstvSubscription,
+    run: &PrepaidRun,
+) -> Attempt {
+    let result = if run.requery {
+        tracing::info!(
+            "🔎 Requerying pending renewal {} of DSTV subscription {}",
+            run.merchant_reference,
+            subscription.id
+        );
+        requery_dstv_confirmation(pool, Some(&subscription.vas_product), &run.reference).await
+    } else {
+        match check_basket_price(
+            pool,
+            &subscription.vas_product,
+            &subscription.basket_id,
+            subscription.amount,
+        )
+        .await
+        {
+            Ok(_) => {
+                confirm_basket(
+                    pool,
+                    Some(&subscription.vas_product),
+                    &run.merchant_reference,
+                    &subscription.customer_id,
+                    &subscription.basket_id,
+                    subscription.amount,
+                )
+                .await
+            }
+            Err(err) => Err(err.into()),
+        }
+    };
+
+    let pending = |message: String| Attempt {
+        status: RunStatus::Pending,
+        error_code: Some(DstvErrorCode::Pending.as_str().to_string()),
+        ..Attempt::failed(None, message, true)
+    };
+
+    let mut attempt = match result {
+        Ok(confirmation) => match confirmation.outcome {
+            VasOutcome::Success => Attempt {
+                status: RunStatus::Confirmed,
+                transaction_id: None,
+                checkout_id: None,
+                error_code: None,
+                error: None,
+                receipt_number: confirmation.receipt_number,
+                retryable: false,
+            },
+            VasOutcome::Pending => pending(confirmation.message),
+            VasOutcome::Failure => {
+                let code = confirmation.error_code.unwrap_or(DstvErrorCode::Unknown);
+                Attempt::failed(
+                    Some(code),
+                    confirmation.message,
+                    code == DstvErrorCode::UpstreamUnavailable,
+                )
+            }
+        },
+        // The renewal may have gone through, so it stays pending
+        Err(err) if run.requery => pending(err.to_string()),
+        Err(err) => {
+            let code = err.error_code();
+            let retryable = matches!(code, None | Some(DstvErrorCode::UpstreamUnavailable));
+            Attempt::failed(code, err.to_string(), retryable)
+        }
+    };
+    attempt.transaction_id = Some(run.transaction_id);
+    attempt
+}
+
+/// Stored with the run that uses it, before MultiChoice is called, so the
+/// confirmation reference and vendor exchanges are linked to it.
+async fn create_prepaid_transaction(
+    db_tx: &mut Transaction<'_, Postgres>,
+    subscription: &DstvSubscription,
+) -> Result<(i32, String), sqlx::Error> {
+    let reference = issue_reference(
+        db_tx,
+        ReferenceProvider::PayuVas,
+        ReferencePurpose::ScheduledRenewal,
+        None,
+    )
+    .await?;
+
+    // No Bluecode payment backs a prepaid renewal, so the poller skips it
+    let transaction_id = sqlx::query!(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ($1, $2, $3, $4, $5, 'PENDING', $6)
+        RETURNING id
+        "#,
+        reference,
+        subscription.customer_id,
+        subscription.basket_id,
+        subscription.amount,
+        FundingSource::Prepaid.as_str(),
+        Utc::now().timestamp_millis(),
+    )
+    .fetch_one(&mut **db_tx)
+    .await?
+    .id;
+    link_transaction(&mut **db_tx, &reference, transaction_id).await?;
+
+    Ok((transaction_id, reference))
+}
+
+/// Starts a checkout whose Bluecode payment the customer has to approve.
+async fn renew_with_bluecode(pool: &PgPool, subscription: &DstvSubscription) -> Attempt {
+    let result = start_checkout(
+        pool,
+        StartCheckoutRequest {
+            customer_id: subscription.customer_id.clone(),
+            basket_id: subscription.basket_id.clone(),
+            amount: subscription.amount,
+            product: Some(subscription.vas_product.clone()),
+        },
+    )
+    .await;
+
+    match result {
+        Ok(checkout) => Attempt {
+            status: RunStatus::PaymentRequested,
+            transaction_id: Some(checkout.transaction_id),
+            checkout_id: Some(checkout.id),
+            error_code: None,
+            error: None,
+            receipt_number: None,
+            retryable: false,
+        },
+        Err(err) => {
+            let (code, retryable) = match &err {
+                CheckoutError::Catalog(CatalogError::PriceMismatch { .. }) => {
+                    (Some(DstvErrorCode::AmountMismatch), false)
+                }
+                CheckoutError::InvalidAmount(_)
+                | CheckoutError::Catalog(CatalogError::UnknownBasket(_))
+                | CheckoutError::Config(_)
+                | CheckoutError::Money(_) => (None, false),
+                _ => (None, true),
+            };
+            Attempt::failed(code, err.to_string(), retryable)
+        }
+    }
+}
+
+/// Copies the result of finished checkouts onto the runs that started them.
+async fn sync_checkout_runs(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE dstv_subscription_runs r
+        SET status = CASE WHEN c.step = $2 THEN $3 ELSE $4 END,
+            error = CASE WHEN c.step = $2 THEN NULL ELSE c.last_error END,
+            updated_at = NOW()
+        FROM dstv_checkouts c
+        WHERE r.checkout_id = c.id
+          AND r.status = $1
+          AND c.step IN ($2, $5, $6)
+        "#,
+        RunStatus::PaymentRequested.as_str(),
+        CheckoutStep::Confirmed.as_str(),
+        RunStatus::Confirmed.as_str(),
+        RunStatus::Failed.as_str(),
+        CheckoutStep::PaymentFailed.as_str(),
+        CheckoutStep::RefundRequired.as_str(),
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(updated)
+}
@@ -0,0 +1,34 @@
+use crate::models::bluecode::BluecodePaymentState;
+use sqlx::PgPool;
+
+/// Applies a Bluecode callback to the transaction whose `merchant_reference`
+/// matches the callback's `merchant_tx_id`.
+///
+/// States Bluecode reported that we do not know are not stored: the payload is
+/// kept and `qr_status` left as it was.
+///
+/// Returns the id of the updated row, or `None` when no such transaction exists.
+pub async fn record_bluecode_callback(
+    pool: &PgPool,
+    merchant_tx_id: &str,
+    state: &BluecodePaymentState,
+    payload: &serde_json::Value,
+) -> Result<Option<i32>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE transactions
+        SET qr_status = COALESCE($1, qr_status),
+            bluecode_callback_payload = $2,
+            bluecode_callback_at = NOW()
+        WHERE merchant_reference = $3
+        RETURNING id
+        "#,
+        state.known().map(|s| s.as_str()),
+        payload,
+        merchant_tx_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.id))
+}
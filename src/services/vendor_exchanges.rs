@@ -0,0 +1,298 @@
+//! Audit log of every call we make to Bluecode, PayU VAS and Quickteller.
+//!
+//! Adapters wrap each call in an [`Exchange`], started with the caller's
+//! pool, and finish it with the provider's answer or the transport error.
+//! The exchange is written to `vendor_exchanges` with credentials and tokens
+//! redacted and linked to the transaction its merchant reference belongs to.
+//! The insert runs in a background task, so the call never waits on it and
+//! never fails because of it; a failed insert is only logged.
+
+use crate::models::vendor_exchanges::{VendorExchange, VendorExchangeQuery};
+use reqwest::{RequestBuilder, StatusCode};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::fmt::Display;
+use std::time::Instant;
+
+/// Bodies are cut to this size before they are stored
+const MAX_BODY_BYTES: usize = 16 * 1024;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Keys whose values are never stored, matched case-insensitively as
+/// substrings (`access_token`, `client_secret`, ...)
+const SENSITIVE_KEYS: &[&str] = &["token", "secret", "password", "authorization", "api_key"];
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VendorProvider {
+    Bluecode,
+    PayuVas,
+    Quickteller,
+}
+
+impl VendorProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VendorProvider::Bluecode => "BLUECODE",
+            VendorProvider::PayuVas => "PAYU_VAS",
+            VendorProvider::Quickteller => "QUICKTELLER",
+        }
+    }
+}
+
+/// One outbound call, timed from [`Exchange::start`] and recorded into the
+/// pool it was started with.
+#[derive(Debug)]
+pub struct Exchange {
+    pool: PgPool,
+    provider: VendorProvider,
+    operation: &'static str,
+    method: &'static str,
+    url: String,
+    correlation_id: Option<String>,
+    request_body: Option<String>,
+    started: Instant,
+}
+
+impl Exchange {
+    pub fn start(
+        pool: &PgPool,
+        provider: VendorProvider,
+        operation: &'static str,
+        method: &'static str,
+        url: &str,
+    ) -> Self {
+        Exchange {
+            pool: pool.clone(),
+            provider,
+            operation,
+            method,
+            url: url.to_string(),
+            correlation_id: None,
+            request_body: None,
+            started: Instant::now(),
+        }
+    }
+
+    /// Merchant reference sent with the call.
+    pub fn correlation_id(mut self, correlation_id: &str) -> Self {
+        self.correlation_id = Some(correlation_id.to_string());
+        self
+    }
+
+    pub fn request_body(mut self, body: &str) -> Self {
+        self.request_body = Some(redact(body));
+        self
+    }
+
+    /// Sends `request` and records the outcome. The status and body are
+    /// returned whatever the status; only transport errors are errors.
+    pub async fn send(
+        self,
+        request: RequestBuilder,
+    ) -> Result<(StatusCode, String), reqwest::Error> {
+        let answer = match request.send().await {
+            Ok(response) => {
+                let status = response.status();
+                response.text().await.map(|body| (status, body))
+            }
+            Err(err) => Err(err),
+        };
+
+        match &answer {
+            Ok((status, body)) => self.response(status.as_u16(), body),
+            Err(err) => self.failed(err),
+        }
+        answer
+    }
+
+    /// Records the provider's answer, whatever its status.
+    pub fn response(self, status: u16, body: &str) {
+        self.record(Some(status), Some(redact(body)), None)
+    }
+
+    /// Records a call that got no answer.
+    pub fn failed(self, error: &(dyn Display + Sync)) {
+        self.record(None, None, Some(error.to_string()))
+    }
+
+    fn record(
+        self,
+        status_code: Option<u16>,
+        response_body: Option<String>,
+        error: Option<String>,
+    ) {
+        let latency_ms = self.started.elapsed().as_millis() as i64;
+        tracing::info!(
+            "🔌 {} {} {} -> {} in {}ms",
+            self.provider.as_str(),
+            self.operation,
+            self.correlation_id.as_deref().unwrap_or("-"),
+            status_code.map_or_else(|| "no response".to_string(), |s| s.to_string()),
+            latency_ms
+        );
+
+        tokio::spawn(self.store(status_code, response_body, error, latency_ms));
+    }
+
+    async fn store(
+        self,
+        status_code: Option<u16>,
+        response_body: Option<String>,
+        error: Option<String>,
+        latency_ms: i64,
+    ) {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO vendor_exchanges
+                (provider, operation, method, url, request_body, response_body, status_code,
+                 error, latency_ms, correlation_id, transaction_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, COALESCE(
+                (SELECT transaction_id FROM merchant_references WHERE reference = $10),
+                (SELECT id FROM transactions WHERE merchant_reference = $10 ORDER BY id LIMIT 1)
+            ))
+            "#,
+            self.provider.as_str(),
+            self.operation,
+            self.method,
+            self.url,
+            self.request_body,
+            response_body,
+            status_code.map(i32::from),
+            error,
+            latency_ms,
+            self.correlation_id,
+        )
+        .execute(&self.pool)
+        .await;
+
+        if let Err(err) = result {
+            tracing::warn!(
+                "⚠️ Failed to store {} {} exchange: {}",
+                self.provider.as_str(),
+                self.operation,
+                err
+            );
+        }
+    }
+}
+
+/// Masks credentials and tokens in JSON and form bodies and caps the size.
+/// XML bodies are kept as they are: PayU takes its credentials in the
+/// `Authorization` header, never in the document.
+pub fn redact(body: &str) -> String {
+    let trimmed = body.trim_start();
+    let redacted = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        match serde_json::from_str::<Value>(body) {
+            Ok(mut json) => {
+                redact_json(&mut json);
+                json.to_string()
+            }
+            Err(_) => body.to_string(),
+        }
+    } else if !trimmed.starts_with('<') && body.contains('=') && !body.contains(char::is_whitespace)
+    {
+        body.split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if is_sensitive(key) => format!("{}={}", key, REDACTED),
+                _ => pair.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    } else {
+        body.to_string()
+    };
+
+    truncate(redacted)
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_sensitive(key) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SENSITIVE_KEYS
+        .iter()
+        .any(|sensitive| key.contains(sensitive))
+}
+
+fn truncate(mut body: String) -> String {
+    if body.len() > MAX_BODY_BYTES {
+        let mut end = MAX_BODY_BYTES;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        body.truncate(end);
+        body.push_str("…[truncated]");
+    }
+    body
+}
+
+/// Newest exchanges first, matching every filter that is set.
+pub async fn search_exchanges(
+    pool: &PgPool,
+    query: &VendorExchangeQuery,
+) -> Result<Vec<VendorExchange>, sqlx::Error> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    sqlx::query_as!(
+        VendorExchange,
+        r#"
+        SELECT id, provider, operation, method, url, request_body, response_body, status_code,
+               error, latency_ms, correlation_id, transaction_id, created_at
+        FROM vendor_exchanges
+        WHERE ($1::TEXT IS NULL OR provider = UPPER($1))
+          AND ($2::TEXT IS NULL OR operation = $2)
+          AND ($3::TEXT IS NULL OR correlation_id = $3)
+          AND ($4::INTEGER IS NULL OR transaction_id = $4)
+          AND ($5::INTEGER IS NULL OR status_code = $5)
+          AND (NOT $6 OR status_code IS NULL OR status_code NOT BETWEEN 200 AND 299)
+          AND ($7::TIMESTAMPTZ IS NULL OR created_at >= $7)
+          AND ($8::TIMESTAMPTZ IS NULL OR created_at < $8)
+        ORDER BY created_at DESC, id DESC
+        LIMIT $9
+        "#,
+        query.provider,
+        query.operation,
+        query.correlation_id,
+        query.transaction_id,
+        query.status_code,
+        query.failed,
+        query.from,
+        query.to,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn find_exchange(pool: &PgPool, id: i64) -> Result<Option<VendorExchange>, sqlx::Error> {
+    sqlx::query_as!(
+        VendorExchange,
+        r#"
+        SELECT id, provider, operation, method, url, request_body, response_body, status_code,
+               error, latency_ms, correlation_id, transaction_id, created_at
+        FROM vendor_exchanges
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
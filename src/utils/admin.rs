@@ -0,0 +1,42 @@
+use axum::extract::Request;
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hmac::digest::CtOutput;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+/// Guards admin routes with the shared `ADMIN_API_KEY`. Requests are refused
+/// when the key is not configured.
+pub async fn require_admin_key(request: Request, next: Next) -> Response {
+    if !has_admin_key(request.headers()) {
+        tracing::warn!("🚫 Rejected admin request to {}", request.uri().path());
+        return (StatusCode::UNAUTHORIZED, "Unauthorized").into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Whether the request carries the configured `ADMIN_API_KEY`.
+pub fn has_admin_key(headers: &HeaderMap) -> bool {
+    let expected = std::env::var("ADMIN_API_KEY").unwrap_or_default();
+    let provided = headers
+        .get(ADMIN_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    !expected.is_empty() && keys_match(&expected, provided)
+}
+
+/// Compares keys in constant time by comparing their MACs.
+fn keys_match(expected: &str, provided: &str) -> bool {
+    fn digest(key: &str) -> CtOutput<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"admin-key").expect("HMAC accepts any key");
+        mac.update(key.as_bytes());
+        mac.finalize()
+    }
+
+    digest(expected) == digest(provided)
+}
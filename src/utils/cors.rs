@@ -0,0 +1,19 @@
+//! CORS policy for browser clients.
+
+use crate::routes::subscriptions::SUBSCRIPTION_TOKEN_HEADER;
+use crate::utils::idempotency::{IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER};
+use axum::http::{header, HeaderName, Method};
+use tower_http::cors::{Any, CorsLayer};
+
+/// Lets any origin call the API with the headers its routes read.
+pub fn cors_layer() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+            HeaderName::from_static(SUBSCRIPTION_TOKEN_HEADER),
+        ])
+        .expose_headers([HeaderName::from_static(IDEMPOTENT_REPLAYED_HEADER)])
+}
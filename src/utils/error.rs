@@ -14,6 +14,17 @@ pub enum ApiError {
     #[error("HTTP error: {0}")]
     HttpError(String),
 
+    /// The upstream answered with a non-success status
+    #[error("Upstream returned {status}: {body}")]
+    UpstreamStatus {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+
+    /// The upstream rejected our access token
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
     #[error("Parse error: {0}")]
     ParseError(String),
 }
@@ -0,0 +1,251 @@
+//! `Idempotency-Key` support for mutating endpoints.
+//!
+//! The first request with a given key runs normally and its response is
+//! stored, server errors included: a 5xx may follow a provider call that
+//! went through, so running the request again under the same key could pay
+//! twice. Repeats with the same body get the stored response back; a reused
+//! key with a different body is refused with 422. A client that wants
+//! another attempt after a failure sends a new key.
+//!
+//! Keys expire after `IDEMPOTENCY_TTL_SECS`. An expired key is reclaimed
+//! when it is used again, and [`spawn_idempotency_pruner`] deletes the rest
+//! in the background.
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::time::Duration;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+const MAX_REQUEST_BYTES: usize = 256 * 1024;
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+const DEFAULT_TTL_SECS: i64 = 24 * 60 * 60;
+const PRUNE_INTERVAL_SECS: u64 = 60 * 60;
+
+enum Claim {
+    /// First time the key is seen, the request should run
+    New,
+    /// Same key and body already answered
+    Completed {
+        status_code: i16,
+        content_type: Option<String>,
+        body: Vec<u8>,
+    },
+    /// Same key and body, the first request has not finished yet
+    InProgress,
+    /// Key already used for a different request body
+    Mismatch,
+}
+
+pub async fn idempotency(State(pool): State<PgPool>, request: Request, next: Next) -> Response {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.trim().is_empty() && key.len() <= MAX_KEY_LENGTH => key.to_string(),
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Idempotency-Key must be 1 to {MAX_KEY_LENGTH} visible characters"),
+            )
+                .into_response()
+        }
+    };
+
+    let scope = format!("{} {}", request.method(), request.uri().path());
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, MAX_REQUEST_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::warn!("⚠️ Could not read request body for {}: {}", scope, err);
+            return (StatusCode::BAD_REQUEST, "Invalid request body").into_response();
+        }
+    };
+    let request_hash = hex::encode(Sha256::digest(&bytes));
+
+    match claim_key(&pool, &key, &scope, &request_hash).await {
+        Ok(Claim::New) => {}
+        Ok(Claim::Completed {
+            status_code,
+            content_type,
+            body,
+        }) => {
+            tracing::info!("🔁 Replaying stored response for {} ({})", scope, key);
+            return replay(status_code, content_type, body);
+        }
+        Ok(Claim::InProgress) => {
+            return (
+                StatusCode::CONFLICT,
+                "A request with this Idempotency-Key is still being processed",
+            )
+                .into_response()
+        }
+        Ok(Claim::Mismatch) => {
+            tracing::warn!("🚫 Idempotency-Key {} reused with a different body", key);
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Idempotency-Key was already used with a different request body",
+            )
+                .into_response();
+        }
+        Err(err) => {
+            tracing::error!("❌ Failed to claim Idempotency-Key {}: {}", key, err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+        }
+    }
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, MAX_RESPONSE_BYTES).await {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::error!("❌ Could not buffer response for {}: {}", scope, err);
+            parts.status = StatusCode::INTERNAL_SERVER_ERROR;
+            parts.headers.remove(header::CONTENT_TYPE);
+            "Failed to read response".into()
+        }
+    };
+
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if let Err(err) = sqlx::query!(
+        r#"
+        UPDATE idempotency_keys
+        SET status_code = $3, content_type = $4, response_body = $5
+        WHERE idempotency_key = $1 AND scope = $2
+        "#,
+        key,
+        scope,
+        parts.status.as_u16() as i16,
+        content_type,
+        body.as_ref(),
+    )
+    .execute(&pool)
+    .await
+    {
+        tracing::error!(
+            "❌ Failed to store response for Idempotency-Key {}: {}",
+            key,
+            err
+        );
+    }
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+async fn claim_key(
+    pool: &PgPool,
+    key: &str,
+    scope: &str,
+    request_hash: &str,
+) -> Result<Claim, sqlx::Error> {
+    // An expired key is taken over as if it had never been used
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO idempotency_keys (idempotency_key, scope, request_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (idempotency_key, scope) DO UPDATE
+        SET request_hash = EXCLUDED.request_hash, status_code = NULL, content_type = NULL,
+            response_body = NULL, created_at = NOW()
+        WHERE idempotency_keys.created_at < NOW() - make_interval(secs => $4)
+        "#,
+        key,
+        scope,
+        request_hash,
+        ttl_secs() as f64
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    if inserted == 1 {
+        return Ok(Claim::New);
+    }
+
+    let stored = sqlx::query!(
+        r#"
+        SELECT request_hash, status_code, content_type, response_body
+        FROM idempotency_keys
+        WHERE idempotency_key = $1 AND scope = $2
+        "#,
+        key,
+        scope
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let claim = match stored {
+        // Expired and pruned in between
+        None => Claim::InProgress,
+        Some(row) if row.request_hash != request_hash => Claim::Mismatch,
+        Some(row) => match (row.status_code, row.response_body) {
+            (Some(status_code), Some(body)) => Claim::Completed {
+                status_code,
+                content_type: row.content_type,
+                body,
+            },
+            _ => Claim::InProgress,
+        },
+    };
+
+    Ok(claim)
+}
+
+fn ttl_secs() -> i64 {
+    std::env::var("IDEMPOTENCY_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TTL_SECS)
+}
+
+/// Deletes expired keys. Returns how many were removed.
+pub async fn prune_idempotency_keys(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let deleted = sqlx::query!(
+        "DELETE FROM idempotency_keys WHERE created_at < NOW() - make_interval(secs => $1)",
+        ttl_secs() as f64
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(deleted)
+}
+
+/// Prunes expired keys periodically, off the request path.
+pub fn spawn_idempotency_pruner(pool: PgPool) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(PRUNE_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            match prune_idempotency_keys(&pool).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("🧹 Pruned {} expired Idempotency-Keys", count),
+                Err(err) => tracing::error!("❌ Failed to prune Idempotency-Keys: {}", err),
+            }
+        }
+    })
+}
+
+fn replay(status_code: i16, content_type: Option<String>, body: Vec<u8>) -> Response {
+    let status = StatusCode::from_u16(status_code as u16).unwrap_or(StatusCode::OK);
+    let mut response = (status, body).into_response();
+    let headers = response.headers_mut();
+    match content_type.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        Some(content_type) => headers.insert(header::CONTENT_TYPE, content_type),
+        None => headers.remove(header::CONTENT_TYPE),
+    };
+    headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
@@ -1,2 +1,5 @@
+pub mod admin;
+pub mod cors;
 pub mod error;
+pub mod idempotency;
 pub mod logger;
@@ -1,13 +1,15 @@
 use bills_backend::services::airtime::get_billers_by_category;
 use bills_backend::services::billers::get_quickteller_access_token;
+use bills_backend::services::quickteller_auth::TokenManager;
+use sqlx::PgPool;
 
-#[tokio::test]
-async fn test_get_biller_payment_items_success() {
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_get_biller_payment_items_success(pool: PgPool) {
     use bills_backend::services::airtime::get_biller_payment_items;
 
     dotenvy::dotenv().ok();
 
-    let token_result = get_quickteller_access_token().await;
+    let token_result = get_quickteller_access_token(&pool, &TokenManager::new()).await;
     assert!(
         token_result.is_ok(),
         "❌ Failed to get access token: {:?}",
@@ -18,7 +20,7 @@ async fn test_get_biller_payment_items_success() {
 
     let service_id = 17305; // Airtel
 
-    let result = get_biller_payment_items(&access_token, service_id).await;
+    let result = get_biller_payment_items(&pool, &access_token, service_id).await;
 
     assert!(
         result.is_ok(),
@@ -26,20 +28,20 @@ async fn test_get_biller_payment_items_success() {
         result.err()
     );
 
-    let json = result.unwrap();
-    println!("✅ Biller payment items: {:#}", json);
+    let items = result.unwrap();
+    println!("✅ Biller payment items: {:#?}", items);
 
     assert!(
-        json.get("ResponseCode").is_some(),
+        items.response_code.is_some(),
         "❌ Missing 'ResponseCode' in response"
     );
 }
 
-#[tokio::test]
-async fn test_get_billers_by_category_success() {
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_get_billers_by_category_success(pool: PgPool) {
     dotenvy::dotenv().ok();
 
-    let token_result = get_quickteller_access_token().await;
+    let token_result = get_quickteller_access_token(&pool, &TokenManager::new()).await;
     assert!(
         token_result.is_ok(),
         "❌ Failed to get access token: {:?}",
@@ -47,21 +49,20 @@ async fn test_get_billers_by_category_success() {
     );
     let access_token = token_result.unwrap();
 
-    let result = get_billers_by_category(4, &access_token).await;
+    let result = get_billers_by_category(&pool, 4, &access_token).await;
     assert!(
         result.is_ok(),
         "❌ Failed to get billers for category: {:?}",
         result.err()
     );
 
-    let json = result.unwrap();
+    let billers = result.unwrap();
 
-    // You can adjust this assertion depending on how the response is structured
     assert!(
-        json.get("BillerList").is_some(),
-        "❌ Expected 'BillerList' key in response: {:?}",
-        json
+        !billers.biller_list.category.is_empty(),
+        "❌ Expected billers in response: {:?}",
+        billers
     );
 
-    println!("✅ Billers by category response: {:?}", json);
+    println!("✅ Billers by category response: {:?}", billers);
 }
@@ -0,0 +1,247 @@
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::{Extension, Router};
+use bills_backend::routes::airtime::airtime_routes;
+use bills_backend::services::quickteller_auth::TokenManager;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, Request as WiremockRequest, ResponseTemplate};
+
+mod common;
+
+fn reply(grouping: &str, code: &str, transaction_ref: &str) -> Value {
+    json!({
+        "TransactionRef": transaction_ref,
+        "ResponseCode": code,
+        "ResponseCodeGrouping": grouping
+    })
+}
+
+/// Quickteller answers each phone number differently; queries are matched
+/// to the phone number the reference was sent for.
+async fn mock_topups() -> MockServer {
+    let server = common::mock_quickteller("airtime-token").await;
+    let sent: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/services/options"))
+        .and(query_param("serviceid", "109"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "PaymentItems": [{
+                "Id": "0",
+                "Name": "MTN Mobile Top-up",
+                "PaymentCode": "10902",
+                "Amount": "0",
+                "IsAmountFixed": false,
+                "MinimumAmount": "5000",
+                "MaximumAmount": "1000000"
+            }],
+            "ResponseCode": "90000",
+            "ResponseCodeGrouping": "SUCCESSFUL"
+        })))
+        .mount(&server)
+        .await;
+
+    let advices = sent.clone();
+    Mock::given(method("POST"))
+        .and(path("/quicktellerservice/api/v5/Transactions"))
+        .respond_with(move |req: &WiremockRequest| {
+            let advice: Value = serde_json::from_slice(&req.body).unwrap();
+            let phone = advice["CustomerId"].as_str().unwrap().to_string();
+            let reference = advice["RequestReference"].as_str().unwrap().to_string();
+            advices.lock().unwrap().insert(reference, phone.clone());
+
+            match phone.as_str() {
+                "08030000001" => ResponseTemplate::new(200).set_body_json(reply(
+                    "SUCCESSFUL",
+                    "90000",
+                    "QT-REF-1",
+                )),
+                "08030000002" => {
+                    ResponseTemplate::new(200).set_body_json(reply("PENDING", "90009", "QT-REF-2"))
+                }
+                "08030000003" => ResponseTemplate::new(500).set_body_string("Internal error"),
+                _ => ResponseTemplate::new(400).set_body_json(json!({
+                    "ResponseCode": "70008",
+                    "ResponseCodeGrouping": "FAILED",
+                    "ResponseDescription": "Invalid customer"
+                })),
+            }
+        })
+        .mount(&server)
+        .await;
+
+    let queries = sent.clone();
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/Transactions"))
+        .respond_with(move |req: &WiremockRequest| {
+            let reference = req
+                .url
+                .query_pairs()
+                .find(|(key, _)| key == "requestRef")
+                .map(|(_, value)| value.to_string())
+                .unwrap();
+            let phone = queries.lock().unwrap().get(&reference).cloned();
+
+            match phone.as_deref() {
+                Some("08030000002") => ResponseTemplate::new(200).set_body_json(reply(
+                    "SUCCESSFUL",
+                    "90000",
+                    "QT-REF-2",
+                )),
+                _ => ResponseTemplate::new(404).set_body_string("Transaction not found"),
+            }
+        })
+        .mount(&server)
+        .await;
+
+    server
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, body.to_vec())
+}
+
+async fn topup(app: &Router, phone_number: &str, amount: i64) -> (StatusCode, Vec<u8>) {
+    let request = Request::post("/airtime/topup")
+        .header("content-type", "application/json")
+        .header("X-Admin-Key", "test-admin-key")
+        .body(Body::from(
+            json!({ "phone_number": phone_number, "network": "mtn", "amount": amount }).to_string(),
+        ))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn confirm_status(pool: &PgPool, reference: &str) -> String {
+    sqlx::query_scalar("SELECT confirm_status FROM transactions WHERE merchant_reference = $1")
+        .bind(reference)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_airtime_topups(pool: PgPool) {
+    let server = mock_topups().await;
+    std::env::set_var("AIRTIME_REQUERY_DELAY_MS", "10");
+    std::env::set_var("ADMIN_API_KEY", "test-admin-key");
+
+    let app = Router::new()
+        .nest("/airtime", airtime_routes(pool.clone()))
+        .layer(Extension(TokenManager::new()))
+        .with_state(pool.clone());
+
+    // Airtime is bought from our float, so callers need the admin key
+    let (status, _) = send(
+        &app,
+        Request::post("/airtime/topup")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({ "phone_number": "08030000001", "network": "mtn", "amount": 10_000 })
+                    .to_string(),
+            ))
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Delivered straight away
+    let (status, body) = topup(&app, "+234 803 000 0001", 10_000).await;
+    assert_eq!(status, StatusCode::OK);
+    let delivered: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(delivered["status"], "SUCCESSFUL");
+    assert_eq!(delivered["transaction_ref"], "QT-REF-1");
+    assert_eq!(delivered["phone_number"], "08030000001");
+    assert_eq!(delivered["payment_code"], "10902");
+    let reference = delivered["request_reference"].as_str().unwrap();
+    assert!(reference.starts_with("QTL-"));
+    assert_eq!(confirm_status(&pool, reference).await, "CONFIRMED");
+
+    // Pending, then settled by a requery
+    let (status, body) = topup(&app, "08030000002", 20_000).await;
+    assert_eq!(status, StatusCode::OK);
+    let settled: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(settled["status"], "SUCCESSFUL");
+    assert_eq!(settled["transaction_ref"], "QT-REF-2");
+
+    // The advice failed and Quickteller does not know the reference yet
+    let (status, body) = topup(&app, "08030000003", 20_000).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let lost: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(lost["status"], "PENDING");
+    let lost_reference = lost["request_reference"].as_str().unwrap();
+    assert_eq!(confirm_status(&pool, lost_reference).await, "PENDING");
+
+    // Once it is old enough, an unknown reference means it was never made
+    sqlx::query(
+        "UPDATE airtime_topups SET created_at = NOW() - INTERVAL '1 hour' WHERE request_reference = $1",
+    )
+    .bind(lost_reference)
+    .execute(&pool)
+    .await
+    .unwrap();
+    let (status, body) = send(
+        &app,
+        Request::get(format!("/airtime/topup/{}", lost_reference))
+            .header("X-Admin-Key", "test-admin-key")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let lost: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(lost["status"], "FAILED");
+    assert_eq!(confirm_status(&pool, lost_reference).await, "FAILED");
+
+    // Declined by Quickteller
+    let (status, body) = topup(&app, "08030000004", 20_000).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let declined: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(declined["response_code"], "70008");
+    assert_eq!(declined["message"], "Invalid customer");
+
+    // Refused before anything is sent
+    let (status, _) = topup(&app, "08030000001", 2_000_000).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = topup(&app, "12345", 10_000).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let advices = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.method.as_str() == "POST" && r.url.path().ends_with("/Transactions"))
+        .count();
+    assert_eq!(advices, 4);
+
+    // Top-ups can be looked up by reference
+    let (status, body) = send(
+        &app,
+        Request::get(format!("/airtime/topup/{}", reference))
+            .header("X-Admin-Key", "test-admin-key")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let fetched: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(fetched["transaction_ref"], "QT-REF-1");
+
+    let (status, _) = send(
+        &app,
+        Request::get("/airtime/topup/QTL-unknown")
+            .header("X-Admin-Key", "test-admin-key")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
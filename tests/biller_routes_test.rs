@@ -0,0 +1,152 @@
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::{Extension, Router};
+use bills_backend::routes::billers::biller_routes;
+use bills_backend::services::quickteller_auth::TokenManager;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower::ServiceExt;
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+mod common;
+
+async fn mock_catalog() -> MockServer {
+    let server = common::mock_quickteller("catalog-token").await;
+
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/services/categories"))
+        .and(header("Authorization", "Bearer catalog-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "BillerCategories": [
+                { "Id": 4, "Name": "Mobile Recharge", "Description": "Airtime top-up" }
+            ],
+            "ResponseCode": "90000",
+            "ResponseCodeGrouping": "SUCCESSFUL"
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/services"))
+        .and(query_param("categoryId", "4"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "BillerList": {
+                "Count": 1,
+                "Category": [{
+                    "Id": 4,
+                    "Name": "Mobile Recharge",
+                    "Description": "Airtime top-up",
+                    "Billers": [{ "Id": 17305, "Name": "Airtel", "ShortName": "AIRTEL" }]
+                }]
+            },
+            "ResponseCode": "90000",
+            "ResponseCodeGrouping": "SUCCESSFUL"
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/services"))
+        .and(query_param("categoryId", "99"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("Category not found"))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/services/options"))
+        .and(query_param("serviceid", "17305"))
+        .and(header("terminalId", "3TST0001"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "PaymentItems": [{
+                "Id": "0",
+                "Name": "Airtel Mobile Top-up",
+                "BillerId": "17305",
+                "ConsumerIdField": "Mobile Number",
+                "Code": "01",
+                "PaymentCode": "1730501",
+                "Amount": "0",
+                "IsAmountFixed": false,
+                "ItemFee": "0",
+                "CurrencyCode": "566"
+            }],
+            "ResponseCode": "90000",
+            "ResponseCodeGrouping": "SUCCESSFUL"
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/services/options"))
+        .and(query_param("serviceid", "500"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("Service unavailable"))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, body.to_vec())
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_biller_catalog_routes(pool: PgPool) {
+    let server = mock_catalog().await;
+
+    let app = Router::new()
+        .nest("/billers", biller_routes())
+        .layer(Extension(TokenManager::new()))
+        .with_state(pool.clone());
+
+    let (status, body) = get(&app, "/billers/categories").await;
+    assert_eq!(status, StatusCode::OK);
+    let categories: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(categories["response_code"], "90000");
+    assert_eq!(categories["biller_categories"][0]["id"], 4);
+    assert_eq!(
+        categories["biller_categories"][0]["name"],
+        "Mobile Recharge"
+    );
+
+    let (status, body) = get(&app, "/billers/categories/4").await;
+    assert_eq!(status, StatusCode::OK);
+    let billers: Value = serde_json::from_slice(&body).unwrap();
+    let biller = &billers["biller_list"]["category"][0]["billers"][0];
+    assert_eq!(biller["id"], 17305);
+    assert_eq!(biller["short_name"], "AIRTEL");
+
+    let (status, body) = get(&app, "/billers/17305/items").await;
+    assert_eq!(status, StatusCode::OK);
+    let items: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(items["payment_items"][0]["payment_code"], "1730501");
+    assert_eq!(items["payment_items"][0]["is_amount_fixed"], false);
+
+    // One token served every call
+    let token_requests = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/passport/oauth/token")
+        .count();
+    assert_eq!(token_requests, 1);
+
+    // Upstream failures keep a meaningful status, but not the upstream body
+    let (status, _) = get(&app, "/billers/categories/99").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, body) = get(&app, "/billers/500/items").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(String::from_utf8_lossy(&body), "Quickteller request failed");
+
+    let (status, _) = get(&app, "/billers/not-a-number/items").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
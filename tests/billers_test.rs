@@ -1,12 +1,13 @@
 use bills_backend::services::billers::get_biller_categories;
 use bills_backend::services::billers::get_quickteller_access_token;
-use dotenvy;
+use bills_backend::services::quickteller_auth::TokenManager;
+use sqlx::PgPool;
 
-#[tokio::test]
-async fn test_get_biller_categories_success() {
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_get_biller_categories_success(pool: PgPool) {
     dotenvy::dotenv().ok();
 
-    let token_result = get_quickteller_access_token().await;
+    let token_result = get_quickteller_access_token(&pool, &TokenManager::new()).await;
     assert!(
         token_result.is_ok(),
         "❌ Failed to get access token: {:?}",
@@ -14,7 +15,7 @@ async fn test_get_biller_categories_success() {
     );
 
     let token = token_result.unwrap();
-    let result = get_biller_categories(&token).await;
+    let result = get_biller_categories(&pool, &token).await;
 
     assert!(
         result.is_ok(),
@@ -43,16 +44,18 @@ async fn test_get_biller_categories_success() {
     for category in parsed.biller_categories {
         println!(
             "• {} (ID: {}) — {}",
-            category.name, category.id, category.description
+            category.name,
+            category.id,
+            category.description.as_deref().unwrap_or("")
         );
     }
 }
 
-#[tokio::test]
-async fn test_get_quickteller_access_token() {
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_get_quickteller_access_token(pool: PgPool) {
     dotenvy::dotenv().ok();
 
-    let result = get_quickteller_access_token().await;
+    let result = get_quickteller_access_token(&pool, &TokenManager::new()).await;
 
     assert!(
         result.is_ok(),
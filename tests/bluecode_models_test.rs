@@ -0,0 +1,51 @@
+use bills_backend::models::bluecode::{BluecodePaymentState, BluecodeStatusResponseWrapper};
+use serde_json::json;
+
+#[test]
+fn test_status_response_full_payload() {
+    let payload = json!({
+        "result": "OK",
+        "payment": {
+            "state": "APPROVED",
+            "merchant_tx_id": "TXN-12345678",
+            "requested_amount": 1500000,
+            "total_amount": 1520000,
+            "currency": "NGN",
+            "acquirer_tx_id": "ACQ-998877",
+            "scheme": "blue_code",
+            "consumer_tip_amount": 20000,
+            "slip": "Thank you for paying with Bluecode",
+            "created_at": "2025-05-29T10:15:00Z",
+            "updated_at": "2025-05-29T10:16:12Z"
+        }
+    });
+
+    let parsed: BluecodeStatusResponseWrapper = serde_json::from_value(payload).unwrap();
+    let payment = parsed.payment;
+
+    assert_eq!(payment.state, BluecodePaymentState::Approved);
+    assert!(payment.state.is_final());
+    assert_eq!(payment.total_amount, Some(1520000));
+    assert_eq!(payment.consumer_tip_amount, Some(20000));
+    assert_eq!(payment.acquirer_tx_id.as_deref(), Some("ACQ-998877"));
+    assert!(payment.updated_at.is_some());
+}
+
+#[test]
+fn test_unknown_state_is_kept_verbatim() {
+    let payload = json!({
+        "result": "OK",
+        "payment": { "state": "CHARGEBACK", "merchant_tx_id": "TXN-1" }
+    });
+
+    let parsed: BluecodeStatusResponseWrapper = serde_json::from_value(payload).unwrap();
+
+    assert_eq!(
+        parsed.payment.state,
+        BluecodePaymentState::Unknown("CHARGEBACK".into())
+    );
+    assert_eq!(
+        serde_json::to_value(&parsed).unwrap()["payment"]["state"],
+        "CHARGEBACK"
+    );
+}
@@ -0,0 +1,126 @@
+use bills_backend::services::bluecode_poller::{poll_stale_payments, PollerSettings};
+use serde_json::json;
+use sqlx::PgPool;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn insert_pending(pool: &PgPool, merchant_reference: &str, age_secs: i64) {
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp, created_at)
+        VALUES ($1, '300115673', 'COMPE36', 1500000, 'REGISTERED', 'PENDING', 0, NOW() - make_interval(secs => $2))
+        "#,
+    )
+    .bind(merchant_reference)
+    .bind(age_secs as f64)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn qr_status(pool: &PgPool, merchant_reference: &str) -> String {
+    let (status,): (String,) =
+        sqlx::query_as("SELECT qr_status FROM transactions WHERE merchant_reference = $1")
+            .bind(merchant_reference)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    status
+}
+
+fn status_response(merchant_tx_id: &str, state: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "result": "OK",
+        "payment": { "merchant_tx_id": merchant_tx_id, "state": state }
+    }))
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_poller_settles_and_expires_stale_payments(pool: PgPool) {
+    let server = MockServer::start().await;
+    for (id, state) in [
+        ("TXN-paid", "APPROVED"),
+        ("TXN-waiting", "REGISTERED"),
+        ("TXN-abandoned", "REGISTERED"),
+        ("TXN-uncancelled", "REGISTERED"),
+    ] {
+        Mock::given(method("POST"))
+            .and(path("/v4/status"))
+            .and(body_partial_json(json!({ "merchant_tx_id": id })))
+            .respond_with(status_response(id, state))
+            .mount(&server)
+            .await;
+    }
+    // Bluecode cannot be asked about this one, so it is not cancelled either
+    Mock::given(method("POST"))
+        .and(path("/v4/status"))
+        .and(body_partial_json(
+            json!({ "merchant_tx_id": "TXN-unreachable" }),
+        ))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v4/cancel"))
+        .and(body_partial_json(
+            json!({ "merchant_tx_id": "TXN-uncancelled" }),
+        ))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v4/cancel"))
+        .and(body_partial_json(
+            json!({ "merchant_tx_id": "TXN-abandoned" }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "result": "OK" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    std::env::set_var("BLUECODE_API_BASE_URL", server.uri());
+    std::env::set_var("BLUECODE_MERCHANT_ACCESS", "access");
+    std::env::set_var("BLUECODE_MERCHANT_SECRET", "secret");
+
+    insert_pending(&pool, "TXN-paid", 600).await;
+    insert_pending(&pool, "TXN-waiting", 600).await;
+    insert_pending(&pool, "TXN-abandoned", 7200).await;
+    insert_pending(&pool, "TXN-uncancelled", 7200).await;
+    insert_pending(&pool, "TXN-unreachable", 7200).await;
+    insert_pending(&pool, "TXN-fresh", 10).await;
+
+    let settings = PollerSettings {
+        interval_secs: 60,
+        min_age_secs: 120,
+        batch_size: 10,
+        registration_ttl_secs: 3600,
+    };
+
+    // Another instance holding the lock keeps this one from polling
+    let mut other = pool.acquire().await.unwrap();
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(0x626c_7565_636f_6465_i64)
+        .execute(&mut *other)
+        .await
+        .unwrap();
+    assert_eq!(poll_stale_payments(&pool, &settings).await.unwrap(), 0);
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(0x626c_7565_636f_6465_i64)
+        .execute(&mut *other)
+        .await
+        .unwrap();
+    drop(other);
+
+    assert_eq!(poll_stale_payments(&pool, &settings).await.unwrap(), 5);
+
+    assert_eq!(qr_status(&pool, "TXN-paid").await, "APPROVED");
+    assert_eq!(qr_status(&pool, "TXN-waiting").await, "REGISTERED");
+    assert_eq!(qr_status(&pool, "TXN-abandoned").await, "CANCELLED");
+    assert_eq!(qr_status(&pool, "TXN-uncancelled").await, "REGISTERED");
+    assert_eq!(qr_status(&pool, "TXN-unreachable").await, "REGISTERED");
+    assert_eq!(qr_status(&pool, "TXN-fresh").await, "REGISTERED");
+
+    // Just-polled payments are left alone until min_age_secs has passed again
+    assert_eq!(poll_stale_payments(&pool, &settings).await.unwrap(), 0);
+}
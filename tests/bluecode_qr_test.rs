@@ -0,0 +1,119 @@
+use axum::body::to_bytes;
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use bills_backend::routes::bluecode::bluecode_routes;
+use sqlx::PgPool;
+use tower::ServiceExt; // for `oneshot`
+
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+async fn insert_registration(pool: &PgPool, merchant_tx_id: &str, checkin_code: &str) {
+    sqlx::query(
+        r#"
+        INSERT INTO bluecode_registrations (merchant_tx_id, checkin_code, requested_amount, currency)
+        VALUES ($1, $2, 1500000, 'NGN')
+        "#,
+    )
+    .bind(merchant_tx_id)
+    .bind(checkin_code)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+fn get(uri: &str) -> Request<Body> {
+    Request::builder()
+        .method("GET")
+        .uri(uri)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_qr_images_and_deep_link(pool: PgPool) {
+    std::env::remove_var("BLUECODE_DEEP_LINK_TEMPLATE");
+
+    let logo_path = std::env::temp_dir().join("bluecode_qr_test_logo.png");
+    image::RgbaImage::from_pixel(16, 16, image::Rgba([0, 90, 200, 255]))
+        .save(&logo_path)
+        .unwrap();
+    std::env::set_var("BLUECODE_QR_LOGO_PATH", &logo_path);
+
+    insert_registration(&pool, "TXN-qr", "98765432").await;
+
+    let app = Router::new()
+        .nest("/bluecode", bluecode_routes(pool.clone()))
+        .with_state(pool.clone());
+
+    // PNG is the default format
+    let response = app
+        .clone()
+        .oneshot(get("/bluecode/qr/TXN-qr"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+    let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    assert!(body.starts_with(PNG_MAGIC));
+    let png = image::load_from_memory(&body).unwrap();
+    assert!(png.width() >= 320);
+
+    // SVG with the logo embedded
+    let response = app
+        .clone()
+        .oneshot(get("/bluecode/qr/TXN-qr?format=svg&size=256&logo=true"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/svg+xml");
+    let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    let svg = String::from_utf8(body.to_vec()).unwrap();
+    assert!(svg.contains("<svg"));
+    assert!(svg.contains("data:image/png;base64,"));
+
+    // PNG with the logo
+    let response = app
+        .clone()
+        .oneshot(get("/bluecode/qr/TXN-qr?logo=true&ecc=q"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Deep link for apps that open the wallet directly
+    let response = app
+        .clone()
+        .oneshot(get("/bluecode/qr/TXN-qr/link"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    let link: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(link["checkin_code"], "98765432");
+    assert_eq!(link["deep_link"], "bluecode://checkin/98765432");
+
+    // Invalid options
+    let response = app
+        .clone()
+        .oneshot(get("/bluecode/qr/TXN-qr?size=10"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app
+        .clone()
+        .oneshot(get("/bluecode/qr/TXN-qr?ecc=Z"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Unknown payment
+    let response = app
+        .clone()
+        .oneshot(get("/bluecode/qr/TXN-missing"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,238 @@
+use axum::body::to_bytes;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use bills_backend::routes::bluecode::bluecode_routes;
+use bills_backend::services::refunds::resolve_pending_refunds;
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt; // for `oneshot`
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+async fn insert_transaction(pool: &PgPool, merchant_reference: &str, qr_status: &str) {
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ($1, '300115673', 'COMPE36', 1500000, $2, 'FAILED', 0)
+        "#,
+    )
+    .bind(merchant_reference)
+    .bind(qr_status)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+fn refund_reply(refund_tx_id: &str, amount: i64, state: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "result": "OK",
+        "refund": {
+            "refund_tx_id": refund_tx_id,
+            "merchant_tx_id": "TXN-paid",
+            "amount": amount,
+            "state": state
+        }
+    }))
+}
+
+async fn qr_status(pool: &PgPool, merchant_reference: &str) -> String {
+    sqlx::query_scalar("SELECT qr_status FROM transactions WHERE merchant_reference = $1")
+        .bind(merchant_reference)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+async fn refund_state(pool: &PgPool, amount: i64) -> String {
+    sqlx::query_scalar("SELECT state FROM bluecode_refunds WHERE amount = $1")
+        .bind(amount)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+fn admin_request(method: &str, uri: &str, body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("X-Admin-Key", "test-admin-key")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_refund_and_cancel_flow(pool: PgPool) {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v4/refund"))
+        .and(body_partial_json(json!({ "amount": 500000 })))
+        .respond_with(refund_reply("RF-1", 500000, "APPROVED"))
+        .mount(&mock_server)
+        .await;
+
+    // Times out on our side: Bluecode may or may not have refunded
+    Mock::given(method("POST"))
+        .and(path("/v4/refund"))
+        .and(body_partial_json(json!({ "amount": 400000 })))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v4/refund"))
+        .and(body_partial_json(json!({ "amount": 600000 })))
+        .respond_with(refund_reply("RF-3", 600000, "PENDING"))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v4/refund/status"))
+        .respond_with(refund_reply("RF-2", 400000, "APPROVED"))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v4/cancel"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "result": "OK" })))
+        .mount(&mock_server)
+        .await;
+
+    std::env::set_var("BLUECODE_API_BASE_URL", mock_server.uri());
+    std::env::set_var("BLUECODE_MERCHANT_ACCESS", "access");
+    std::env::set_var("BLUECODE_MERCHANT_SECRET", "secret");
+    std::env::set_var("ADMIN_API_KEY", "test-admin-key");
+
+    insert_transaction(&pool, "TXN-paid", "APPROVED").await;
+    insert_transaction(&pool, "TXN-unpaid", "REGISTERED").await;
+
+    let app = Router::new()
+        .nest("/bluecode", bluecode_routes(pool.clone()))
+        .with_state(pool.clone());
+
+    // Requests without the admin key never reach the handlers
+    let unauthenticated = Request::builder()
+        .method("POST")
+        .uri("/bluecode/admin/payments/TXN-unpaid/cancel")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(unauthenticated).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Partial refund
+    let response = app
+        .clone()
+        .oneshot(admin_request(
+            "POST",
+            "/bluecode/admin/payments/TXN-paid/refunds",
+            json!({ "amount": 500000, "reason": "DSTV confirmation failed" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    let refund: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(refund["amount"], 500000);
+    assert_eq!(refund["refund_tx_id"], "RF-1");
+
+    // Refunding more than what is left is refused
+    let response = app
+        .clone()
+        .oneshot(admin_request(
+            "POST",
+            "/bluecode/admin/payments/TXN-paid/refunds",
+            json!({ "amount": 1500000, "reason": "too much" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // A refund with no answer is kept as UNKNOWN and still counts as refunded
+    let response = app
+        .clone()
+        .oneshot(admin_request(
+            "POST",
+            "/bluecode/admin/payments/TXN-paid/refunds",
+            json!({ "amount": 400000, "reason": "timed out" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    let unknown: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(unknown["state"], "UNKNOWN");
+    assert!(unknown["refund_reference"]
+        .as_str()
+        .unwrap()
+        .starts_with("TXN-"));
+
+    let response = app
+        .clone()
+        .oneshot(admin_request(
+            "POST",
+            "/bluecode/admin/payments/TXN-paid/refunds",
+            json!({ "amount": 1000000, "reason": "again" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // A requery settles it
+    assert_eq!(resolve_pending_refunds(&pool, 0, 10).await.unwrap(), 1);
+    assert_eq!(refund_state(&pool, 400000).await, "APPROVED");
+
+    // The rest is refunded, but the payment is only marked refunded once
+    // Bluecode approves it
+    let response = app
+        .clone()
+        .oneshot(admin_request(
+            "POST",
+            "/bluecode/admin/payments/TXN-paid/refunds",
+            json!({ "reason": "the rest" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(refund_state(&pool, 600000).await, "PENDING");
+    assert_eq!(qr_status(&pool, "TXN-paid").await, "APPROVED");
+
+    assert_eq!(resolve_pending_refunds(&pool, 0, 10).await.unwrap(), 1);
+    assert_eq!(refund_state(&pool, 600000).await, "APPROVED");
+    assert_eq!(qr_status(&pool, "TXN-paid").await, "REFUNDED");
+
+    // Cannot refund a payment that was never approved
+    let response = app
+        .clone()
+        .oneshot(admin_request(
+            "POST",
+            "/bluecode/admin/payments/TXN-unpaid/refunds",
+            json!({ "reason": "not paid" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // Cancel the unpaid registration
+    let response = app
+        .clone()
+        .oneshot(admin_request(
+            "POST",
+            "/bluecode/admin/payments/TXN-unpaid/cancel",
+            json!({}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(qr_status(&pool, "TXN-unpaid").await, "CANCELLED");
+
+    let (refunds,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM bluecode_refunds")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(refunds, 3);
+}
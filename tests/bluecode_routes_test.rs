@@ -8,11 +8,38 @@ use axum::{
 use bills_backend::routes::bluecode::callback_handler;
 use bills_backend::routes::dstv::requery_handler;
 use serde_json::json;
+use sqlx::PgPool;
 use tower::ServiceExt; // for `oneshot`
 
-#[tokio::test]
-async fn test_bluecode_callback_handler() {
-    let app = Router::new().route("/bluecode/callback", post(callback_handler));
+async fn insert_transaction(pool: &PgPool, merchant_reference: &str) {
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ($1, '300115673', 'COMPE36', 1500000, 'REGISTERED', 'PENDING', 0)
+        "#,
+    )
+    .bind(merchant_reference)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+fn callback_request(payload: &serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/bluecode/callback")
+        .header("Content-Type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_bluecode_callback_handler(pool: PgPool) {
+    insert_transaction(&pool, "TXN-12345678").await;
+
+    let app = Router::new()
+        .route("/bluecode/callback", post(callback_handler))
+        .with_state(pool.clone());
 
     let payload = json!({
         "result": "OK",
@@ -22,20 +49,46 @@ async fn test_bluecode_callback_handler() {
         }
     });
 
-    let request = Request::builder()
-        .method("POST")
-        .uri("/bluecode/callback")
-        .header("Content-Type", "application/json")
-        .body(Body::from(payload.to_string()))
+    let response = app.oneshot(callback_request(&payload)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (qr_status, stored, received_at): (String, Option<serde_json::Value>, Option<chrono::DateTime<chrono::Utc>>) =
+        sqlx::query_as(
+            "SELECT qr_status, bluecode_callback_payload, bluecode_callback_at FROM transactions WHERE merchant_reference = $1",
+        )
+        .bind("TXN-12345678")
+        .fetch_one(&pool)
+        .await
         .unwrap();
 
-    let response = app.clone().oneshot(request).await.unwrap();
-    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(qr_status, "APPROVED");
+    assert_eq!(stored, Some(payload));
+    assert!(received_at.is_some());
 }
 
-#[tokio::test]
-async fn test_requery_handler_stubbed() {
-    let app = Router::new().route("/dstv/requery/{merchant_tx_id}", get(requery_handler));
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_bluecode_callback_unknown_transaction(pool: PgPool) {
+    let app = Router::new()
+        .route("/bluecode/callback", post(callback_handler))
+        .with_state(pool);
+
+    let payload = json!({
+        "result": "OK",
+        "payment": {
+            "state": "APPROVED",
+            "merchant_tx_id": "TXN-does-not-exist"
+        }
+    });
+
+    let response = app.oneshot(callback_request(&payload)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_requery_handler_stubbed(pool: PgPool) {
+    let app = Router::new()
+        .route("/dstv/requery/{merchant_tx_id}", get(requery_handler))
+        .with_state(pool);
 
     let request = Request::builder()
         .method("GET")
@@ -55,3 +108,31 @@ async fn test_requery_handler_stubbed() {
 
     assert_eq!(status, StatusCode::OK);
 }
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_bluecode_callback_unknown_state_is_not_stored(pool: PgPool) {
+    insert_transaction(&pool, "TXN-chargeback").await;
+
+    let app = Router::new()
+        .route("/bluecode/callback", post(callback_handler))
+        .with_state(pool.clone());
+
+    let payload = json!({
+        "result": "OK",
+        "payment": { "state": "CHARGEBACK", "merchant_tx_id": "TXN-chargeback" }
+    });
+
+    let response = app.oneshot(callback_request(&payload)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (qr_status, stored): (String, Option<serde_json::Value>) = sqlx::query_as(
+        "SELECT qr_status, bluecode_callback_payload FROM transactions WHERE merchant_reference = $1",
+    )
+    .bind("TXN-chargeback")
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    assert_eq!(qr_status, "REGISTERED");
+    assert_eq!(stored, Some(payload));
+}
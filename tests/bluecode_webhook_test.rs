@@ -0,0 +1,147 @@
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use bills_backend::routes::bluecode::bluecode_routes;
+use bills_backend::services::bluecode_webhook::sign;
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt; // for `oneshot`
+
+const SECRET: &str = "test-webhook-secret";
+
+fn app(pool: PgPool) -> Router {
+    std::env::set_var("BLUECODE_WEBHOOK_SECRET", SECRET);
+    Router::new()
+        .nest("/bluecode", bluecode_routes(pool.clone()))
+        .with_state(pool)
+}
+
+async fn insert_transaction(pool: &PgPool, merchant_reference: &str) {
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ($1, '300115673', 'COMPE36', 1500000, 'REGISTERED', 'PENDING', 0)
+        "#,
+    )
+    .bind(merchant_reference)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+fn signed_request(body: &str, timestamp: i64, signature: &str, delivery: &str) -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/bluecode/callback")
+        .header("Content-Type", "application/json")
+        .header("X-Bluecode-Timestamp", timestamp.to_string())
+        .header("X-Bluecode-Signature", signature)
+        .header("X-Bluecode-Delivery", delivery)
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn approved_body(merchant_tx_id: &str) -> String {
+    json!({
+        "result": "OK",
+        "payment": { "state": "APPROVED", "merchant_tx_id": merchant_tx_id }
+    })
+    .to_string()
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_signed_callback_is_accepted_once(pool: PgPool) {
+    insert_transaction(&pool, "TXN-signed").await;
+    let body = approved_body("TXN-signed");
+    let now = chrono::Utc::now().timestamp();
+    let signature = sign(SECRET, now, body.as_bytes());
+
+    let response = app(pool.clone())
+        .oneshot(signed_request(&body, now, &signature, "dlv-1"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The same delivery replayed is refused, whatever delivery id it claims
+    let response = app(pool.clone())
+        .oneshot(signed_request(&body, now, &signature, "dlv-1"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app(pool.clone())
+        .oneshot(signed_request(&body, now, &signature, "dlv-1-replayed"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Concurrent copies are handled once
+    let body = approved_body("TXN-signed-twice");
+    insert_transaction(&pool, "TXN-signed-twice").await;
+    let signature = sign(SECRET, now, body.as_bytes());
+    let (first, second) = tokio::join!(
+        app(pool.clone()).oneshot(signed_request(&body, now, &signature, "dlv-4")),
+        app(pool.clone()).oneshot(signed_request(&body, now, &signature, "dlv-5")),
+    );
+    let mut statuses = [first.unwrap().status(), second.unwrap().status()];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::UNAUTHORIZED]);
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_unprocessed_callback_can_be_retried(pool: PgPool) {
+    // Unknown to us yet, so the callback is answered with an error
+    let body = approved_body("TXN-late");
+    let now = chrono::Utc::now().timestamp();
+    let signature = sign(SECRET, now, body.as_bytes());
+
+    let response = app(pool.clone())
+        .oneshot(signed_request(&body, now, &signature, "dlv-6"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    insert_transaction(&pool, "TXN-late").await;
+    let response = app(pool)
+        .oneshot(signed_request(&body, now, &signature, "dlv-6"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_forged_callback_is_rejected(pool: PgPool) {
+    insert_transaction(&pool, "TXN-forged").await;
+    let body = approved_body("TXN-forged");
+    let now = chrono::Utc::now().timestamp();
+    let signature = sign("not-the-secret", now, body.as_bytes());
+
+    let response = app(pool.clone())
+        .oneshot(signed_request(&body, now, &signature, "dlv-2"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let (qr_status,): (String,) =
+        sqlx::query_as("SELECT qr_status FROM transactions WHERE merchant_reference = $1")
+            .bind("TXN-forged")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(qr_status, "REGISTERED");
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_stale_callback_is_rejected(pool: PgPool) {
+    insert_transaction(&pool, "TXN-stale").await;
+    let body = approved_body("TXN-stale");
+    let an_hour_ago = chrono::Utc::now().timestamp() - 3600;
+    let signature = sign(SECRET, an_hour_ago, body.as_bytes());
+
+    let response = app(pool)
+        .oneshot(signed_request(&body, an_hour_ago, &signature, "dlv-3"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,30 @@
+//! Helpers shared by the integration tests.
+
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// A Quickteller mock whose passport issues `access_token`, with the
+/// Quickteller settings pointed at it. Tests mount the endpoints they call.
+pub async fn mock_quickteller(access_token: &str) -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/passport/oauth/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": access_token,
+            "expires_in": 86400
+        })))
+        .mount(&server)
+        .await;
+
+    std::env::set_var("QUICKTELLER_BASE_URL", server.uri());
+    std::env::set_var(
+        "QUICKTELLER_PASSPORT_URL",
+        format!("{}/passport/oauth/token", server.uri()),
+    );
+    std::env::set_var("QUICKTELLER_CLIENT_ID", "id");
+    std::env::set_var("QUICKTELLER_SECRET_KEY", "secret");
+    std::env::set_var("INTERSWITCH_TERMINAL_ID", "3TST0001");
+    server
+}
@@ -0,0 +1,154 @@
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::{Extension, Router};
+use bills_backend::routes::billers::biller_routes;
+use bills_backend::services::customer_validation::{
+    prune_customer_validations, require_validation, ValidationError,
+};
+use bills_backend::services::quickteller_auth::TokenManager;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower::ServiceExt;
+use wiremock::matchers::{body_string_contains, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+mod common;
+
+async fn mock_validation() -> MockServer {
+    let server = common::mock_quickteller("validation-token").await;
+
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/services/options"))
+        .and(query_param("serviceid", "479"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "PaymentItems": [{
+                "Id": "0",
+                "Name": "Ikeja Electric Prepaid",
+                "ConsumerIdField": "Meter Number",
+                "PaymentCode": "04792701",
+                "Amount": "0",
+                "IsAmountFixed": false
+            }],
+            "ResponseCode": "90000",
+            "ResponseCodeGrouping": "SUCCESSFUL"
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(
+            "/quicktellerservice/api/v5/Transactions/validatecustomers",
+        ))
+        .and(body_string_contains("45012345678"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "Customers": [{
+                "PaymentCode": "04792701",
+                "CustomerId": "45012345678",
+                "ResponseCode": "90000",
+                "FullName": "ADEOLA BALOGUN",
+                "Amount": "250000",
+                "AmountType": 0,
+                "AmountTypeDescription": "Outstanding balance",
+                "ResponseDescription": "Successful"
+            }],
+            "ResponseCode": "90000",
+            "ResponseCodeGrouping": "SUCCESSFUL"
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(
+            "/quicktellerservice/api/v5/Transactions/validatecustomers",
+        ))
+        .and(body_string_contains("00000000000"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "Customers": [{
+                "PaymentCode": "04792701",
+                "CustomerId": "00000000000",
+                "ResponseCode": "70022",
+                "ResponseDescription": "Invalid meter number"
+            }],
+            "ResponseCode": "90000",
+            "ResponseCodeGrouping": "SUCCESSFUL"
+        })))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+async fn validate(app: &Router, payment_code: &str, customer_id: &str) -> (StatusCode, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::post("/billers/479/validate")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    json!({ "payment_code": payment_code, "customer_id": customer_id }).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, body.to_vec())
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_customers_are_validated_and_remembered(pool: PgPool) {
+    let _server = mock_validation().await;
+
+    let app = Router::new()
+        .nest("/billers", biller_routes())
+        .layer(Extension(TokenManager::new()))
+        .with_state(pool.clone());
+
+    let (status, body) = validate(&app, "04792701", " 45012345678 ").await;
+    assert_eq!(status, StatusCode::OK);
+    let validation: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(validation["customer_name"], "ADEOLA BALOGUN");
+    assert_eq!(validation["balance"], 250_000);
+    assert_eq!(validation["amount_type"], "Outstanding balance");
+    assert_eq!(validation["required_fields"], json!(["Meter Number"]));
+    assert_eq!(validation["customer_id"], "45012345678");
+
+    // Rejected customers and unknown items are refused
+    let (status, body) = validate(&app, "04792701", "00000000000").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(String::from_utf8_lossy(&body).contains("Invalid meter number"));
+
+    let (status, _) = validate(&app, "99999999", "45012345678").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // The payment step finds the validation for the same customer only
+    let remembered = require_validation(&pool, 479, "04792701", "45012345678")
+        .await
+        .unwrap();
+    assert_eq!(remembered.id.to_string(), validation["id"]);
+
+    let err = require_validation(&pool, 479, "04792701", "00000000000")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ValidationError::NotValidated { .. }));
+
+    // Nor for another biller
+    let err = require_validation(&pool, 480, "04792701", "45012345678")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ValidationError::NotValidated { .. }));
+
+    sqlx::query("UPDATE quickteller_customer_validations SET expires_at = NOW()")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let err = require_validation(&pool, 479, "04792701", "45012345678")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ValidationError::NotValidated { .. }));
+
+    // Expired validations are pruned
+    assert_eq!(prune_customer_validations(&pool).await.unwrap(), 1);
+    assert_eq!(prune_customer_validations(&pool).await.unwrap(), 0);
+}
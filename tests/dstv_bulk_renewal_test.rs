@@ -0,0 +1,350 @@
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use bills_backend::models::bulk_renewals::BulkRenewalReport;
+use bills_backend::models::money::Currency;
+use bills_backend::routes::dstv::dstv_routes;
+use bills_backend::services::bulk_renewals::{parse_csv, DUPLICATE_ROW, INVALID_ROW};
+use bills_backend::services::catalog::invalidate_cache;
+use sqlx::PgPool;
+use std::time::Duration;
+use tower::ServiceExt;
+use wiremock::matchers::{body_string_contains, method, path, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn vas_response(code: &str, message: &str) -> String {
+    format!(
+        r#"<PayUVasResponse>
+            <ResultCode>{code}</ResultCode>
+            <ResultMessage>{message}</ResultMessage>
+            <ReceiptNumber>RCPT-1</ReceiptNumber>
+            <CustomFields>
+                <Customfield Key="SURNAME" Value="HOTEL"/>
+            </CustomFields>
+        </PayUVasResponse>"#
+    )
+}
+
+async fn mock_multichoice() -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/lookup"))
+        .and(body_string_contains("BADCARD"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(vas_response("12", "Invalid smartcard"), "application/xml"),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/lookup"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(vas_response("00", "Success"), "application/xml"),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .and(body_string_contains("PENDINGCARD"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(vas_response("01", "In progress"), "application/xml"),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(vas_response("00", "Success"), "application/xml"),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex("^/transactions/single/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+async fn send(pool: &PgPool, method: &str, uri: &str, body: &str) -> (StatusCode, Vec<u8>) {
+    let response = Router::new()
+        .nest("/dstv", dstv_routes(pool.clone()))
+        .with_state(pool.clone())
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("x-admin-key", "test-admin-key")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    (status, bytes.to_vec())
+}
+
+async fn wait_for_report(
+    pool: &PgPool,
+    id: uuid::Uuid,
+    done: impl Fn(&BulkRenewalReport) -> bool,
+) -> BulkRenewalReport {
+    for _ in 0..50 {
+        let (_, body) = send(pool, "GET", &format!("/dstv/admin/bulk-renewals/{id}"), "").await;
+        let report: BulkRenewalReport = serde_json::from_slice(&body).unwrap();
+        if done(&report) {
+            return report;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("bulk renewal {} did not get there", id);
+}
+
+#[test]
+fn test_parse_csv_reads_columns_by_name() {
+    let rows = parse_csv(
+        b"Amount,Customer_ID,basket_id\n15000,300115673,COMPE36\n\nabc,4131,COMPE36\n,,\n15000,41\xff31,COMPE36\n15000,4132,COMPE36\n",
+        Currency::Ngn,
+    )
+    .unwrap();
+
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[0].customer_id, "300115673");
+    assert_eq!(rows[0].amount.unwrap().minor(), 1500000);
+    assert!(rows[0].error.is_none());
+    assert_eq!(rows[1].error.as_ref().unwrap().code, INVALID_ROW);
+
+    // An unreadable row is reported, and the rows after it still read
+    assert_eq!(rows[2].line_number, 6);
+    assert_eq!(rows[2].error.as_ref().unwrap().code, INVALID_ROW);
+    assert_eq!(rows[3].customer_id, "4132");
+    assert!(rows[3].error.is_none());
+
+    assert!(parse_csv(b"customer_id,amount\n1,2\n", Currency::Ngn).is_err());
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_bulk_renewal_preview_approve_and_results(pool: PgPool) {
+    let server = mock_multichoice().await;
+    std::env::set_var("DSTV_BASE_URL", server.uri());
+    std::env::set_var("ADMIN_API_KEY", "test-admin-key");
+    std::env::set_var("DSTV_BULK_REQUERY_SECS", "1");
+
+    sqlx::query(
+        "INSERT INTO dstv_products (basket_id, name, kind, price, validity_days)
+         VALUES ('COMPE36', 'Compact', 'BOUQUET', 1500000, 30)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    invalidate_cache();
+
+    let csv = "customer_id,basket_id,amount\n\
+               300115673,COMPE36,15000\n\
+               BADCARD,COMPE36,15000\n\
+               PENDINGCARD,COMPE36,\"15,000.00\"\n\
+               300115673,COMPE36,15000\n\
+               4131953321,COMPE36,100\n";
+    let (status, body) = send(
+        &pool,
+        "POST",
+        "/dstv/admin/bulk-renewals?name=Lagoon%20Hotel",
+        csv,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let preview: BulkRenewalReport = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(preview.batch.status, "PREVIEWED");
+    assert_eq!(preview.batch.total_rows, 5);
+    assert_eq!(preview.batch.valid_rows, 2);
+    let codes: Vec<_> = preview
+        .rows
+        .iter()
+        .map(|r| (r.status.as_str(), r.error_code.as_deref()))
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            ("VALID", None),
+            ("INVALID", Some("INVALID_SMARTCARD")),
+            ("VALID", None),
+            ("INVALID", Some(DUPLICATE_ROW)),
+            ("INVALID", Some("AMOUNT_MISMATCH")),
+        ]
+    );
+    assert_eq!(preview.rows[0].account_name.as_deref(), Some("HOTEL"));
+    assert_eq!(preview.rows[0].line_number, 2);
+
+    // Nothing reaches MultiChoice before approval
+    let payments = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/vendor/singlepayment")
+        .count();
+    assert_eq!(payments, 0);
+
+    let id = preview.batch.id;
+    let (status, _) = send(
+        &pool,
+        "POST",
+        &format!("/dstv/admin/bulk-renewals/{id}/approve"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let (status, _) = send(
+        &pool,
+        "POST",
+        &format!("/dstv/admin/bulk-renewals/{id}/approve"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // A pending row keeps the batch running while it is requeried
+    let report = wait_for_report(&pool, id, |report| {
+        report
+            .rows
+            .iter()
+            .all(|r| r.status != "VALID" && r.status != "PROCESSING")
+    })
+    .await;
+    assert_eq!(report.batch.status, "RUNNING");
+    let statuses: Vec<_> = report.rows.iter().map(|r| r.status.as_str()).collect();
+    assert_eq!(
+        statuses,
+        ["CONFIRMED", "INVALID", "PENDING", "INVALID", "INVALID"]
+    );
+    assert_eq!(report.rows[0].receipt_number.as_deref(), Some("RCPT-1"));
+    assert_eq!(report.rows[2].error_code.as_deref(), Some("PENDING"));
+
+    Mock::given(method("GET"))
+        .and(path_regex("^/transactions/single/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"[{"merchantreference":"VAS-1","smartcard":"PENDINGCARD","status":1,"basketid":"COMPE36"}]"#,
+        ))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    let report = wait_for_report(&pool, id, |report| report.batch.status == "COMPLETED").await;
+    let statuses: Vec<_> = report.rows.iter().map(|r| r.status.as_str()).collect();
+    assert_eq!(
+        statuses,
+        ["CONFIRMED", "INVALID", "CONFIRMED", "INVALID", "INVALID"]
+    );
+    let payments = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.url.path() == "/vendor/singlepayment")
+        .count();
+    assert_eq!(payments, 2);
+
+    let (status, body) = send(
+        &pool,
+        "GET",
+        &format!("/dstv/admin/bulk-renewals/{id}/results.csv"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let csv = String::from_utf8(body).unwrap();
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().starts_with("line_number,customer_id"));
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("2,300115673,COMPE36,15000.00,HOTEL,CONFIRMED"));
+    assert_eq!(csv.lines().count(), 6);
+
+    let (status, _) = send(
+        &pool,
+        "GET",
+        &format!("/dstv/admin/bulk-renewals/{}", uuid::Uuid::new_v4()),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_bulk_renewal_unknown_outcome_stays_pending(pool: PgPool) {
+    let server = mock_multichoice().await;
+    std::env::set_var("DSTV_BASE_URL", server.uri());
+    std::env::set_var("ADMIN_API_KEY", "test-admin-key");
+    std::env::set_var("DSTV_BULK_REQUERY_SECS", "1");
+
+    // The payment times out at the gateway and its requery fails too
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .and(body_string_contains("TIMEOUTCARD"))
+        .respond_with(ResponseTemplate::new(504))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/transactions/single/.*"))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    sqlx::query(
+        "INSERT INTO dstv_products (basket_id, name, kind, price, validity_days)
+         VALUES ('COMPE36', 'Compact', 'BOUQUET', 1500000, 30)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    invalidate_cache();
+
+    let (status, body) = send(
+        &pool,
+        "POST",
+        "/dstv/admin/bulk-renewals?name=Lagoon%20Hotel",
+        "customer_id,basket_id,amount\nTIMEOUTCARD,COMPE36,15000\n",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", String::from_utf8_lossy(&body));
+    let id = serde_json::from_slice::<BulkRenewalReport>(&body)
+        .unwrap()
+        .batch
+        .id;
+
+    let (status, _) = send(
+        &pool,
+        "POST",
+        &format!("/dstv/admin/bulk-renewals/{id}/approve"),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let report = wait_for_report(&pool, id, |report| report.rows[0].status == "PENDING").await;
+    assert_eq!(report.batch.status, "RUNNING");
+
+    // Later requeries that fail leave the row for the next one
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let (_, body) = send(&pool, "GET", &format!("/dstv/admin/bulk-renewals/{id}"), "").await;
+    let report: BulkRenewalReport = serde_json::from_slice(&body).unwrap();
+    assert_eq!(report.batch.status, "RUNNING");
+    assert_eq!(report.rows[0].status, "PENDING");
+}
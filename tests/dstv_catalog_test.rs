@@ -0,0 +1,174 @@
+use axum::body::to_bytes;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use bills_backend::models::checkout::StartCheckoutRequest;
+use bills_backend::routes::dstv::dstv_routes;
+use bills_backend::services::catalog::{check_basket_price, invalidate_cache, CatalogError};
+use bills_backend::services::checkout::{start_checkout, CheckoutError};
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt; // for `oneshot`
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn catalog(with_french: bool) -> serde_json::Value {
+    let mut products = vec![
+        json!({ "basket_id": "COMPE36", "name": "Compact", "kind": "BOUQUET", "price": 1500000, "validity_days": 30 }),
+        json!({ "basket_id": "PRWE36", "name": "Premium", "kind": "BOUQUET", "price": 3700000, "validity_days": 30 }),
+        json!({ "basket_id": "HDPVRE36", "name": "Extra View", "kind": "ADDON", "price": 600000, "validity_days": 30 }),
+        json!({ "vas_product": "gotv-ng", "basket_id": "GOTVMAX", "name": "GOtv Max", "kind": "BOUQUET", "price": 850000, "validity_days": 30 }),
+    ];
+    if with_french {
+        products.push(json!({ "basket_id": "FRN15E36", "name": "French Touch", "kind": "ADDON", "price": 720000, "validity_days": 30 }));
+    }
+    json!(products)
+}
+
+fn request(method: &str, uri: &str, body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("X-Admin-Key", "test-admin-key")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+async fn json_body(response: axum::response::Response) -> serde_json::Value {
+    let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_catalog_refresh_listing_and_price_check(pool: PgPool) {
+    let mock_server = MockServer::start().await;
+
+    // A mispriced basket must never reach MultiChoice
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&mock_server)
+        .await;
+
+    let catalog_file = std::env::temp_dir().join("dstv_catalog_test.json");
+    std::fs::write(&catalog_file, catalog(true).to_string()).unwrap();
+
+    std::env::remove_var("DSTV_CATALOG_URL");
+    std::env::set_var("DSTV_CATALOG_FILE", &catalog_file);
+    std::env::set_var("DSTV_BASE_URL", mock_server.uri());
+    std::env::set_var("ADMIN_API_KEY", "test-admin-key");
+
+    // Nothing is sold at an unchecked price before the catalog is loaded
+    invalidate_cache();
+    assert!(matches!(
+        check_basket_price(&pool, "dstv-ng", "COMPE36", 1500000).await,
+        Err(CatalogError::Empty)
+    ));
+
+    let app = Router::new()
+        .nest("/dstv", dstv_routes(pool.clone()))
+        .with_state(pool.clone());
+
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/dstv/admin/products/refresh", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["refreshed"], 5);
+
+    let response = app
+        .clone()
+        .oneshot(request("GET", "/dstv/products", json!({})))
+        .await
+        .unwrap();
+    let products = json_body(response).await;
+    assert_eq!(products.as_array().unwrap().len(), 4);
+    assert_eq!(products[0]["kind"], "BOUQUET");
+
+    let response = app
+        .clone()
+        .oneshot(request("GET", "/dstv/products?kind=ADDON", json!({})))
+        .await
+        .unwrap();
+    let addons = json_body(response).await;
+    assert_eq!(addons.as_array().unwrap().len(), 2);
+    assert_eq!(addons[0]["basket_id"], "HDPVRE36");
+
+    let response = app
+        .clone()
+        .oneshot(request("GET", "/dstv/products?product=gotv-ng", json!({})))
+        .await
+        .unwrap();
+    let gotv = json_body(response).await;
+    assert_eq!(gotv.as_array().unwrap().len(), 1);
+    assert_eq!(gotv[0]["basket_id"], "GOTVMAX");
+
+    // Wrong price for the basket
+    let response = app
+        .clone()
+        .oneshot(request(
+            "POST",
+            "/dstv/confirm-payment",
+            json!({
+                "customer_id": "300115673",
+                "basket_id": "COMPE36",
+                "amount": 100,
+                "merchant_reference": "TXN-cheap"
+            }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json_body(response).await["success"], false);
+
+    let err = start_checkout(
+        &pool,
+        StartCheckoutRequest {
+            customer_id: "300115673".into(),
+            basket_id: "NOPE".into(),
+            amount: 1500000,
+            product: None,
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        CheckoutError::Catalog(CatalogError::UnknownBasket(_))
+    ));
+
+    // Products dropped from the source are no longer offered
+    std::fs::write(&catalog_file, catalog(false).to_string()).unwrap();
+    let response = app
+        .clone()
+        .oneshot(request("POST", "/dstv/admin/products/refresh", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(json_body(response).await["refreshed"], 4);
+
+    let response = app
+        .clone()
+        .oneshot(request("GET", "/dstv/products?kind=ADDON", json!({})))
+        .await
+        .unwrap();
+    assert_eq!(json_body(response).await.as_array().unwrap().len(), 1);
+
+    // The catalog refresh is admin only
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/dstv/admin/products/refresh")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
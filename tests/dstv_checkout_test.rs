@@ -0,0 +1,288 @@
+use bills_backend::models::bluecode::BluecodePaymentState;
+use bills_backend::models::checkout::StartCheckoutRequest;
+use bills_backend::services::catalog::invalidate_cache;
+use bills_backend::services::checkout::{
+    advance_checkout, load_checkout, start_checkout, CheckoutError,
+};
+use bills_backend::services::transactions::record_bluecode_callback;
+use serde_json::json;
+use sqlx::PgPool;
+use wiremock::matchers::{body_string_contains, method, path, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const LOOKUP_RESPONSE: &str = r#"
+    <PayUVasResponse>
+        <ResultCode>00</ResultCode>
+        <ResultMessage>Success</ResultMessage>
+        <CustomFields>
+            <Customfield Key="SURNAME" Value="AKINTAYO"/>
+            <Customfield Key="DSTV_CUSTOMER_NUMBER" Value="300115673"/>
+        </CustomFields>
+    </PayUVasResponse>
+"#;
+
+const PENDING_RESPONSE: &str = r#"
+    <PayUVasResponse>
+        <ResultCode>01</ResultCode>
+        <ResultMessage>Transaction in progress</ResultMessage>
+    </PayUVasResponse>
+"#;
+
+const CONFIRM_RESPONSE: &str = r#"
+    <PayUVasResponse>
+        <ResultCode>00</ResultCode>
+        <ResultMessage>Success</ResultMessage>
+    </PayUVasResponse>
+"#;
+
+async fn seed_catalog(pool: &PgPool) {
+    sqlx::query(
+        "INSERT INTO dstv_products (vas_product, basket_id, name, kind, price, validity_days)
+         VALUES ('dstv-ng', 'COMPE36', 'Compact', 'BOUQUET', 1500000, 30),
+                ('dstv-ke', 'COMPKE', 'Compact', 'BOUQUET', 150000, 30)",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    invalidate_cache();
+}
+
+async fn mock_upstreams() -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/lookup"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(LOOKUP_RESPONSE, "application/xml"))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v4/register"))
+        .respond_with(|req: &wiremock::Request| {
+            let body: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({
+                "result": "OK",
+                "payment": {
+                    "merchant_tx_id": body["merchant_tx_id"],
+                    "checkin_code": "bluecode://checkin/12345",
+                    "state": "REGISTERED"
+                }
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    // MultiChoice rejects every confirmation for the FAILCARD smartcard
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .and(body_string_contains("FAILCARD"))
+        .respond_with(ResponseTemplate::new(500))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    // ... and leaves every confirmation for PENDCARD in progress
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .and(body_string_contains("PENDCARD"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(PENDING_RESPONSE, "application/xml"))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(CONFIRM_RESPONSE, "application/xml"))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex("^/transactions/single/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_checkout_confirms_after_payment_and_flags_failures(pool: PgPool) {
+    let server = mock_upstreams().await;
+    std::env::set_var("DSTV_BASE_URL", server.uri());
+    std::env::set_var("BLUECODE_API_BASE_URL", server.uri());
+    std::env::set_var("BLUECODE_MERCHANT_ACCESS", "access");
+    std::env::set_var("BLUECODE_MERCHANT_SECRET", "secret");
+    std::env::set_var("DSTV_CHECKOUT_MAX_ATTEMPTS", "1");
+    seed_catalog(&pool).await;
+
+    // Happy path: registered, paid, confirmed
+    let checkout = start_checkout(
+        &pool,
+        StartCheckoutRequest {
+            customer_id: "300115673".into(),
+            basket_id: "COMPE36".into(),
+            amount: 1500000,
+            product: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(checkout.step, "PAYMENT_REGISTERED");
+    assert_eq!(
+        checkout.checkin_code.as_deref(),
+        Some("bluecode://checkin/12345")
+    );
+    assert_eq!(checkout.account_name.as_deref(), Some("AKINTAYO"));
+
+    record_bluecode_callback(
+        &pool,
+        &checkout.merchant_tx_id,
+        &BluecodePaymentState::Approved,
+        &json!({}),
+    )
+    .await
+    .unwrap();
+    advance_checkout(&pool, checkout.id).await.unwrap();
+
+    let checkout = load_checkout(&pool, checkout.id).await.unwrap();
+    assert_eq!(checkout.step, "CONFIRMED");
+
+    let (confirm_status,): (String,) =
+        sqlx::query_as("SELECT confirm_status FROM transactions WHERE id = $1")
+            .bind(checkout.transaction_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(confirm_status, "CONFIRMED");
+
+    // Paid but MultiChoice keeps failing: flagged for refund
+    let failing = start_checkout(
+        &pool,
+        StartCheckoutRequest {
+            customer_id: "FAILCARD".into(),
+            basket_id: "COMPE36".into(),
+            amount: 1500000,
+            product: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    record_bluecode_callback(
+        &pool,
+        &failing.merchant_tx_id,
+        &BluecodePaymentState::Approved,
+        &json!({}),
+    )
+    .await
+    .unwrap();
+    advance_checkout(&pool, failing.id).await.unwrap();
+
+    let failing = load_checkout(&pool, failing.id).await.unwrap();
+    assert_eq!(failing.step, "REFUND_REQUIRED");
+    assert!(failing.last_error.is_some());
+
+    // MultiChoice is still processing: requeried until it settles, never
+    // flagged for refund in the meantime
+    let pending = start_checkout(
+        &pool,
+        StartCheckoutRequest {
+            customer_id: "PENDCARD".into(),
+            basket_id: "COMPE36".into(),
+            amount: 1500000,
+            product: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    record_bluecode_callback(
+        &pool,
+        &pending.merchant_tx_id,
+        &BluecodePaymentState::Approved,
+        &json!({}),
+    )
+    .await
+    .unwrap();
+    advance_checkout(&pool, pending.id).await.unwrap();
+    assert_eq!(
+        load_checkout(&pool, pending.id).await.unwrap().step,
+        "CONFIRMATION_PENDING"
+    );
+
+    // The requery does not know the payment yet
+    make_due(&pool, pending.id).await;
+    advance_checkout(&pool, pending.id).await.unwrap();
+    let still_pending = load_checkout(&pool, pending.id).await.unwrap();
+    assert_eq!(still_pending.step, "CONFIRMATION_PENDING");
+    assert!(still_pending.next_attempt_at.is_some());
+
+    Mock::given(method("GET"))
+        .and(path_regex("^/transactions/single/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+            "merchantreference": "VAS-pending",
+            "smartcard": "PENDCARD",
+            "status": 1,
+            "basketid": "COMPE36"
+        }])))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    make_due(&pool, pending.id).await;
+    advance_checkout(&pool, pending.id).await.unwrap();
+    assert_eq!(
+        load_checkout(&pool, pending.id).await.unwrap().step,
+        "CONFIRMED"
+    );
+
+    let single_payments = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| {
+            r.url.path() == "/vendor/singlepayment"
+                && String::from_utf8_lossy(&r.body).contains("PENDCARD")
+        })
+        .count();
+    assert_eq!(single_payments, 1);
+
+    // Bluecode registers NGN, so a KES basket cannot be paid through it
+    std::env::set_var(
+        "VAS_PRODUCTS",
+        json!([{
+            "id": "dstv-ke",
+            "name": "DSTV Kenya",
+            "vas_id": "MCA_ACCOUNT_SQ_KE",
+            "country_code": "KE",
+            "currency": "KES",
+            "merchant_id": "test",
+            "username": "ke",
+            "password": "secret"
+        }])
+        .to_string(),
+    );
+    let err = start_checkout(
+        &pool,
+        StartCheckoutRequest {
+            customer_id: "300115673".into(),
+            basket_id: "COMPKE".into(),
+            amount: 150000,
+            product: Some("dstv-ke".into()),
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, CheckoutError::Money(_)));
+    std::env::remove_var("VAS_PRODUCTS");
+}
+
+async fn make_due(pool: &PgPool, id: uuid::Uuid) {
+    sqlx::query("UPDATE dstv_checkouts SET next_attempt_at = NOW() WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await
+        .unwrap();
+}
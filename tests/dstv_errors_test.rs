@@ -0,0 +1,121 @@
+use axum::http::StatusCode;
+use axum::Json;
+use bills_backend::models::dstv::{DstvConfirmation, DstvErrorCode};
+use bills_backend::models::payu_vas::{PayUVasResponse, VasRequeryItem};
+use bills_backend::services::catalog::CatalogError;
+use bills_backend::services::dstv::{confirmation_reply, lookup_reply, DstvError};
+use bills_backend::services::payu_vas::VasError;
+use serde_json::json;
+
+fn rejected(code: &str, message: &str) -> DstvError {
+    DstvError::Vas(VasError::Rejected {
+        code: code.into(),
+        message: message.into(),
+    })
+}
+
+#[test]
+fn test_result_codes_map_to_error_codes() {
+    let cases = [
+        ("00", "Success", None),
+        ("01", "In progress", Some(DstvErrorCode::Pending)),
+        (
+            "12",
+            "Invalid smartcard",
+            Some(DstvErrorCode::InvalidSmartcard),
+        ),
+        ("62", "", Some(DstvErrorCode::AccountSuspended)),
+        ("94", "", Some(DstvErrorCode::DuplicateReference)),
+        ("96", "", Some(DstvErrorCode::UpstreamUnavailable)),
+        // Other codes fall back to the message
+        (
+            "05",
+            "Account is DISCONNECTED",
+            Some(DstvErrorCode::AccountSuspended),
+        ),
+        (
+            "05",
+            "Duplicate MerchantReference",
+            Some(DstvErrorCode::DuplicateReference),
+        ),
+        (
+            "05",
+            "Amount does not match basket",
+            Some(DstvErrorCode::AmountMismatch),
+        ),
+        ("05", "Something odd", Some(DstvErrorCode::Unknown)),
+    ];
+    for (code, message, expected) in cases {
+        assert_eq!(
+            DstvErrorCode::from_result(code, message),
+            expected,
+            "{code} {message}"
+        );
+    }
+
+    assert_eq!(DstvErrorCode::from_requery_status(1), None);
+    assert_eq!(
+        DstvErrorCode::from_requery_status(-1),
+        Some(DstvErrorCode::Pending)
+    );
+    assert_eq!(
+        DstvErrorCode::from_requery_status(0),
+        Some(DstvErrorCode::Unknown)
+    );
+
+    assert_eq!(
+        serde_json::to_value(DstvErrorCode::InvalidSmartcard).unwrap(),
+        json!(DstvErrorCode::InvalidSmartcard.as_str())
+    );
+}
+
+#[test]
+fn test_replies_carry_machine_code_and_customer_message() {
+    let (status, Json(body)) = lookup_reply(Err(rejected("12", "Invalid smartcard")));
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!body.success);
+    assert_eq!(body.error_code, Some(DstvErrorCode::InvalidSmartcard));
+    assert_eq!(
+        body.message,
+        DstvErrorCode::InvalidSmartcard.customer_message()
+    );
+
+    let (status, Json(body)) = lookup_reply(Err(DstvError::Vas(VasError::EmptyResponse)));
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body.error_code, Some(DstvErrorCode::UpstreamUnavailable));
+
+    let (status, Json(body)) =
+        confirmation_reply(Err(DstvError::Catalog(CatalogError::PriceMismatch {
+            basket_id: "COMPE36".into(),
+            expected: 1500000,
+            actual: 100,
+        })));
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body.error_code, Some(DstvErrorCode::AmountMismatch));
+
+    let duplicate = DstvConfirmation::from(PayUVasResponse {
+        result_code: "94".into(),
+        result_message: "Duplicate transaction".into(),
+        merchant_reference: None,
+        payu_reference: None,
+        receipt_number: None,
+        custom_fields: None,
+    });
+    let (status, Json(body)) = confirmation_reply(Ok(duplicate));
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body.error_code, Some(DstvErrorCode::DuplicateReference));
+    assert_eq!(
+        body.message,
+        DstvErrorCode::DuplicateReference.customer_message()
+    );
+
+    let pending = DstvConfirmation::from(VasRequeryItem {
+        merchantreference: "VAS-1".into(),
+        smartcard: "300115673".into(),
+        status: -1,
+        basketid: "COMPE36".into(),
+    });
+    let (status, Json(body)) = confirmation_reply(Ok(pending));
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(body.error_code, Some(DstvErrorCode::Pending));
+}
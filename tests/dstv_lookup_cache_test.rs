@@ -0,0 +1,124 @@
+use bills_backend::models::dstv::{DstvErrorCode, DstvLookupRequest};
+use bills_backend::services::dstv::lookup_dstv_account;
+use bills_backend::services::lookup_cache::lookup_cache_stats;
+use serde_json::json;
+use sqlx::PgPool;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn vas_response(code: &str, surname: &str) -> String {
+    format!(
+        r#"<PayUVasResponse>
+            <ResultCode>{code}</ResultCode>
+            <ResultMessage>Result {code}</ResultMessage>
+            <CustomFields>
+                <Customfield Key="SURNAME" Value="{surname}"/>
+            </CustomFields>
+        </PayUVasResponse>"#
+    )
+}
+
+async fn mock_lookup(server: &MockServer, customer_id: &str, code: &str) {
+    Mock::given(method("POST"))
+        .and(path("/vendor/lookup"))
+        .and(body_string_contains(customer_id))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(vas_response(code, "ADEBAYO"), "application/xml"),
+        )
+        .mount(server)
+        .await;
+}
+
+async fn lookups_sent(server: &MockServer, customer_id: &str) -> usize {
+    server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| String::from_utf8_lossy(&r.body).contains(customer_id))
+        .count()
+}
+
+fn request(customer_id: &str, refresh: bool) -> DstvLookupRequest {
+    DstvLookupRequest {
+        customer_id: customer_id.to_string(),
+        product: None,
+        refresh,
+    }
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_lookups_are_cached_per_smartcard(pool: PgPool) {
+    let server = MockServer::start().await;
+    mock_lookup(&server, "300115673", "00").await;
+    mock_lookup(&server, "BADCARD", "12").await;
+    mock_lookup(&server, "OUTAGECARD", "96").await;
+    std::env::set_var("DSTV_BASE_URL", server.uri());
+    std::env::set_var("DSTV_LOOKUP_CACHE_STORE", "postgres");
+
+    let before = lookup_cache_stats();
+
+    // Repeated lookups are answered from the cache until a refresh is asked for
+    for _ in 0..3 {
+        let lookup = lookup_dstv_account(&pool, request("300115673", false))
+            .await
+            .unwrap();
+        assert_eq!(lookup.account_name.as_deref(), Some("ADEBAYO"));
+    }
+    assert_eq!(lookups_sent(&server, "300115673").await, 1);
+
+    lookup_dstv_account(&pool, request("300115673", true))
+        .await
+        .unwrap();
+    assert_eq!(lookups_sent(&server, "300115673").await, 2);
+
+    // Rejected smartcards are cached briefly, outages are not
+    for _ in 0..2 {
+        let err = lookup_dstv_account(&pool, request("BADCARD", false))
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_code(), Some(DstvErrorCode::InvalidSmartcard));
+
+        let err = lookup_dstv_account(&pool, request("OUTAGECARD", false))
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_code(), Some(DstvErrorCode::UpstreamUnavailable));
+    }
+    assert_eq!(lookups_sent(&server, "BADCARD").await, 1);
+    assert_eq!(lookups_sent(&server, "OUTAGECARD").await, 2);
+
+    let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM dstv_lookup_cache")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stored, 2);
+
+    // Lookups stored by another instance are served from Postgres
+    sqlx::query(
+        "INSERT INTO dstv_lookup_cache (vas_product, customer_id, outcome, expires_at)
+         VALUES ('dstv-ng', 'SHAREDCARD', $1, NOW() + INTERVAL '5 minutes')",
+    )
+    .bind(json!({
+        "kind": "FOUND",
+        "account_name": "SHARED",
+        "customer_id": "SHAREDCARD",
+        "message": "Success",
+        "success": true,
+        "custom_fields": null
+    }))
+    .execute(&pool)
+    .await
+    .unwrap();
+    let shared = lookup_dstv_account(&pool, request("SHAREDCARD", false))
+        .await
+        .unwrap();
+    assert_eq!(shared.account_name.as_deref(), Some("SHARED"));
+    assert_eq!(lookups_sent(&server, "SHAREDCARD").await, 0);
+
+    let after = lookup_cache_stats();
+    assert_eq!(after.hits - before.hits, 3);
+    assert_eq!(after.store_hits - before.store_hits, 1);
+    assert_eq!(after.misses - before.misses, 4);
+    assert_eq!(after.refreshes - before.refreshes, 1);
+}
@@ -1,12 +1,13 @@
 use bills_backend::models::dstv::DstvLookupRequest;
 use bills_backend::services::dstv::lookup_dstv_account;
+use sqlx::PgPool;
 use std::sync::{Arc, Mutex};
 use wiremock::matchers::{method, path};
 use wiremock::Request as WiremockRequest;
-use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
-#[tokio::test]
-async fn test_successful_dstv_lookup() {
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_successful_dstv_lookup(pool: PgPool) {
     let mock_server = MockServer::start().await;
 
     let fake_response = r#"
@@ -46,9 +47,11 @@ async fn test_successful_dstv_lookup() {
 
     let request = DstvLookupRequest {
         customer_id: "300115673".to_string(),
+        product: None,
+        refresh: false,
     };
 
-    let result = lookup_dstv_account(request).await;
+    let result = lookup_dstv_account(&pool, request).await;
 
     match result {
         Ok(r) => {
@@ -56,6 +59,21 @@ async fn test_successful_dstv_lookup() {
             assert_eq!(r.customer_id.unwrap(), "300115673");
             assert!(r.success);
             assert_eq!(r.message, "Success");
+
+            // Each lookup goes out under its own recorded reference
+            let (reference,): (String,) = sqlx::query_as(
+                "SELECT reference FROM merchant_references WHERE purpose = 'LOOKUP'",
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+            assert!(reference.starts_with("VAS-"));
+            assert!(seen_body
+                .lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .contains(&reference));
         }
         Err(e) => {
             println!("❌ Test failed with error: {:?}", e);
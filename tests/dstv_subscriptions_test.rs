@@ -0,0 +1,487 @@
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use bills_backend::models::subscriptions::{
+    next_monthly_run, parse_due_date, Schedule, SubscriptionDetails,
+};
+use bills_backend::routes::dstv::dstv_routes;
+use bills_backend::services::catalog::invalidate_cache;
+use bills_backend::services::subscriptions::{run_due_subscriptions, SubscriptionSettings};
+use bills_backend::utils::cors::cors_layer;
+use chrono::{NaiveDate, TimeZone, Utc};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower::ServiceExt;
+use uuid::Uuid;
+use wiremock::matchers::{body_string_contains, method, path, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn vas_response(code: &str) -> String {
+    format!(
+        r#"<PayUVasResponse>
+            <ResultCode>{code}</ResultCode>
+            <ResultMessage>Result {code}</ResultMessage>
+            <ReceiptNumber>RCPT-7</ReceiptNumber>
+            <CustomFields>
+                <Customfield Key="SURNAME" Value="OKAFOR"/>
+                <Customfield Key="DUE_DATE" Value="2030-01-10"/>
+            </CustomFields>
+        </PayUVasResponse>"#
+    )
+}
+
+async fn mock_upstreams() -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/lookup"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(vas_response("00"), "application/xml"),
+        )
+        .mount(&server)
+        .await;
+
+    // MultiChoice is down for FLAKYCARD
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .and(body_string_contains("FLAKYCARD"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(vas_response("96"), "application/xml"),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    // MultiChoice has not settled PENDCARD yet
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .and(body_string_contains("PENDCARD"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(vas_response("01"), "application/xml"),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw(vas_response("00"), "application/xml"),
+        )
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v4/register"))
+        .respond_with(|req: &wiremock::Request| {
+            let body: Value = serde_json::from_slice(&req.body).unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({
+                "result": "OK",
+                "payment": {
+                    "merchant_tx_id": body["merchant_tx_id"],
+                    "checkin_code": "bluecode://checkin/777",
+                    "state": "REGISTERED"
+                }
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    server
+}
+
+const ADMIN: (&str, &str) = ("X-Admin-Key", "test-admin-key");
+
+async fn send(
+    pool: &PgPool,
+    method: &str,
+    uri: &str,
+    body: Option<Value>,
+    headers: &[(&str, &str)],
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let request = request
+        .body(Body::from(body.map(|b| b.to_string()).unwrap_or_default()))
+        .unwrap();
+    let response = Router::new()
+        .nest("/dstv", dstv_routes(pool.clone()))
+        .with_state(pool.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+fn create_body(customer_id: &str, schedule: Value, funding: &str) -> Value {
+    json!({
+        "customer_id": customer_id,
+        "basket_id": "COMPE36",
+        "amount": 1500000,
+        "schedule": schedule,
+        "funding_source": funding
+    })
+}
+
+/// Returns the subscription's id and management token.
+async fn create(
+    pool: &PgPool,
+    customer_id: &str,
+    schedule: Value,
+    funding: &str,
+) -> (Uuid, String) {
+    let (status, body) = send(
+        pool,
+        "POST",
+        "/dstv/subscriptions",
+        Some(create_body(customer_id, schedule, funding)),
+        &[ADMIN],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let details: SubscriptionDetails = serde_json::from_value(body).unwrap();
+    (details.subscription.id, details.manage_token.unwrap())
+}
+
+async fn seed_catalog(pool: &PgPool) {
+    sqlx::query(
+        "INSERT INTO dstv_products (basket_id, name, kind, price, validity_days)
+         VALUES ('COMPE36', 'Compact', 'BOUQUET', 1500000, 30)",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    invalidate_cache();
+}
+
+async fn make_due(pool: &PgPool, id: Uuid) {
+    sqlx::query(
+        "UPDATE dstv_subscriptions
+         SET cycle_scheduled_for = NOW() - INTERVAL '1 minute', next_run_at = NOW() - INTERVAL '1 minute'
+         WHERE id = $1",
+    )
+    .bind(id)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn details(pool: &PgPool, id: Uuid) -> SubscriptionDetails {
+    let (status, body) = send(
+        pool,
+        "GET",
+        &format!("/dstv/subscriptions/{id}"),
+        None,
+        &[ADMIN],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_value(body).unwrap()
+}
+
+#[test]
+fn test_next_monthly_run_clamps_to_month_end() {
+    let at = |y, m, d, h| Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap();
+
+    assert_eq!(
+        next_monthly_run(15, at(2026, 10, 18, 9)),
+        at(2026, 11, 15, 0)
+    );
+    assert_eq!(
+        next_monthly_run(20, at(2026, 10, 18, 9)),
+        at(2026, 10, 20, 0)
+    );
+    assert_eq!(next_monthly_run(31, at(2027, 1, 31, 0)), at(2027, 2, 28, 0));
+    assert_eq!(
+        next_monthly_run(31, at(2026, 12, 31, 1)),
+        at(2027, 1, 31, 0)
+    );
+
+    assert_eq!(
+        parse_due_date("10/01/2030"),
+        NaiveDate::from_ymd_opt(2030, 1, 10)
+    );
+    assert_eq!(
+        parse_due_date("2030-01-10T00:00:00"),
+        NaiveDate::from_ymd_opt(2030, 1, 10)
+    );
+    assert_eq!(parse_due_date("soon"), None);
+
+    assert!(Schedule::Monthly { day: 0 }.validate().is_err());
+    assert!(Schedule::BeforeDueDate { days_before: 3 }
+        .validate()
+        .is_ok());
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_subscriptions_renew_retry_and_change_status(pool: PgPool) {
+    let server = mock_upstreams().await;
+    std::env::set_var("DSTV_BASE_URL", server.uri());
+    std::env::set_var("BLUECODE_API_BASE_URL", server.uri());
+    std::env::set_var("BLUECODE_MERCHANT_ACCESS", "access");
+    std::env::set_var("BLUECODE_MERCHANT_SECRET", "secret");
+    std::env::set_var("ADMIN_API_KEY", "test-admin-key");
+    seed_catalog(&pool).await;
+
+    let mut settings = SubscriptionSettings::from_env();
+    settings.retry_secs = 60;
+    settings.retry_window_secs = 3600;
+
+    // Prepaid renewals are paid from our float, so only admins set them up
+    let (status, _) = send(
+        &pool,
+        "POST",
+        "/dstv/subscriptions",
+        Some(create_body(
+            "4131953321",
+            json!({ "kind": "MONTHLY", "day": 5 }),
+            "PREPAID",
+        )),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Due-date schedules run a few days before MultiChoice's due date
+    let (due, _) = create(
+        &pool,
+        "300115673",
+        json!({ "kind": "BEFORE_DUE_DATE", "days_before": 3 }),
+        "PREPAID",
+    )
+    .await;
+    let subscription = details(&pool, due).await.subscription;
+    assert_eq!(
+        subscription.next_run_at,
+        Utc.with_ymd_and_hms(2030, 1, 7, 0, 0, 0).unwrap()
+    );
+    assert_eq!(subscription.account_name.as_deref(), Some("OKAFOR"));
+
+    // Prepaid renewal confirmed and moved to next month
+    let (monthly, token) = create(
+        &pool,
+        "4131953321",
+        json!({ "kind": "MONTHLY", "day": 5 }),
+        "PREPAID",
+    )
+    .await;
+    make_due(&pool, monthly).await;
+    assert_eq!(run_due_subscriptions(&pool, &settings).await.unwrap(), 1);
+
+    let renewed = details(&pool, monthly).await;
+    assert_eq!(renewed.runs.len(), 1);
+    assert_eq!(renewed.runs[0].status, "CONFIRMED");
+    assert_eq!(renewed.runs[0].receipt_number.as_deref(), Some("RCPT-7"));
+    assert!(renewed.subscription.next_run_at > Utc::now());
+    let (confirm_status,): (String,) =
+        sqlx::query_as("SELECT confirm_status FROM transactions WHERE id = $1")
+            .bind(renewed.runs[0].transaction_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(confirm_status, "CONFIRMED");
+
+    // Upstream outage: retried under the same transaction until the window closes
+    let (flaky, _) = create(
+        &pool,
+        "FLAKYCARD",
+        json!({ "kind": "MONTHLY", "day": 5 }),
+        "PREPAID",
+    )
+    .await;
+    make_due(&pool, flaky).await;
+    run_due_subscriptions(&pool, &settings).await.unwrap();
+    let retrying = details(&pool, flaky).await;
+    assert_eq!(retrying.runs[0].status, "RETRYING");
+    assert_eq!(
+        retrying.runs[0].error_code.as_deref(),
+        Some("UPSTREAM_UNAVAILABLE")
+    );
+    let retry_in = retrying.subscription.next_run_at - Utc::now();
+    assert!(retry_in.num_seconds() > 0 && retry_in.num_seconds() <= 60);
+
+    sqlx::query("UPDATE dstv_subscriptions SET next_run_at = NOW() WHERE id = $1")
+        .bind(flaky)
+        .execute(&pool)
+        .await
+        .unwrap();
+    settings.retry_window_secs = 0;
+    run_due_subscriptions(&pool, &settings).await.unwrap();
+    let failed = details(&pool, flaky).await;
+    assert_eq!(failed.runs.len(), 2);
+    assert_eq!(failed.runs[0].status, "FAILED");
+    assert_eq!(failed.runs[0].transaction_id, failed.runs[1].transaction_id);
+    assert!(failed.subscription.next_run_at > Utc::now());
+
+    // A pending renewal is requeried on the next attempt, not paid again
+    let (pending, _) = create(
+        &pool,
+        "PENDCARD",
+        json!({ "kind": "MONTHLY", "day": 5 }),
+        "PREPAID",
+    )
+    .await;
+    settings.retry_window_secs = 3600;
+    make_due(&pool, pending).await;
+    run_due_subscriptions(&pool, &settings).await.unwrap();
+    let waiting = details(&pool, pending).await;
+    assert_eq!(waiting.runs.len(), 1);
+    assert_eq!(waiting.runs[0].status, "PENDING");
+    assert!(waiting.subscription.next_run_at > Utc::now());
+
+    Mock::given(method("GET"))
+        .and(path_regex("^/transactions/single/"))
+        .respond_with(|req: &wiremock::Request| {
+            let reference = req.url.path().rsplit('/').next().unwrap().to_string();
+            ResponseTemplate::new(200).set_body_string(
+                json!([{
+                    "merchantreference": reference,
+                    "smartcard": "PENDCARD",
+                    "status": 1,
+                    "basketid": "COMPE36"
+                }])
+                .to_string(),
+            )
+        })
+        .mount(&server)
+        .await;
+    sqlx::query("UPDATE dstv_subscriptions SET next_run_at = NOW() WHERE id = $1")
+        .bind(pending)
+        .execute(&pool)
+        .await
+        .unwrap();
+    run_due_subscriptions(&pool, &settings).await.unwrap();
+    let settled = details(&pool, pending).await;
+    assert_eq!(settled.runs.len(), 2);
+    assert_eq!(settled.runs[0].status, "CONFIRMED");
+    assert_eq!(
+        settled.runs[0].transaction_id,
+        settled.runs[1].transaction_id
+    );
+    let payments = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| {
+            r.url.path() == "/vendor/singlepayment"
+                && String::from_utf8_lossy(&r.body).contains("PENDCARD")
+        })
+        .count();
+    assert_eq!(payments, 1);
+
+    // Bluecode renewals wait for the checkout, and anyone can set them up
+    let (status, body) = send(
+        &pool,
+        "POST",
+        "/dstv/subscriptions",
+        Some(create_body(
+            "5555555555",
+            json!({ "kind": "MONTHLY", "day": 5 }),
+            "BLUECODE",
+        )),
+        &[],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let bluecode: Uuid = serde_json::from_value(body["subscription"]["id"].clone()).unwrap();
+    make_due(&pool, bluecode).await;
+    run_due_subscriptions(&pool, &settings).await.unwrap();
+    let requested = details(&pool, bluecode).await;
+    assert_eq!(requested.runs[0].status, "PAYMENT_REQUESTED");
+    let checkout_id = requested.runs[0].checkout_id.unwrap();
+
+    sqlx::query("UPDATE dstv_checkouts SET step = 'CONFIRMED' WHERE id = $1")
+        .bind(checkout_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    run_due_subscriptions(&pool, &settings).await.unwrap();
+    assert_eq!(details(&pool, bluecode).await.runs[0].status, "CONFIRMED");
+
+    // Pause, resume and cancel need the subscription's token
+    let uri = |action: &str| format!("/dstv/subscriptions/{monthly}/{action}");
+    let owner = [("X-Subscription-Token", token.as_str())];
+    let (status, _) = send(&pool, "POST", &uri("pause"), None, &[]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(
+        &pool,
+        "POST",
+        &uri("pause"),
+        None,
+        &[("X-Subscription-Token", "guess")],
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(&pool, "POST", &uri("pause"), None, &owner).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["subscription"]["status"], "PAUSED");
+
+    make_due(&pool, monthly).await;
+    assert_eq!(run_due_subscriptions(&pool, &settings).await.unwrap(), 0);
+
+    let (status, _) = send(&pool, "POST", &uri("pause"), None, &owner).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, body) = send(&pool, "POST", &uri("resume"), None, &owner).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["subscription"]["status"], "ACTIVE");
+
+    let (status, body) = send(&pool, "POST", &uri("cancel"), None, &owner).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["subscription"]["status"], "CANCELLED");
+
+    let (status, _) = send(&pool, "POST", &uri("resume"), None, &owner).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = send(
+        &pool,
+        "POST",
+        &format!("/dstv/subscriptions/{}/cancel", Uuid::new_v4()),
+        None,
+        &owner,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_cors_preflight_allows_subscription_token() {
+    let app = Router::new()
+        .route("/dstv/subscriptions/{id}", axum::routing::get(|| async {}))
+        .layer(cors_layer());
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("OPTIONS")
+                .uri("/dstv/subscriptions/1")
+                .header("origin", "https://app.example.com")
+                .header("access-control-request-method", "GET")
+                .header("access-control-request-headers", "x-subscription-token")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let allowed = response
+        .headers()
+        .get("access-control-allow-headers")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(allowed.contains("x-subscription-token"), "{}", allowed);
+}
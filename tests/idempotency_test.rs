@@ -0,0 +1,186 @@
+use axum::body::to_bytes;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use bills_backend::routes::dstv::dstv_routes;
+use bills_backend::routes::transactions::transaction_routes;
+use serde_json::json;
+use sqlx::PgPool;
+use tower::ServiceExt; // for `oneshot`
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn post(uri: &str, key: Option<&str>, body: serde_json::Value) -> Request<Body> {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("Content-Type", "application/json");
+    if let Some(key) = key {
+        builder = builder.header("Idempotency-Key", key);
+    }
+    builder.body(Body::from(body.to_string())).unwrap()
+}
+
+fn new_transaction(reference: &str) -> serde_json::Value {
+    json!({
+        "merchant_reference": reference,
+        "customer_id": "300115673",
+        "basket_id": "COMPE36",
+        "amount": 1500000,
+        "qr_status": "PENDING",
+        "confirm_status": "PENDING",
+        "timestamp": 0,
+        "user_id": null
+    })
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_idempotency_key_replays_and_rejects_reuse(pool: PgPool) {
+    let mock_server = MockServer::start().await;
+
+    // A double-clicked payment button must register only once
+    Mock::given(method("POST"))
+        .and(path("/v4/register"))
+        .respond_with(|req: &wiremock::Request| {
+            let body: serde_json::Value = serde_json::from_slice(&req.body).unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({
+                "result": "OK",
+                "payment": {
+                    "merchant_tx_id": body["merchant_tx_id"],
+                    "checkin_code": "12345",
+                    "state": "REGISTERED"
+                }
+            }))
+        })
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    // Bluecode errors out once; a retry with the same key must not register
+    // a second payment behind it
+    Mock::given(method("POST"))
+        .and(path("/v4/register"))
+        .and(body_partial_json(json!({ "requested_amount": 999 })))
+        .respond_with(ResponseTemplate::new(503))
+        .with_priority(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    std::env::set_var("BLUECODE_API_BASE_URL", mock_server.uri());
+    std::env::set_var("BLUECODE_MERCHANT_ACCESS", "access");
+    std::env::set_var("BLUECODE_MERCHANT_SECRET", "secret");
+
+    let app = Router::new()
+        .nest("/dstv", dstv_routes(pool.clone()))
+        .nest("/transactions", transaction_routes(pool.clone()))
+        .with_state(pool.clone());
+
+    let first = app
+        .clone()
+        .oneshot(post(
+            "/dstv/initiate-payment",
+            Some("pay-1"),
+            json!({ "amount": 1500000 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first = to_bytes(first.into_body(), 1024 * 1024).await.unwrap();
+
+    let repeat = app
+        .clone()
+        .oneshot(post(
+            "/dstv/initiate-payment",
+            Some("pay-1"),
+            json!({ "amount": 1500000 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(repeat.status(), StatusCode::OK);
+    assert_eq!(repeat.headers()["idempotent-replayed"], "true");
+    assert_eq!(repeat.headers()["content-type"], "application/json");
+    let repeat = to_bytes(repeat.into_body(), 1024 * 1024).await.unwrap();
+    assert_eq!(first, repeat);
+
+    // Same key, different body
+    let reused = app
+        .clone()
+        .oneshot(post(
+            "/dstv/initiate-payment",
+            Some("pay-1"),
+            json!({ "amount": 2000000 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(reused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Without a key every request runs (second registration)
+    let response = app
+        .clone()
+        .oneshot(post(
+            "/dstv/initiate-payment",
+            None,
+            json!({ "amount": 1500000 }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // POST /transactions stores a single row for a retried request
+    for _ in 0..2 {
+        let response = app
+            .clone()
+            .oneshot(post(
+                "/transactions",
+                Some("tx-1"),
+                new_transaction("TXN-idem"),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM transactions WHERE merchant_reference = 'TXN-idem'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(count, 1);
+
+    // Keys are scoped per endpoint
+    let response = app
+        .clone()
+        .oneshot(post(
+            "/transactions",
+            Some("pay-1"),
+            new_transaction("TXN-other"),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Server errors are stored and replayed too
+    for replayed in [false, true] {
+        let response = app
+            .clone()
+            .oneshot(post(
+                "/dstv/initiate-payment",
+                Some("pay-failed"),
+                json!({ "amount": 999 }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            response.headers().get("idempotent-replayed").is_some(),
+            replayed
+        );
+        let body = to_bytes(response.into_body(), 1024 * 1024).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["state"], "FAILED");
+    }
+}
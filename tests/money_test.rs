@@ -0,0 +1,122 @@
+use bills_backend::config::vas_product;
+use bills_backend::models::money::{Currency, Money, MoneyError};
+use bills_backend::models::payments::PaymentRequest;
+use bills_backend::services::bluecode::register_request;
+use bills_backend::services::payments::quickteller_request;
+use bills_backend::services::payu_vas::{payment_request, to_xml, VasError};
+use proptest::prelude::*;
+
+fn currency() -> impl Strategy<Value = Currency> {
+    prop_oneof![
+        Just(Currency::Ngn),
+        Just(Currency::Ghs),
+        Just(Currency::Kes),
+        Just(Currency::Zar),
+        Just(Currency::Usd),
+    ]
+}
+
+#[test]
+fn test_money_formatting_and_serde() {
+    let amount = Money::ngn(1500050);
+
+    assert_eq!(amount.to_major_string(), "15000.50");
+    assert_eq!(amount.to_string(), "NGN 15000.50");
+    assert_eq!(Money::ngn(-5).to_major_string(), "-0.05");
+    assert_eq!(
+        serde_json::to_value(amount).unwrap(),
+        serde_json::json!({ "amount": 1500050, "currency": "NGN" })
+    );
+    assert_eq!(
+        Money::parse_major("15000.5", Currency::Ngn).unwrap(),
+        amount
+    );
+    assert!(Money::parse_major("1.005", Currency::Ngn).is_err());
+    assert!(Money::parse_major("1e3", Currency::Ngn).is_err());
+    assert_eq!(
+        Money::ngn(1).checked_add(Money::from_minor(1, Currency::Usd)),
+        Err(MoneyError::CurrencyMismatch(Currency::Usd, Currency::Ngn))
+    );
+}
+
+#[test]
+fn test_payu_rejects_foreign_and_non_positive_amounts() {
+    let product = vas_product(None).unwrap();
+
+    for amount in [
+        Money::from_minor(100, Currency::Usd),
+        Money::ngn(0),
+        Money::ngn(i64::from(u32::MAX) + 1),
+    ] {
+        assert!(matches!(
+            payment_request(&product, "TXN-1", "123", "COMPE36", amount),
+            Err(VasError::InvalidAmount(_))
+        ));
+    }
+}
+
+proptest! {
+    #[test]
+    fn prop_major_string_round_trips(minor in any::<i64>().prop_filter("negatable", |m| *m != i64::MIN), currency in currency()) {
+        let money = Money::from_minor(minor, currency);
+        prop_assert_eq!(Money::parse_major(&money.to_major_string(), currency), Ok(money));
+    }
+
+    #[test]
+    fn prop_from_major_scales_by_minor_units(major in -1_000_000_000i64..1_000_000_000, currency in currency()) {
+        let money = Money::from_major(major, currency).unwrap();
+        prop_assert_eq!(money.minor(), major * 100);
+        prop_assert_eq!(money.to_major_string(), format!("{}.00", major));
+    }
+
+    #[test]
+    fn prop_checked_arithmetic_matches_i64(a in any::<i64>(), b in any::<i64>(), currency in currency()) {
+        let (x, y) = (Money::from_minor(a, currency), Money::from_minor(b, currency));
+
+        match a.checked_add(b) {
+            Some(sum) => prop_assert_eq!(x.checked_add(y).unwrap().minor(), sum),
+            None => prop_assert_eq!(x.checked_add(y), Err(MoneyError::Overflow)),
+        }
+        match a.checked_sub(b) {
+            Some(diff) => {
+                prop_assert_eq!(x.checked_sub(y).unwrap().minor(), diff);
+                prop_assert_eq!(x.checked_sub(y).unwrap().checked_add(y), Ok(x));
+            }
+            None => prop_assert_eq!(x.checked_sub(y), Err(MoneyError::Overflow)),
+        }
+    }
+
+    #[test]
+    fn prop_serde_round_trips(minor in any::<i64>(), currency in currency()) {
+        let money = Money::from_minor(minor, currency);
+        let json = serde_json::to_string(&money).unwrap();
+        prop_assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), money);
+    }
+
+    #[test]
+    fn prop_payu_sends_minor_units(kobo in 1..=i64::from(u32::MAX)) {
+        let request = payment_request(&vas_product(None).unwrap(), "TXN-1", "123", "COMPE36", Money::ngn(kobo)).unwrap();
+        let xml = to_xml(&request).unwrap();
+
+        prop_assert_eq!(request.amount_in_cents, Some(kobo as u32));
+        let expected = format!("<AmountInCents>{}</AmountInCents>", kobo);
+        prop_assert!(xml.contains(&expected));
+    }
+
+    #[test]
+    fn prop_bluecode_sends_minor_units(minor in 1..i64::MAX, currency in currency()) {
+        let request = register_request("TXN-1".into(), Money::from_minor(minor, currency));
+
+        prop_assert_eq!(request.requested_amount, minor);
+        prop_assert_eq!(request.currency, currency.code());
+    }
+
+    #[test]
+    fn prop_quickteller_sends_kobo(kobo in any::<i64>()) {
+        let payment = PaymentRequest { biller_id: "100".into(), amount: Money::ngn(kobo) };
+        prop_assert_eq!(quickteller_request(&payment).unwrap().amount, kobo);
+
+        let cedis = PaymentRequest { biller_id: "100".into(), amount: Money::from_minor(kobo, Currency::Ghs) };
+        prop_assert!(quickteller_request(&cedis).is_err());
+    }
+}
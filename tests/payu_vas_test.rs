@@ -0,0 +1,382 @@
+use axum::http::StatusCode;
+use bills_backend::config::{vas_product, ConfigError, VasProduct};
+use bills_backend::models::money::Money;
+use bills_backend::models::payu_vas::{VasCustomFields, VasOutcome, VasTransactionType};
+use bills_backend::services::catalog::invalidate_cache;
+use bills_backend::services::catalog::CatalogError;
+use bills_backend::services::dstv::{confirm_dstv_payment, confirmation_reply, DstvError};
+use bills_backend::services::payu_vas::{
+    parse_response, single_payment, to_xml, vas_request, VasError,
+};
+use bills_backend::services::references::{reference_for, ReferenceProvider, ReferencePurpose};
+use serde_json::json;
+use sqlx::PgPool;
+use wiremock::matchers::{body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn test_request_escapes_user_input() {
+    let request = vas_request(
+        &vas_product(None).unwrap(),
+        VasTransactionType::Single,
+        "TXN-1",
+        "123</CustomerId><AmountInCents>1</AmountInCents><CustomerId>",
+        Some(1500000),
+        Some(VasCustomFields::single("BasketId", "\"COMPE36\" & more")),
+    );
+
+    let xml = to_xml(&request).unwrap();
+
+    assert!(xml.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?><PayUVasRequest Ver="1.0">"#));
+    assert!(xml.contains("<TransactionType>SINGLE</TransactionType>"));
+    assert!(xml.contains("<AmountInCents>1500000</AmountInCents>"));
+    assert_eq!(xml.matches("<CustomerId>").count(), 1);
+    assert_eq!(xml.matches("<AmountInCents>").count(), 1);
+    assert!(xml.contains("123&lt;/CustomerId&gt;"));
+    assert!(xml.contains(r#"Value="&quot;COMPE36&quot; &amp; more""#));
+}
+
+#[test]
+fn test_requery_url_keeps_reference_in_one_segment() {
+    let product: VasProduct = serde_json::from_value(json!({
+        "id": "gotv-ng",
+        "name": "GOtv Nigeria",
+        "vas_id": "MCA_ACCOUNT_SQ_NG",
+        "country_code": "NG",
+        "currency": "NGN",
+        "merchant_id": "test",
+        "base_url": "https://vas.example.com/api/"
+    }))
+    .unwrap();
+
+    assert_eq!(
+        product.requery_url("VAS-1").unwrap(),
+        "https://vas.example.com/api/transactions/single/VAS-1"
+    );
+    assert_eq!(
+        product.requery_url("../admin?all=1#x").unwrap(),
+        "https://vas.example.com/api/transactions/single/..%2Fadmin%3Fall=1%23x"
+    );
+}
+
+#[test]
+fn test_lookup_request_omits_payment_fields() {
+    let request = vas_request(
+        &vas_product(None).unwrap(),
+        VasTransactionType::AccountLookup,
+        "ref-1",
+        "300115673",
+        None,
+        None,
+    );
+
+    let xml = to_xml(&request).unwrap();
+
+    assert!(xml.contains("<TransactionType>ACCOUNT_LOOKUP</TransactionType>"));
+    assert!(!xml.contains("AmountInCents"));
+    assert!(!xml.contains("CustomFields"));
+}
+
+#[test]
+fn test_parse_response() {
+    let response = parse_response(
+        r#"
+        <PayUVasResponse>
+            <ResultCode>00</ResultCode>
+            <ResultMessage>Success</ResultMessage>
+            <CustomFields>
+                <Customfield Key="SURNAME" Value="AKINTAYO"/>
+                <Customfield Key="DSTV_CUSTOMER_NUMBER" Value="300115673"/>
+            </CustomFields>
+        </PayUVasResponse>
+        "#,
+    )
+    .unwrap();
+
+    assert!(response.is_success());
+    assert_eq!(response.field("SURNAME"), Some("AKINTAYO"));
+    assert_eq!(response.fields().len(), 2);
+
+    assert!(matches!(parse_response("  "), Err(VasError::EmptyResponse)));
+    assert!(matches!(parse_response("not xml"), Err(VasError::Parse(_))));
+}
+
+#[test]
+fn test_confirmation_fields_and_outcomes() {
+    let response = parse_response(
+        r#"
+        <PayUVasResponse>
+            <ResultCode>00</ResultCode>
+            <ResultMessage>Payment successful</ResultMessage>
+            <PayUVasReference>PAYU-991</PayUVasReference>
+            <ReceiptNumber>MC-123456</ReceiptNumber>
+        </PayUVasResponse>
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(response.payu_reference.as_deref(), Some("PAYU-991"));
+    assert_eq!(response.receipt_number.as_deref(), Some("MC-123456"));
+
+    assert_eq!(VasOutcome::from_result_code(" 00 "), VasOutcome::Success);
+    assert_eq!(VasOutcome::from_result_code("09"), VasOutcome::Pending);
+    assert_eq!(VasOutcome::from_result_code("12"), VasOutcome::Failure);
+    assert_eq!(VasOutcome::from_requery_status(1), VasOutcome::Success);
+    assert_eq!(VasOutcome::from_requery_status(-1), VasOutcome::Pending);
+    assert_eq!(VasOutcome::from_requery_status(0), VasOutcome::Failure);
+}
+
+async fn seed_catalog(pool: &PgPool) {
+    sqlx::query(
+        "INSERT INTO dstv_products (vas_product, basket_id, name, kind, price, validity_days)
+         VALUES ('dstv-ng', 'COMPE36', 'Compact', 'BOUQUET', 1500000, 30),
+                ('gotv-ng', 'GOTVMAX', 'GOtv Max', 'BOUQUET', 850000, 30)",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    invalidate_cache();
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_payment_rejection_and_requery_fallback(pool: PgPool) {
+    let mock_server = MockServer::start().await;
+    seed_catalog(&pool).await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .and(body_string_contains("REJECTED"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "<PayUVasResponse><ResultCode>12</ResultCode><ResultMessage>Invalid smartcard</ResultMessage></PayUVasResponse>",
+            "application/xml",
+        ))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .and(body_string_contains("DUPCARD"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "<PayUVasResponse><ResultCode>94</ResultCode><ResultMessage>Duplicate transaction</ResultMessage></PayUVasResponse>",
+            "application/xml",
+        ))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/vendor/singlepayment"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&mock_server)
+        .await;
+
+    // Requeries use the confirmation reference issued for the payment
+    for (payment, status) in [
+        ("TXN-settled", 1),
+        ("TXN-pending", -1),
+        ("TXN-duplicate", 1),
+    ] {
+        let reference = reference_for(
+            &mut pool.acquire().await.unwrap(),
+            ReferenceProvider::PayuVas,
+            ReferencePurpose::Confirmation,
+            payment,
+        )
+        .await
+        .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path(format!("/transactions/single/{}", reference)))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(
+                    json!([{
+                        "merchantreference": reference,
+                        "smartcard": "300115673",
+                        "status": status,
+                        "basketid": "COMPE36"
+                    }])
+                    .to_string(),
+                ),
+            )
+            .mount(&mock_server)
+            .await;
+    }
+
+    std::env::remove_var("DSTV_PAYMENT_URL");
+    std::env::set_var("DSTV_BASE_URL", mock_server.uri());
+
+    // MultiChoice answered, but with a failing result code
+    let rejected = single_payment(
+        &pool,
+        &vas_product(None).unwrap(),
+        "TXN-1",
+        "REJECTED",
+        "COMPE36",
+        Money::ngn(1500000),
+    )
+    .await
+    .unwrap();
+    assert_eq!(rejected.outcome(), VasOutcome::Failure);
+    assert_eq!(rejected.result_message, "Invalid smartcard");
+
+    let rejected = confirm_dstv_payment(
+        &pool,
+        None,
+        "TXN-1".into(),
+        "REJECTED".into(),
+        "COMPE36".into(),
+        1500000,
+    )
+    .await;
+    let (status, reply) = confirmation_reply(rejected);
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!reply.success);
+    assert_eq!(reply.outcome, Some(VasOutcome::Failure));
+
+    // The payment call fails but the requery shows it went through
+    let settled = confirm_dstv_payment(
+        &pool,
+        None,
+        "TXN-settled".into(),
+        "300115673".into(),
+        "COMPE36".into(),
+        1500000,
+    )
+    .await
+    .unwrap();
+    assert_eq!(settled.outcome, VasOutcome::Success);
+    assert!(settled.requeried);
+    let (status, reply) = confirmation_reply(Ok(settled));
+    assert_eq!(status, StatusCode::OK);
+    assert!(reply.success);
+
+    let pending = confirm_dstv_payment(
+        &pool,
+        None,
+        "TXN-pending".into(),
+        "300115673".into(),
+        "COMPE36".into(),
+        1500000,
+    )
+    .await;
+    let (status, reply) = confirmation_reply(pending);
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(reply.outcome, Some(VasOutcome::Pending));
+
+    // A duplicate is settled by the requery, not the refusal
+    let duplicate = confirm_dstv_payment(
+        &pool,
+        None,
+        "TXN-duplicate".into(),
+        "DUPCARD".into(),
+        "COMPE36".into(),
+        1500000,
+    )
+    .await
+    .unwrap();
+    assert_eq!(duplicate.outcome, VasOutcome::Success);
+    assert!(duplicate.requeried);
+
+    // Neither call reaches MultiChoice
+    let unknown = confirm_dstv_payment(
+        &pool,
+        None,
+        "TXN-unknown".into(),
+        "300115673".into(),
+        "COMPE36".into(),
+        1500000,
+    )
+    .await;
+    assert!(unknown.is_err());
+    let (status, _) = confirmation_reply(unknown);
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    // Other MultiChoice services are configured, not coded
+    Mock::given(method("POST"))
+        .and(path("/gotv/singlepayment"))
+        .and(body_string_contains("MCA_GOTV_NG"))
+        // AmountInCents is the kobo amount itself, not naira
+        .and(body_string_contains("%3CAmountInCents%3E850000%3C"))
+        .and(header("Authorization", "Basic Z290djpzZWNyZXQ="))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "<PayUVasResponse><ResultCode>00</ResultCode><ResultMessage>OK</ResultMessage><ReceiptNumber>GO-1</ReceiptNumber></PayUVasResponse>",
+            "application/xml",
+        ))
+        .mount(&mock_server)
+        .await;
+
+    std::env::set_var(
+        "VAS_PRODUCTS",
+        json!([{
+            "id": "gotv-ng",
+            "name": "GOtv Nigeria",
+            "vas_id": "MCA_GOTV_NG",
+            "country_code": "NG",
+            "currency": "NGN",
+            "merchant_id": "Bluecode",
+            "username": "gotv",
+            "password": "secret",
+            "payment_url": format!("{}/gotv/singlepayment", mock_server.uri())
+        }])
+        .to_string(),
+    );
+
+    let gotv = confirm_dstv_payment(
+        &pool,
+        Some("gotv-ng"),
+        "TXN-gotv".into(),
+        "2012345678".into(),
+        "GOTVMAX".into(),
+        850000,
+    )
+    .await
+    .unwrap();
+    assert_eq!(gotv.outcome, VasOutcome::Success);
+    assert_eq!(gotv.receipt_number.as_deref(), Some("GO-1"));
+
+    let unknown_product = confirm_dstv_payment(
+        &pool,
+        Some("showmax-ke"),
+        "TXN-showmax".into(),
+        "123".into(),
+        "SHOWMAX".into(),
+        100,
+    )
+    .await;
+    let (status, _) = confirmation_reply(unknown_product);
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Baskets are priced per product
+    let wrong_product = confirm_dstv_payment(
+        &pool,
+        Some("gotv-ng"),
+        "TXN-gotv-compact".into(),
+        "2012345678".into(),
+        "COMPE36".into(),
+        1500000,
+    )
+    .await;
+    assert!(matches!(
+        wrong_product,
+        Err(DstvError::Catalog(CatalogError::UnknownBasket(_)))
+    ));
+
+    // Only dstv-ng may go without its own credentials
+    std::env::set_var(
+        "VAS_PRODUCTS",
+        json!([{
+            "id": "gotv-ng",
+            "name": "GOtv Nigeria",
+            "vas_id": "MCA_GOTV_NG",
+            "country_code": "NG",
+            "currency": "NGN",
+            "merchant_id": "Bluecode"
+        }])
+        .to_string(),
+    );
+    assert!(matches!(
+        vas_product(Some("gotv-ng")),
+        Err(ConfigError::Invalid(_))
+    ));
+
+    std::env::remove_var("VAS_PRODUCTS");
+}
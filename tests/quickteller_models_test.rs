@@ -0,0 +1,80 @@
+use bills_backend::models::billers::{
+    GetBillerCategoriesResponse, GetBillerPaymentItemsResponse, GetBillersByCategoryResponse,
+    SUCCESS_RESPONSE_CODE,
+};
+use serde_json::json;
+
+#[test]
+fn test_biller_categories_fixture() {
+    let parsed: GetBillerCategoriesResponse =
+        serde_json::from_str(include_str!("fixtures/quickteller/biller_categories.json")).unwrap();
+
+    assert_eq!(parsed.response_code.as_deref(), Some(SUCCESS_RESPONSE_CODE));
+    assert_eq!(parsed.biller_categories.len(), 4);
+
+    let recharge = &parsed.biller_categories[2];
+    assert_eq!(recharge.id, 4);
+    assert_eq!(recharge.name, "Mobile Recharge");
+    assert!(recharge.billers.is_empty());
+    assert_eq!(parsed.biller_categories[3].description, None);
+}
+
+#[test]
+fn test_billers_by_category_fixture() {
+    let parsed: GetBillersByCategoryResponse = serde_json::from_str(include_str!(
+        "fixtures/quickteller/billers_by_category.json"
+    ))
+    .unwrap();
+
+    assert_eq!(parsed.response_code_grouping.as_deref(), Some("SUCCESSFUL"));
+    assert_eq!(parsed.biller_list.count, 1);
+
+    let billers = &parsed.biller_list.category[0].billers;
+    assert_eq!(billers.len(), 2);
+    assert_eq!(billers[0].id, 109);
+    assert_eq!(billers[0].short_name.as_deref(), Some("MTN"));
+    assert_eq!(billers[0].category_id, Some(4));
+    assert_eq!(billers[0].currency_code.as_deref(), Some("566"));
+    assert_eq!(billers[1].customer_field1.as_deref(), Some("Mobile Number"));
+    assert_eq!(billers[1].logo_url, None);
+}
+
+#[test]
+fn test_payment_items_fixture() {
+    let parsed: GetBillerPaymentItemsResponse =
+        serde_json::from_str(include_str!("fixtures/quickteller/payment_items.json")).unwrap();
+
+    let [top_up, bundle] = parsed.payment_items.as_slice() else {
+        panic!("expected two payment items");
+    };
+
+    // Amounts come as strings or numbers
+    assert_eq!(top_up.payment_code, "10902");
+    assert_eq!(top_up.code.as_deref(), Some("02"));
+    assert!(!top_up.is_amount_fixed);
+    assert_eq!(top_up.amount, Some(0));
+    assert_eq!(top_up.item_fee, Some(0));
+    assert_eq!(top_up.currency_symbol.as_deref(), Some("NGN"));
+
+    assert!(bundle.is_amount_fixed);
+    assert_eq!(bundle.amount, Some(100_000));
+    assert_eq!(bundle.item_fee, Some(10_000));
+    assert_eq!(bundle.minimum_amount, None);
+
+    // Served to our clients in snake_case
+    let served = serde_json::to_value(&parsed).unwrap();
+    assert_eq!(served["payment_items"][1]["amount"], 100_000);
+    assert_eq!(served["payment_items"][1]["is_amount_fixed"], true);
+    assert_eq!(served["response_code"], SUCCESS_RESPONSE_CODE);
+}
+
+#[test]
+fn test_payment_item_rejects_malformed_amount() {
+    let payload = json!({
+        "PaymentItems": [
+            { "Id": "0", "Name": "Top-up", "PaymentCode": "10902", "Amount": "N100" }
+        ]
+    });
+
+    assert!(serde_json::from_value::<GetBillerPaymentItemsResponse>(payload).is_err());
+}
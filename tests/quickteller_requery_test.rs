@@ -0,0 +1,297 @@
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use axum::{Extension, Router};
+use bills_backend::models::airtime::TopupStatus;
+use bills_backend::routes::billers::biller_routes;
+use bills_backend::services::quickteller_auth::TokenManager;
+use bills_backend::services::quickteller_requery::{resolve_pending_transactions, RequerySettings};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower::ServiceExt;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+mod common;
+
+const ADMIN_KEY: &str = "test-admin-key";
+
+async fn mock_query(server: &MockServer, reference: &str, response: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/Transactions"))
+        .and(query_param("requestRef", reference))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+fn reply(grouping: Option<&str>, code: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "TransactionRef": format!("QT-{}", code),
+        "ResponseCode": code,
+        "ResponseCodeGrouping": grouping
+    }))
+}
+
+/// A pending top-up `age_secs` old. `answered` top-ups got a reply to their
+/// payment advice.
+async fn insert_pending(pool: &PgPool, reference: &str, age_secs: i64, answered: bool) {
+    let transaction_id: i32 = sqlx::query_scalar(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ($1, '08030000001', '10902', 20000, 'PREPAID', 'PENDING', 0)
+        RETURNING id
+        "#,
+    )
+    .bind(reference)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        r#"
+        INSERT INTO airtime_topups
+            (transaction_id, request_reference, phone_number, network, service_id, payment_code,
+             amount, status, response_code, created_at)
+        VALUES ($1, $2, '08030000001', 'mtn', 109, '10902', 20000, 'PENDING', $3,
+                NOW() - make_interval(secs => $4))
+        "#,
+    )
+    .bind(transaction_id)
+    .bind(reference)
+    .bind(answered.then_some("90009"))
+    .bind(age_secs as f64)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+async fn statuses(pool: &PgPool, reference: &str) -> (String, String) {
+    sqlx::query_as(
+        r#"
+        SELECT a.status, t.confirm_status
+        FROM airtime_topups a JOIN transactions t ON t.id = a.transaction_id
+        WHERE a.request_reference = $1
+        "#,
+    )
+    .bind(reference)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+async fn get(app: &Router, uri: &str, admin_key: &str) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get(uri)
+                .header("x-admin-key", admin_key)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[test]
+fn test_response_codes_map_to_status() {
+    let cases = [
+        (Some("90000"), Some("SUCCESSFUL"), TopupStatus::Successful),
+        (Some("90000"), None, TopupStatus::Successful),
+        (Some("90009"), Some("PENDING"), TopupStatus::Pending),
+        (Some("90068"), None, TopupStatus::Pending),
+        (Some("90096"), Some("FAILED"), TopupStatus::Pending),
+        (Some("Z0"), Some("PENDING"), TopupStatus::Pending),
+        (Some("20021"), Some("FAILED"), TopupStatus::Failed),
+        (Some("70008"), None, TopupStatus::Failed),
+        (None, Some("SUCCESSFUL"), TopupStatus::Successful),
+        (None, Some("FAILED"), TopupStatus::Failed),
+        (None, None, TopupStatus::Pending),
+    ];
+    for (code, grouping, expected) in cases {
+        assert_eq!(
+            TopupStatus::from_response(code, grouping),
+            expected,
+            "{:?} / {:?}",
+            code,
+            grouping
+        );
+    }
+
+    assert_eq!(TopupStatus::Successful.confirm_status(), "CONFIRMED");
+    assert_eq!(TopupStatus::Pending.confirm_status(), "PENDING");
+    assert_eq!(TopupStatus::Failed.confirm_status(), "FAILED");
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_pending_transactions_are_resolved(pool: PgPool) {
+    let server = common::mock_quickteller("requery-token").await;
+    mock_query(&server, "QTL-done", reply(Some("SUCCESSFUL"), "90000")).await;
+    mock_query(&server, "QTL-slow", reply(Some("PENDING"), "90009")).await;
+    mock_query(&server, "QTL-late", reply(None, "90068")).await;
+    mock_query(&server, "QTL-declined", reply(Some("FAILED"), "20021")).await;
+    for reference in ["QTL-lost", "QTL-unseen"] {
+        mock_query(
+            &server,
+            reference,
+            ResponseTemplate::new(404).set_body_string("Transaction not found"),
+        )
+        .await;
+    }
+
+    insert_pending(&pool, "QTL-done", 600, true).await;
+    insert_pending(&pool, "QTL-slow", 600, true).await;
+    insert_pending(&pool, "QTL-late", 600, true).await;
+    insert_pending(&pool, "QTL-declined", 600, true).await;
+    insert_pending(&pool, "QTL-lost", 7200, false).await;
+    insert_pending(&pool, "QTL-unseen", 600, false).await;
+    insert_pending(&pool, "QTL-fresh", 10, true).await;
+
+    let tokens = TokenManager::new();
+    let settings = RequerySettings {
+        interval_secs: 60,
+        min_age_secs: 120,
+        batch_size: 10,
+    };
+
+    // Instances running side by side split the batch between them
+    let (first, second) = tokio::join!(
+        resolve_pending_transactions(&pool, &tokens, &settings),
+        resolve_pending_transactions(&pool, &tokens, &settings),
+    );
+    assert_eq!(first.unwrap() + second.unwrap(), 6);
+    let queries = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|r| r.method.as_str() == "GET" && r.url.path().ends_with("/Transactions"))
+        .count();
+    assert_eq!(queries, 6);
+
+    let resolved = |status: &str, confirm: &str| (status.to_string(), confirm.to_string());
+    assert_eq!(
+        statuses(&pool, "QTL-done").await,
+        resolved("SUCCESSFUL", "CONFIRMED")
+    );
+    assert_eq!(
+        statuses(&pool, "QTL-slow").await,
+        resolved("PENDING", "PENDING")
+    );
+    assert_eq!(
+        statuses(&pool, "QTL-late").await,
+        resolved("PENDING", "PENDING")
+    );
+    assert_eq!(
+        statuses(&pool, "QTL-declined").await,
+        resolved("FAILED", "FAILED")
+    );
+    assert_eq!(
+        statuses(&pool, "QTL-lost").await,
+        resolved("FAILED", "FAILED")
+    );
+    // Unknown to Quickteller, but too recent to be taken as never sent
+    assert_eq!(
+        statuses(&pool, "QTL-unseen").await,
+        resolved("PENDING", "PENDING")
+    );
+    assert_eq!(
+        statuses(&pool, "QTL-fresh").await,
+        resolved("PENDING", "PENDING")
+    );
+
+    // Just-requeried transactions are left alone until min_age_secs has passed again
+    assert_eq!(
+        resolve_pending_transactions(&pool, &tokens, &settings)
+            .await
+            .unwrap(),
+        0
+    );
+
+    // The status endpoint asks Quickteller again, for admins only
+    std::env::set_var("ADMIN_API_KEY", ADMIN_KEY);
+    let app = Router::new()
+        .nest("/billers", biller_routes())
+        .layer(Extension(tokens.clone()))
+        .with_state(pool.clone());
+
+    Mock::given(method("GET"))
+        .and(path("/quicktellerservice/api/v5/Transactions"))
+        .and(query_param("requestRef", "QTL-fresh"))
+        .respond_with(reply(Some("SUCCESSFUL"), "90000"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let (status, body) = get(&app, "/billers/transactions/QTL-fresh", ADMIN_KEY).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "SUCCESSFUL");
+    assert_eq!(body["response_code"], "90000");
+    assert_eq!(body["transaction_ref"], "QT-90000");
+    assert_eq!(
+        statuses(&pool, "QTL-fresh").await,
+        resolved("SUCCESSFUL", "CONFIRMED")
+    );
+
+    // Settled transactions are not requeried
+    let (status, body) = get(&app, "/billers/transactions/QTL-fresh", ADMIN_KEY).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "SUCCESSFUL");
+
+    let (status, body) = get(&app, "/billers/transactions/QTL-slow", ADMIN_KEY).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "PENDING");
+
+    let (status, _) = get(&app, "/billers/transactions/QTL-unknown", ADMIN_KEY).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = get(&app, "/billers/transactions/QTL-fresh", "wrong-key").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_later_outcome_never_replaces_a_final_one(pool: PgPool) {
+    let server = common::mock_quickteller("requery-token").await;
+    mock_query(
+        &server,
+        "QTL-race",
+        reply(Some("FAILED"), "20021").set_delay(std::time::Duration::from_millis(500)),
+    )
+    .await;
+    insert_pending(&pool, "QTL-race", 600, true).await;
+
+    let tokens = TokenManager::new();
+    let settings = RequerySettings {
+        interval_secs: 60,
+        min_age_secs: 120,
+        batch_size: 10,
+    };
+    let requery = tokio::spawn({
+        let pool = pool.clone();
+        async move { resolve_pending_transactions(&pool, &tokens, &settings).await }
+    });
+
+    // The top-up settles while its requery is in flight
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    sqlx::query(
+        r#"
+        WITH topup AS (
+            UPDATE airtime_topups SET status = 'SUCCESSFUL', response_code = '90000'
+            WHERE request_reference = 'QTL-race'
+            RETURNING transaction_id
+        )
+        UPDATE transactions SET confirm_status = 'CONFIRMED'
+        WHERE id = (SELECT transaction_id FROM topup)
+        "#,
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    assert_eq!(requery.await.unwrap().unwrap(), 1);
+    assert_eq!(
+        statuses(&pool, "QTL-race").await,
+        ("SUCCESSFUL".to_string(), "CONFIRMED".to_string())
+    );
+}
@@ -0,0 +1,90 @@
+use bills_backend::services::quickteller_auth::TokenManager;
+use bills_backend::utils::error::ApiError;
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::matchers::{body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Passport mock issuing `token-1`, `token-2`, ... valid for `expires_in`.
+async fn mock_passport(expires_in: u64) -> (MockServer, Arc<AtomicUsize>) {
+    let server = MockServer::start().await;
+    let issued = Arc::new(AtomicUsize::new(0));
+    let counter = issued.clone();
+
+    Mock::given(method("POST"))
+        .and(path("/passport/oauth/token"))
+        .and(header("Authorization", "Basic aWQ6c2VjcmV0"))
+        .and(body_string_contains("scope=billing"))
+        .respond_with(move |_: &wiremock::Request| {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "access_token": format!("token-{n}"),
+                    "expires_in": expires_in
+                }))
+                .set_delay(Duration::from_millis(50))
+        })
+        .mount(&server)
+        .await;
+
+    std::env::set_var(
+        "QUICKTELLER_PASSPORT_URL",
+        format!("{}/passport/oauth/token", server.uri()),
+    );
+    (server, issued)
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_token_is_cached_refreshed_ahead_and_after_401(pool: PgPool) {
+    std::env::set_var("QUICKTELLER_CLIENT_ID", "id");
+    std::env::set_var("QUICKTELLER_SECRET_KEY", "secret");
+    std::env::set_var("QUICKTELLER_SCOPE", "billing");
+    std::env::set_var("QUICKTELLER_TOKEN_REFRESH_SECS", "300");
+
+    // Concurrent callers share one request
+    let (_server, issued) = mock_passport(86400).await;
+    let tokens = TokenManager::new();
+    let handles: Vec<_> = (0..20)
+        .map(|_| {
+            let tokens = tokens.clone();
+            let pool = pool.clone();
+            tokio::spawn(async move { tokens.token(&pool).await.unwrap() })
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.await.unwrap(), "token-1");
+    }
+    assert_eq!(issued.load(Ordering::SeqCst), 1);
+    assert_eq!(tokens.token(&pool).await.unwrap(), "token-1");
+
+    // A 401 refreshes the token once and the call is retried with it
+    let calls = AtomicUsize::new(0);
+    let result = tokens
+        .with_token(&pool, |token| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match token.as_str() {
+                    "token-1" => Err(ApiError::Unauthorized("expired".into())),
+                    other => Ok(other.to_string()),
+                }
+            }
+        })
+        .await
+        .unwrap();
+    assert_eq!(result, "token-2");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(issued.load(Ordering::SeqCst), 2);
+
+    // Inside the refresh window the current token is served while a new one
+    // is fetched in the background
+    let (_server, issued) = mock_passport(300).await;
+    let tokens = TokenManager::new();
+    assert_eq!(tokens.token(&pool).await.unwrap(), "token-1");
+    assert_eq!(tokens.token(&pool).await.unwrap(), "token-1");
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(issued.load(Ordering::SeqCst), 2);
+    assert_eq!(tokens.token(&pool).await.unwrap(), "token-2");
+}
@@ -0,0 +1,99 @@
+use bills_backend::services::references::{
+    generate_reference, issue_reference, link_transaction, reference_for, ReferenceProvider,
+    ReferencePurpose,
+};
+use sqlx::PgPool;
+
+#[test]
+fn test_references_are_prefixed_sortable_and_bounded() {
+    for provider in [
+        ReferenceProvider::Bluecode,
+        ReferenceProvider::PayuVas,
+        ReferenceProvider::Quickteller,
+    ] {
+        let references: Vec<String> = (0..50).map(|_| generate_reference(provider)).collect();
+
+        let mut sorted = references.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted, references);
+
+        for reference in &references {
+            assert!(reference.starts_with(&format!("{}-", provider.prefix())));
+            assert!(reference.len() <= provider.max_len());
+        }
+    }
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_references_are_recorded_and_linked(pool: PgPool) {
+    let mut conn = pool.acquire().await.unwrap();
+
+    let registration = issue_reference(
+        &mut conn,
+        ReferenceProvider::Bluecode,
+        ReferencePurpose::Registration,
+        None,
+    )
+    .await
+    .unwrap();
+
+    let (transaction_id,): (i32,) = sqlx::query_as(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ($1, '300115673', 'COMPE36', 1500000, 'APPROVED', 'PENDING', 0)
+        RETURNING id
+        "#,
+    )
+    .bind(&registration)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    link_transaction(&pool, &registration, transaction_id)
+        .await
+        .unwrap();
+
+    // Retried confirmations reuse the reference issued the first time
+    let confirmation = reference_for(
+        &mut conn,
+        ReferenceProvider::PayuVas,
+        ReferencePurpose::Confirmation,
+        &registration,
+    )
+    .await
+    .unwrap();
+    let retried = reference_for(
+        &mut conn,
+        ReferenceProvider::PayuVas,
+        ReferencePurpose::Confirmation,
+        &registration,
+    )
+    .await
+    .unwrap();
+    assert_eq!(retried, confirmation);
+    assert!(confirmation.starts_with("VAS-"));
+
+    let rows: Vec<(String, String, String, Option<i32>)> = sqlx::query_as(
+        "SELECT reference, provider, purpose, transaction_id FROM merchant_references ORDER BY reference",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            (
+                registration,
+                "BLUECODE".to_string(),
+                "REGISTRATION".to_string(),
+                Some(transaction_id)
+            ),
+            (
+                confirmation,
+                "PAYU_VAS".to_string(),
+                "CONFIRMATION".to_string(),
+                Some(transaction_id)
+            ),
+        ]
+    );
+}
@@ -0,0 +1,179 @@
+use axum::body::to_bytes;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use bills_backend::models::money::Money;
+use bills_backend::routes::vendor_exchanges::vendor_exchange_routes;
+use bills_backend::services::bluecode::{
+    initiate_qr_payment, register_request, requery_transaction,
+};
+use bills_backend::services::vendor_exchanges::redact;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::time::Duration;
+use tower::ServiceExt; // for `oneshot`
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn admin_get(uri: &str, key: &str) -> Request<Body> {
+    Request::builder()
+        .uri(uri)
+        .header("X-Admin-Key", key)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn get_json(app: &Router, uri: &str) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(admin_get(uri, "test-admin-key"))
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Exchanges are stored in the background, after the call has returned.
+async fn wait_for_exchanges(pool: &PgPool, correlation_id: &str, expected: i64) {
+    for _ in 0..50 {
+        let (stored,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM vendor_exchanges WHERE correlation_id = $1")
+                .bind(correlation_id)
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        if stored >= expected {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!(
+        "{} exchanges for {} were not stored",
+        expected, correlation_id
+    );
+}
+
+#[test]
+fn test_redacts_tokens_and_credentials() {
+    let json =
+        redact(r#"{"access_token":"abc","nested":[{"client_secret":"x"}],"expires_in":3600}"#);
+    assert_eq!(
+        serde_json::from_str::<Value>(&json).unwrap(),
+        json!({
+            "access_token": "[REDACTED]",
+            "nested": [{ "client_secret": "[REDACTED]" }],
+            "expires_in": 3600
+        })
+    );
+
+    assert_eq!(
+        redact("grant_type=client_credentials&password=hunter2"),
+        "grant_type=client_credentials&password=[REDACTED]"
+    );
+    assert_eq!(redact("<Xml>1</Xml>"), "<Xml>1</Xml>");
+    assert!(redact(&"a".repeat(20_000)).ends_with("[truncated]"));
+}
+
+#[sqlx::test(migrations = "src/migrations")]
+async fn test_bluecode_calls_are_audited_and_searchable(pool: PgPool) {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/v4/register"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "result": "OK",
+            "payment": {
+                "merchant_tx_id": "TXN-audit",
+                "checkin_code": "bc-123",
+                "state": "PENDING"
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v4/status"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("maintenance"))
+        .mount(&mock_server)
+        .await;
+
+    std::env::set_var("BLUECODE_API_BASE_URL", mock_server.uri());
+    std::env::set_var("BLUECODE_MERCHANT_ACCESS", "access");
+    std::env::set_var("BLUECODE_MERCHANT_SECRET", "secret");
+    std::env::set_var("ADMIN_API_KEY", "test-admin-key");
+
+    let (transaction_id,): (i32,) = sqlx::query_as(
+        r#"
+        INSERT INTO transactions (merchant_reference, customer_id, basket_id, amount, qr_status, confirm_status, timestamp)
+        VALUES ('TXN-audit', '300115673', 'COMPE36', 1500000, 'PENDING', 'PENDING', 0)
+        RETURNING id
+        "#,
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    initiate_qr_payment(
+        &pool,
+        register_request("TXN-audit".into(), Money::ngn(1500000)),
+    )
+    .await
+    .unwrap();
+    wait_for_exchanges(&pool, "TXN-audit", 1).await;
+    assert!(requery_transaction(&pool, "TXN-audit".into())
+        .await
+        .is_err());
+    wait_for_exchanges(&pool, "TXN-audit", 2).await;
+
+    let app = Router::new()
+        .nest("/admin/vendor-exchanges", vendor_exchange_routes())
+        .with_state(pool.clone());
+
+    let (status, exchanges) =
+        get_json(&app, "/admin/vendor-exchanges?correlation_id=TXN-audit").await;
+    assert_eq!(status, StatusCode::OK);
+    let exchanges = exchanges.as_array().unwrap();
+    assert_eq!(exchanges.len(), 2);
+
+    // Newest first
+    let (requery, register) = (&exchanges[0], &exchanges[1]);
+    assert_eq!(register["provider"], "BLUECODE");
+    assert_eq!(register["operation"], "register");
+    assert_eq!(register["status_code"], 200);
+    assert_eq!(register["transaction_id"], transaction_id);
+    assert!(register["request_body"]
+        .as_str()
+        .unwrap()
+        .contains(r#""requested_amount":1500000"#));
+    assert!(register["response_body"]
+        .as_str()
+        .unwrap()
+        .contains("bc-123"));
+    assert_eq!(requery["operation"], "status");
+    assert_eq!(requery["status_code"], 503);
+
+    let (_, failed) = get_json(
+        &app,
+        "/admin/vendor-exchanges?provider=bluecode&failed=true",
+    )
+    .await;
+    assert_eq!(failed.as_array().unwrap().len(), 1);
+    assert_eq!(failed[0]["id"], requery["id"]);
+
+    let (status, single) =
+        get_json(&app, &format!("/admin/vendor-exchanges/{}", register["id"])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(single["correlation_id"], "TXN-audit");
+
+    let (status, _) = get_json(&app, "/admin/vendor-exchanges/999999").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let response = app
+        .oneshot(admin_get("/admin/vendor-exchanges", "wrong"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}